@@ -5,7 +5,6 @@ use axum::{
         ws::{Message, WebSocket},
         WebSocketUpgrade,
     },
-    handler::Handler,
     http::StatusCode,
     response::{Html, IntoResponse},
     routing::{get, get_service},
@@ -54,6 +53,7 @@ async fn main() {
         .unwrap();
 }
 
+#[allow(dead_code)]
 async fn handler() -> Html<&'static str> {
     Html("Hello!")
 }
@@ -110,6 +110,7 @@ async fn handle_socket(mut socket: WebSocket) {
     }
 }
 
+#[allow(dead_code)]
 async fn handler_404() -> impl IntoResponse {
     (StatusCode::NOT_FOUND, "bad route :(")
 }
@@ -24,7 +24,7 @@ fn main() -> Result<()> {
         }
     }
     let parse = parse(tokenize(&buffer));
-    print!("errors: {:#?}\n", parse.get_errors());
+    println!("errors: {:#?}", parse.get_errors());
     print_node(parse.syntax(), 2);
     Ok(())
 }
@@ -19,8 +19,10 @@ const TEMPLATE_DIR: &str = "meta";
 
 const SYNTAX_KINDS_SRC: &str = "syntax_kinds.rs.tera";
 const TOKEN_KINDS_SRC: &str = "token_kinds.rs.tera";
+const AST_NODES_SRC: &str = "ast_nodes.rs.tera";
 const SYNTAX_KINDS_DST: &str = "syntax_kinds.rs";
 const TOKEN_KINDS_DST: &str = "token_kinds.rs";
+const AST_NODES_DST: &str = "ast_nodes.rs";
 
 fn project_root() -> &'static Path {
     // manifest is currently at the project root
@@ -49,6 +51,23 @@ impl<'de> Deserialize<'de> for OperatorConfig {
     }
 }
 
+/// An accessor on a typed AST node, returning either child nodes (`node`) or
+/// child tokens (`token`) of the given kind
+#[derive(Serialize, Deserialize)]
+struct ChildConfig {
+    name: String,
+    node: Option<String>,
+    token: Option<String>,
+    #[serde(default)]
+    many: bool,
+}
+
+#[derive(Serialize, Deserialize)]
+struct NodeConfig {
+    kind: String,
+    children: Vec<ChildConfig>,
+}
+
 #[derive(Serialize, Deserialize)]
 struct SyntaxConfig {
     comparisons: Vec<OperatorConfig>,
@@ -58,6 +77,7 @@ struct SyntaxConfig {
     expressions: Vec<String>,
     composites: Vec<String>,
     nameops: Vec<String>,
+    nodes: Vec<NodeConfig>,
 }
 
 fn main() -> Result<(), Box<dyn Error>> {
@@ -96,5 +116,9 @@ fn main() -> Result<(), Box<dyn Error>> {
         out.join(SYNTAX_KINDS_DST),
         tera.render(SYNTAX_KINDS_SRC, &context)?,
     )?;
+    write(
+        out.join(AST_NODES_DST),
+        tera.render(AST_NODES_SRC, &context)?,
+    )?;
     Ok(())
 }
@@ -0,0 +1,555 @@
+//! Typed wrappers over the lossless syntax tree.
+//!
+//! The wrappers and most of their accessors are generated from
+//! `meta/syntax.toml`; anything that needs more than finding a child of some
+//! kind lives at the bottom of this file.
+
+use std::marker::PhantomData;
+
+use rowan::{SyntaxNodeChildren, TextRange, TextSize};
+
+use crate::parser::{Lang, SyntaxElement, SyntaxKind, SyntaxNode, SyntaxToken};
+
+include!(concat!(env!("OUT_DIR"), "/ast_nodes.rs"));
+
+pub trait AstNode {
+    fn can_cast(kind: SyntaxKind) -> bool
+    where
+        Self: Sized;
+
+    fn cast(syntax: SyntaxNode) -> Option<Self>
+    where
+        Self: Sized;
+
+    fn syntax(&self) -> &SyntaxNode;
+}
+
+/// Iterator over the child nodes of a given type
+#[derive(Debug, Clone)]
+pub struct AstChildren<N> {
+    inner: SyntaxNodeChildren<Lang>,
+    ph: PhantomData<N>,
+}
+
+impl<N> AstChildren<N> {
+    fn new(parent: &SyntaxNode) -> Self {
+        AstChildren {
+            inner: parent.children(),
+            ph: PhantomData,
+        }
+    }
+}
+
+impl<N: AstNode> Iterator for AstChildren<N> {
+    type Item = N;
+    fn next(&mut self) -> Option<N> {
+        self.inner.find_map(N::cast)
+    }
+}
+
+mod support {
+    use super::{AstChildren, AstNode, SyntaxKind, SyntaxNode, SyntaxToken};
+
+    pub(super) fn child<N: AstNode>(parent: &SyntaxNode) -> Option<N> {
+        parent.children().find_map(N::cast)
+    }
+
+    pub(super) fn children<N: AstNode>(parent: &SyntaxNode) -> AstChildren<N> {
+        AstChildren::new(parent)
+    }
+
+    pub(super) fn token(parent: &SyntaxNode, kind: SyntaxKind) -> Option<SyntaxToken> {
+        parent
+            .children_with_tokens()
+            .filter_map(|it| it.into_token())
+            .find(|it| it.kind() == kind)
+    }
+}
+
+/// strips the quotes from a string literal
+fn unquote(literal: &str) -> &str {
+    &literal[unquoted_range(literal)]
+}
+
+/// where the contents of a string literal are, without its quotes or any
+/// whitespace around them
+fn unquoted_range(literal: &str) -> std::ops::Range<usize> {
+    let start = literal.len() - literal.trim_start().len();
+    let end = literal.trim_end().len();
+    let trimmed = &literal[start..end];
+    for quote in ['\'', '"'] {
+        if trimmed.len() >= 2 * quote.len_utf8()
+            && trimmed.starts_with(quote)
+            && trimmed.ends_with(quote)
+        {
+            return start + quote.len_utf8()..end - quote.len_utf8();
+        }
+    }
+    start..end
+}
+
+impl ExprName {
+    pub fn name(&self) -> Option<String> {
+        self.ident().map(|token| token.text().to_string())
+    }
+}
+
+impl ExprConstantString {
+    /// the contents of the string, without quotes
+    pub fn value(&self) -> Option<String> {
+        self.literal()
+            .map(|token| unquote(token.text()).to_string())
+    }
+
+    /// where the contents of the string are, without quotes
+    pub fn value_range(&self) -> Option<TextRange> {
+        let literal = self.literal()?;
+        let range = unquoted_range(literal.text());
+        let start = literal.text_range().start();
+        Some(TextRange::new(
+            start + TextSize::from(range.start as u32),
+            start + TextSize::from(range.end as u32),
+        ))
+    }
+}
+
+impl StmtMacro {
+    pub fn name(&self) -> Option<ExprName> {
+        self.start()?.name()
+    }
+
+    pub fn signature(&self) -> Option<Signature> {
+        self.start()?.signature()
+    }
+}
+
+impl StmtTest {
+    /// the name of the generic test, which dbt turns into a macro called
+    /// `test_<name>`
+    pub fn name(&self) -> Option<ExprName> {
+        self.start()?.name()
+    }
+
+    pub fn signature(&self) -> Option<Signature> {
+        self.start()?.signature()
+    }
+}
+
+impl SignatureDefaultArg {
+    /// The default value of the argument. Literals other than strings aren't
+    /// wrapped in a node, so this can be either a node or a token.
+    pub fn default_value(&self) -> Option<SyntaxElement> {
+        self.syntax
+            .children_with_tokens()
+            .skip_while(|child| child.kind() != SyntaxKind::Assign)
+            .skip(1)
+            .find(|child| child.kind() != SyntaxKind::Whitespace)
+    }
+}
+
+impl StmtMaterialization {
+    pub fn name(&self) -> Option<ExprName> {
+        self.start()?.name()
+    }
+
+    /// the adapter this materialization is for, which is `"default"` unless
+    /// one was given with `adapter='...'`
+    pub fn adapter(&self) -> String {
+        self.start()
+            .and_then(|start| start.adapter())
+            .and_then(|adapter| adapter.value())
+            .and_then(|value| value.value())
+            .unwrap_or_else(|| "default".to_string())
+    }
+}
+
+impl StmtDocs {
+    pub fn name(&self) -> Option<ExprName> {
+        self.start()?.name()
+    }
+}
+
+impl StmtSnapshot {
+    pub fn name(&self) -> Option<ExprName> {
+        self.start()?.name()
+    }
+}
+
+/// the names an assignment sets, e.g. both `a` and `b` in `set a, b = ...`
+fn assigned_names(target: Option<Expr>) -> Vec<ExprName> {
+    match target {
+        Some(Expr::Name(name)) => vec![name],
+        Some(Expr::Tuple(tuple)) => support::children(tuple.syntax()).collect(),
+        _ => Vec::new(),
+    }
+}
+
+impl StmtAssign {
+    pub fn names(&self) -> Vec<ExprName> {
+        assigned_names(self.target())
+    }
+}
+
+impl StmtAssignBlock {
+    pub fn names(&self) -> Vec<ExprName> {
+        assigned_names(self.start().and_then(|start| start.target()))
+    }
+}
+
+impl StmtFor {
+    /// the loop variables, e.g. both `k` and `v` in `for k, v in ...`
+    pub fn names(&self) -> Vec<ExprName> {
+        assigned_names(self.start().and_then(|start| start.target()))
+    }
+}
+
+impl ExprCall {
+    /// name of the function being called, if it's just a plain name (e.g.
+    /// `ref` in `ref('model')`)
+    pub fn callee_name(&self) -> Option<String> {
+        match self.callee()? {
+            Expr::Name(name) => name.name(),
+            _ => None,
+        }
+    }
+
+    /// Namespace and name of the function being called, e.g.
+    /// `(Some("dbt_utils"), "star")` for `dbt_utils.star(...)` and
+    /// `(None, "my_macro")` for `my_macro(...)`. Deeper attribute chains
+    /// aren't macros, so they give `None`.
+    pub fn qualified_callee_name(&self) -> Option<(Option<String>, String)> {
+        match self.callee()? {
+            Expr::Name(name) => Some((None, name.name()?)),
+            Expr::GetAttr(get_attr) => {
+                let namespace = match get_attr.expr()? {
+                    Expr::Name(namespace) => namespace.name()?,
+                    _ => return None,
+                };
+                let name = get_attr.subscript()?.ident()?;
+                Some((Some(namespace), name.text().to_string()))
+            }
+            _ => None,
+        }
+    }
+
+    /// where the name in `qualified_callee_name` is written, e.g. `star`
+    /// in `dbt_utils.star(...)`
+    pub fn callee_name_range(&self) -> Option<TextRange> {
+        match self.callee()? {
+            Expr::Name(name) => Some(name.ident()?.text_range()),
+            Expr::GetAttr(get_attr) => Some(get_attr.subscript()?.ident()?.text_range()),
+            _ => None,
+        }
+    }
+
+    pub fn static_args(&self) -> impl Iterator<Item = CallStaticArg> {
+        self.arguments()
+            .into_iter()
+            .flat_map(|arguments| arguments.static_args())
+    }
+
+    /// the keyword argument called `name`, e.g. `materialized` in
+    /// `config(materialized='table')`
+    pub fn static_kwarg(&self, name: &str) -> Option<CallStaticKwarg> {
+        self.arguments()?
+            .static_kwargs()
+            .find(|kwarg| kwarg.name().as_deref() == Some(name))
+    }
+}
+
+/// Which argument of a call an offset is in
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ArgumentPosition {
+    /// the `n`th positional argument, counting from 0
+    Positional(usize),
+    /// a keyword argument, by name
+    Keyword(String),
+}
+
+impl CallArguments {
+    /// Finds the argument that `offset` is in, or `None` if it's outside the
+    /// parentheses. An argument is everything between two commas, so an
+    /// offset in the whitespace around one or in an argument that hasn't
+    /// been written yet still counts.
+    pub fn argument_at(&self, offset: TextSize) -> Option<ArgumentPosition> {
+        let mut children = self.syntax.children_with_tokens();
+        let left_paren = children.find(|child| child.kind() == SyntaxKind::LeftParen)?;
+        if offset < left_paren.text_range().end() {
+            return None;
+        }
+        let mut positional = 0;
+        let mut current: Option<SyntaxElement> = None;
+        for child in children {
+            match child.kind() {
+                SyntaxKind::RightParen => {
+                    if offset > child.text_range().start() {
+                        return None;
+                    }
+                    break;
+                }
+                SyntaxKind::Comma => {
+                    if child.text_range().end() > offset {
+                        break;
+                    }
+                    if current.map(|arg| arg.kind()) != Some(SyntaxKind::CallStaticKwarg) {
+                        positional += 1;
+                    }
+                    current = None;
+                }
+                SyntaxKind::CallStaticArg | SyntaxKind::CallStaticKwarg => current = Some(child),
+                _ => (),
+            }
+        }
+        match current
+            .and_then(|arg| arg.into_node())
+            .and_then(CallStaticKwarg::cast)
+            .and_then(|kwarg| kwarg.name())
+        {
+            Some(name) => Some(ArgumentPosition::Keyword(name)),
+            None => Some(ArgumentPosition::Positional(positional)),
+        }
+    }
+}
+
+impl CallStaticKwarg {
+    pub fn name(&self) -> Option<String> {
+        self.ident().map(|token| token.text().to_string())
+    }
+
+    /// the value of the argument if it's a string literal
+    pub fn string_value(&self) -> Option<String> {
+        match self.expr()? {
+            Expr::ConstantString(string) => string.value(),
+            _ => None,
+        }
+    }
+}
+
+impl CallStaticArg {
+    /// the value of the argument if it's a string literal
+    pub fn string_value(&self) -> Option<String> {
+        match self.expr()? {
+            Expr::ConstantString(string) => string.value(),
+            _ => None,
+        }
+    }
+
+    /// where the contents of the argument are if it's a string literal
+    pub fn string_value_range(&self) -> Option<TextRange> {
+        match self.expr()? {
+            Expr::ConstantString(string) => string.value_range(),
+            _ => None,
+        }
+    }
+
+    /// The argument's value as written. Literals other than strings aren't
+    /// wrapped in a node, so this can be either a node or a token.
+    pub fn value(&self) -> Option<SyntaxElement> {
+        self.syntax
+            .children_with_tokens()
+            .find(|child| child.kind() != SyntaxKind::Whitespace)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lexer::tokenize;
+    use crate::parser::parse;
+
+    fn first<N: AstNode>(input: &str) -> N {
+        parse(tokenize(input))
+            .syntax()
+            .descendants()
+            .find_map(N::cast)
+            .unwrap()
+    }
+
+    #[test]
+    fn test_macro_signature() {
+        let macro_node: StmtMacro = first("{% macro foo(a, b, c='x', d=1) %}{% endmacro %}");
+        assert_eq!(macro_node.name().and_then(|n| n.name()), Some("foo".into()));
+
+        let signature = macro_node.signature().unwrap();
+        let args: Vec<_> = signature
+            .args()
+            .map(|arg| arg.name().and_then(|n| n.name()))
+            .collect();
+        assert_eq!(args, vec![Some("a".into()), Some("b".into())]);
+
+        let default_args: Vec<_> = signature
+            .default_args()
+            .map(|arg| {
+                (
+                    arg.name().and_then(|n| n.name()),
+                    arg.default_value().map(|v| v.to_string()),
+                )
+            })
+            .collect();
+        assert_eq!(
+            default_args,
+            vec![
+                (Some("c".into()), Some("'x'".into())),
+                (Some("d".into()), Some("1".into()))
+            ]
+        );
+    }
+
+    #[test]
+    fn test_macro_without_signature() {
+        let macro_node: StmtMacro = first("{% macro foo %}{% endmacro %}");
+        assert_eq!(macro_node.name().and_then(|n| n.name()), Some("foo".into()));
+        assert!(macro_node.signature().is_none());
+    }
+
+    #[test]
+    fn test_call_static_args() {
+        let call: ExprCall = first("{{ ref('pkg', \"model\", 3) }}");
+        assert_eq!(call.callee_name(), Some("ref".into()));
+        let args: Vec<_> = call.static_args().map(|arg| arg.string_value()).collect();
+        assert_eq!(args, vec![Some("pkg".into()), Some("model".into()), None]);
+    }
+
+    #[test]
+    fn test_call_static_kwarg() {
+        let call: ExprCall = first("{{ config(materialized='table', enabled=true) }}");
+        let materialized = call.static_kwarg("materialized").unwrap();
+        assert_eq!(materialized.name(), Some("materialized".into()));
+        assert_eq!(materialized.string_value(), Some("table".into()));
+        assert!(call.static_kwarg("enabled").is_some());
+        assert!(call.static_kwarg("tags").is_none());
+    }
+
+    #[test]
+    fn test_call_argument_ranges() {
+        let input = "{{ dbt_utils.star(ref( 'model' ), \"x\") }}";
+        let call: ExprCall = first(input);
+        let range = |needle: &str| {
+            let start = input.find(needle).unwrap();
+            TextRange::at(TextSize::from(start as u32), TextSize::of(needle))
+        };
+        assert_eq!(call.callee_name_range(), Some(range("star")));
+        let args: Vec<_> = call.static_args().collect();
+        assert_eq!(args[0].string_value_range(), None);
+        assert_eq!(args[1].string_value_range(), Some(range("x")));
+
+        let ref_call: ExprCall = first("{{ ref( 'model' ) }}");
+        let arg = ref_call.static_args().next().unwrap();
+        assert_eq!(
+            arg.string_value_range(),
+            Some(TextRange::new(9.into(), 14.into()))
+        );
+    }
+
+    #[test]
+    fn test_empty_string_range() {
+        let call: ExprCall = first("{{ ref('') }}");
+        let arg = call.static_args().next().unwrap();
+        assert_eq!(arg.string_value(), Some("".into()));
+        assert_eq!(arg.string_value_range(), Some(TextRange::empty(8.into())));
+    }
+
+    #[test]
+    fn test_test_block() {
+        let test_node: StmtTest = first("{% test unique(model, column_name) %}{% endtest %}");
+        assert_eq!(
+            test_node.name().and_then(|n| n.name()),
+            Some("unique".into())
+        );
+        assert_eq!(test_node.signature().unwrap().args().count(), 2);
+    }
+
+    #[test]
+    fn test_block_names() {
+        let docs_node: StmtDocs = first("{% docs orders %}the orders{% enddocs %}");
+        assert_eq!(
+            docs_node.name().and_then(|n| n.name()),
+            Some("orders".into())
+        );
+        let snapshot_node: StmtSnapshot = first("{% snapshot snap %}{% endsnapshot %}");
+        assert_eq!(
+            snapshot_node.name().and_then(|n| n.name()),
+            Some("snap".into())
+        );
+    }
+
+    #[test]
+    fn test_assigned_names() {
+        let names = |names: Vec<ExprName>| -> Vec<_> {
+            names.iter().filter_map(|name| name.name()).collect()
+        };
+        let assign: StmtAssign = first("{% set a = b %}");
+        assert_eq!(names(assign.names()), vec!["a".to_string()]);
+        let assign: StmtAssign = first("{% set a, b = 1, 2 %}");
+        assert_eq!(names(assign.names()), vec!["a".to_string(), "b".into()]);
+        let assign: StmtAssign = first("{% set ns.a = 1 %}");
+        assert!(assign.names().is_empty());
+        let assign_block: StmtAssignBlock = first("{% set a %}b{% endset %}");
+        assert_eq!(names(assign_block.names()), vec!["a".to_string()]);
+        let stmt_for: StmtFor = first("{% for k, v in d.items() %}{% endfor %}");
+        assert_eq!(names(stmt_for.names()), vec!["k".to_string(), "v".into()]);
+    }
+
+    #[test]
+    fn test_call_argument_at() {
+        let input = "{{ f(a, g(1), b=2, c= ) }}";
+        let arguments: CallArguments = first(input);
+        let at = |needle: &str| {
+            arguments.argument_at(TextSize::from(input.find(needle).unwrap() as u32))
+        };
+        assert_eq!(at("f("), None);
+        assert_eq!(at("a,"), Some(ArgumentPosition::Positional(0)));
+        assert_eq!(at(", g"), Some(ArgumentPosition::Positional(0)));
+        assert_eq!(at(" g"), Some(ArgumentPosition::Positional(1)));
+        assert_eq!(at("1)"), Some(ArgumentPosition::Positional(1)));
+        assert_eq!(at("b="), Some(ArgumentPosition::Keyword("b".into())));
+        assert_eq!(at(" ) }}"), Some(ArgumentPosition::Keyword("c".into())));
+        assert_eq!(at(") }}"), Some(ArgumentPosition::Keyword("c".into())));
+        assert_eq!(at(" }}"), None);
+
+        // while the call is still being typed
+        let input = "{{ f(a, ";
+        let arguments: CallArguments = first(input);
+        assert_eq!(
+            arguments.argument_at(TextSize::of(input)),
+            Some(ArgumentPosition::Positional(1))
+        );
+    }
+
+    #[test]
+    fn test_call_attribute_callee() {
+        let call: ExprCall = first("{{ dbt_utils.star(from=ref('a')) }}");
+        assert_eq!(call.callee_name(), None);
+        match call.callee() {
+            Some(Expr::GetAttr(get_attr)) => {
+                let ident = get_attr.subscript().and_then(|s| s.ident()).unwrap();
+                assert_eq!(ident.text(), "star");
+            }
+            other => panic!("unexpected callee {:?}", other),
+        }
+        assert_eq!(
+            call.qualified_callee_name(),
+            Some((Some("dbt_utils".into()), "star".into()))
+        );
+
+        let call: ExprCall = first("{% call my_macro(1) %}x{% endcall %}");
+        assert_eq!(
+            call.qualified_callee_name(),
+            Some((None, "my_macro".into()))
+        );
+
+        let call: ExprCall = first("{{ a.b.c() }}");
+        assert_eq!(call.qualified_callee_name(), None);
+    }
+
+    #[test]
+    fn test_materialization_adapter() {
+        let default: StmtMaterialization =
+            first("{% materialization table, default %}{% endmaterialization %}");
+        assert_eq!(default.adapter(), "default");
+        let adapter: StmtMaterialization =
+            first("{% materialization table, adapter='snowflake' %}{% endmaterialization %}");
+        assert_eq!(adapter.name().and_then(|n| n.name()), Some("table".into()));
+        assert_eq!(adapter.adapter(), "snowflake");
+    }
+}
@@ -275,11 +275,11 @@ fn process_token(kind: TokenKind, input: &str) -> Token {
         TokenKind::Operator => Token {
             kind: *OPERATORS
                 .get(input)
-                .expect(&format!("unable to find TokenKind for \"{}\"", input)),
+                .unwrap_or_else(|| panic!("unable to find TokenKind for \"{}\"", input)),
             text: input.into(),
         },
         _ => Token {
-            kind: kind,
+            kind,
             text: input.into(),
         },
     }
@@ -445,7 +445,7 @@ mod tests {
         Context::VALUES.iter().for_each(|c| {
             RULES_BY_CONTEXT
                 .get(c)
-                .expect(&format!("Failed to find rules for context {:?}", c));
+                .unwrap_or_else(|| panic!("Failed to find rules for context {:?}", c));
         })
     }
 
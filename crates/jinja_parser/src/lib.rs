@@ -1,2 +1,4 @@
+pub mod ast;
 pub mod lexer;
 pub mod parser;
+mod reparsing;
@@ -2,7 +2,7 @@ use core::panic;
 use std::collections::VecDeque;
 
 use super::lexer::{Token, TokenKind, COMPARE_OPERATORS};
-use rowan::{Checkpoint, GreenNode, GreenNodeBuilder};
+use rowan::{Checkpoint, GreenNode, GreenNodeBuilder, TextRange, TextSize};
 use SyntaxKind::*;
 
 include!(concat!(env!("OUT_DIR"), "/syntax_kinds.rs"));
@@ -28,11 +28,81 @@ impl rowan::Language for Lang {
     }
 }
 
-// struct ParseError {
-//     // text range of errors
-//     range: (u32, u32),
-//     message: String,
-// }
+/// How seriously a consumer should treat a [`ParseError`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+/// Stable identifier for the kind of problem a [`ParseError`] describes.
+///
+/// Messages may be reworded over time, but codes shouldn't change, so they're
+/// what consumers should match on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ErrorCode {
+    /// found a token that doesn't fit where it is
+    UnexpectedToken,
+    /// ran out of input partway through a construct
+    UnexpectedEof,
+    /// hit `%}` or `}}` partway through an expression
+    UnexpectedContextEnd,
+    /// an expression was required, but nothing was there
+    EmptyExpression,
+    /// a block tag (e.g. `{% if %}`) was never closed by its end tag
+    UnclosedTag,
+    /// an end tag (e.g. `{% endfor %}`) with no matching opening tag
+    UnmatchedEndTag,
+    /// `{% macro %}` or `{% test %}` without an argument list
+    MissingSignature,
+    /// arguments in a call or signature are in an order Jinja rejects
+    InvalidArgumentOrder,
+    /// malformed `{% materialization %}` header
+    InvalidMaterialization,
+    /// tags that are valid Jinja, but aren't parsed by us
+    UnsupportedTag,
+    /// tags that we don't recognize at all
+    UnknownTag,
+    /// `{# #}` or `{% raw %}` missing its closing delimiter
+    UnterminatedBlock,
+}
+
+impl ErrorCode {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ErrorCode::UnexpectedToken => "unexpected-token",
+            ErrorCode::UnexpectedEof => "unexpected-eof",
+            ErrorCode::UnexpectedContextEnd => "unexpected-context-end",
+            ErrorCode::EmptyExpression => "empty-expression",
+            ErrorCode::UnclosedTag => "unclosed-tag",
+            ErrorCode::UnmatchedEndTag => "unmatched-end-tag",
+            ErrorCode::MissingSignature => "missing-signature",
+            ErrorCode::InvalidArgumentOrder => "invalid-argument-order",
+            ErrorCode::InvalidMaterialization => "invalid-materialization",
+            ErrorCode::UnsupportedTag => "unsupported-tag",
+            ErrorCode::UnknownTag => "unknown-tag",
+            ErrorCode::UnterminatedBlock => "unterminated-block",
+        }
+    }
+}
+
+/// Some other location that helps explain a [`ParseError`], like the opening
+/// tag of a block that was never closed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RelatedRange {
+    pub range: TextRange,
+    pub message: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseError {
+    /// text range the error should be reported at
+    pub range: TextRange,
+    pub code: ErrorCode,
+    pub severity: Severity,
+    pub message: String,
+    pub related: Vec<RelatedRange>,
+}
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 enum Tag {
@@ -54,24 +124,45 @@ enum Tag {
     Test,            // generic tests
     Docs,            // markdown docs
     Snapshot,        // snapshot
+}
 
-    // Assume these aren't used, because I'm lazy :eyes:
-    With,
-    Block,
-    Extends,
-    Print,
-    Include,
-    From,
-    Import,
-    Autoescape,
+impl Tag {
+    /// name of the tag that opened the block
+    fn name(&self) -> &'static str {
+        match self {
+            Tag::Root => "template",
+            Tag::For | Tag::ForElse => "for",
+            Tag::If | Tag::Elif | Tag::IfElse => "if",
+            Tag::Set => "set",
+            Tag::Call => "call",
+            Tag::Filter => "filter",
+            Tag::Macro => "macro",
+            Tag::Materialization => "materialization",
+            Tag::Test => "test",
+            Tag::Docs => "docs",
+            Tag::Snapshot => "snapshot",
+        }
+    }
+}
+
+/// A block that's currently open, along with the range of its opening tag
+/// (e.g. `{% for`) so errors can point back at it.
+#[derive(Debug, Clone, Copy)]
+struct OpenTag {
+    tag: Tag,
+    range: TextRange,
 }
 
 struct Parser {
     tokens: Vec<Token>,
     builder: GreenNodeBuilder<'static>,
-    tag_stack: VecDeque<Tag>,
-    // TODO: switch errors to use ParseError for text ranges
-    errors: Vec<String>,
+    tag_stack: VecDeque<OpenTag>,
+    /// blocks that were closed without their end tag, used to explain
+    /// end tags that show up later without a match
+    implicitly_closed: Vec<OpenTag>,
+    /// offset of the current token in the original text
+    offset: TextSize,
+    errors: Vec<ParseError>,
 }
 
 enum TupleParseMode {
@@ -82,7 +173,6 @@ enum TupleParseMode {
 
 enum AssignTargetTuple<'a> {
     WithTuple(&'a [&'static str]),
-    NoTuple,
 }
 
 enum AssignTargetNameMode<'a> {
@@ -107,8 +197,31 @@ impl Parser {
     // Recursive descent
 
     fn parse(mut self) -> Parse {
+        self.parse_template_body();
+        let open_blocks = self
+            .tag_stack
+            .iter()
+            .filter(|open| open.tag != Tag::Root)
+            .map(|open| open.tag.name())
+            .collect();
+        self.empty_tag_stack_until(&[Tag::Root]);
+        self.tag_stack.pop_back();
+        self.builder.finish_node();
+        Parse {
+            green_node: self.builder.finish(),
+            errors: self.errors,
+            open_blocks,
+        }
+    }
+
+    /// Parses every token, leaving the blocks that are still open at the end
+    /// on the tag stack.
+    fn parse_template_body(&mut self) {
         self.builder.start_node(Template.into());
-        self.tag_stack.push_back(Tag::Root);
+        self.tag_stack.push_back(OpenTag {
+            tag: Tag::Root,
+            range: TextRange::empty(0.into()),
+        });
         loop {
             match self.current() {
                 None => break,
@@ -132,14 +245,6 @@ impl Parser {
                 }
             }
         }
-
-        self.empty_tag_stack_until(&[Tag::Root]);
-        self.tag_stack.pop_back();
-        self.builder.finish_node();
-        Parse {
-            green_node: self.builder.finish(),
-            errors: self.errors,
-        }
     }
 
     // TODO: Consider re-writing recursive-descent as Pratt parser?
@@ -156,17 +261,21 @@ impl Parser {
         match self.current() {
             Some(TokenKind::Name) => self.bump(),
             Some(kind) if Self::is_expression_end(kind) => {
-                self.errors.push(
-                    "expected name for 1st part of namespace ref, but found end of context".into(),
+                self.error(
+                    ErrorCode::UnexpectedContextEnd,
+                    "expected name for 1st part of namespace ref, but found end of context",
                 );
                 self.builder.finish_node();
                 return;
             }
             Some(kind) => {
-                self.errors.push(format!(
-                    "expected name for 1st part of namespace ref, but found {:?}",
-                    kind
-                ));
+                self.error(
+                    ErrorCode::UnexpectedToken,
+                    format!(
+                        "expected name for 1st part of namespace ref, but found {:?}",
+                        kind
+                    ),
+                );
                 self.builder.finish_node();
                 return;
             }
@@ -177,23 +286,26 @@ impl Parser {
         match self.current() {
             Some(TokenKind::Name) => self.bump(),
             Some(kind) if Self::is_expression_end(kind) => {
-                self.errors.push(
-                    "expected name for 2nd part of namespace ref, but found end of context".into(),
+                self.error(
+                    ErrorCode::UnexpectedContextEnd,
+                    "expected name for 2nd part of namespace ref, but found end of context",
                 );
                 self.builder.finish_node();
                 return;
             }
             kind => {
-                self.errors.push(format!(
-                    "expected name for 2nd part of namespace ref, but found {:?}",
-                    kind
-                ));
+                self.error(
+                    ErrorCode::UnexpectedToken,
+                    format!(
+                        "expected name for 2nd part of namespace ref, but found {:?}",
+                        kind
+                    ),
+                );
                 self.builder.finish_node();
                 return;
             }
         }
         self.builder.finish_node();
-        return;
     }
 
     /// Parses the thing before an assignment (e.g. `_this_ = expression`)
@@ -206,16 +318,16 @@ impl Parser {
             AssignTargetNameMode::NameOnly => match self.current() {
                 Some(TokenKind::Name) => self.register(SyntaxKind::ExprName),
                 Some(kind) if Self::is_expression_end(kind) => {
-                    self.errors
-                        .push("expected name for assign target, but found end of context".into());
-                    return;
+                    self.error(
+                        ErrorCode::UnexpectedContextEnd,
+                        "expected name for assign target, but found end of context",
+                    );
                 }
                 kind => {
-                    self.errors.push(format!(
-                        "expected name for assign target, but found {:?}",
-                        kind
-                    ));
-                    return;
+                    self.error(
+                        ErrorCode::UnexpectedToken,
+                        format!("expected name for assign target, but found {:?}", kind),
+                    );
                 }
             },
             AssignTargetNameMode::NotNameOnly(with_namespace, with_tuple) => {
@@ -225,10 +337,7 @@ impl Parser {
                 }
                 match with_tuple {
                     AssignTargetTuple::WithTuple(extra_end_rules) => {
-                        self.parse_tuple(TupleParseMode::Simplified, &extra_end_rules, false);
-                    }
-                    AssignTargetTuple::NoTuple => {
-                        self.parse_primary();
+                        self.parse_tuple(TupleParseMode::Simplified, extra_end_rules, false);
                     }
                 }
             }
@@ -249,13 +358,17 @@ impl Parser {
         self.skip_ws();
         match self.current_tok() {
             None => {
-                self.errors
-                    .push("expected \"in\" for for-loop, but found EOF".into());
+                self.error(
+                    ErrorCode::UnexpectedEof,
+                    "expected \"in\" for for-loop, but found EOF",
+                );
                 return;
             }
             Some(tok) if Self::is_expression_end(tok.kind) => {
-                self.errors
-                    .push("expected \"in\" for for-loop, but found end of context".into());
+                self.error(
+                    ErrorCode::UnexpectedContextEnd,
+                    "expected \"in\" for for-loop, but found end of context",
+                );
                 return;
             }
             Some(tok) if tok.is_name("in") => {
@@ -263,18 +376,24 @@ impl Parser {
             }
             Some(tok) if tok.kind == TokenKind::Name => {
                 let text = tok.text.clone();
-                self.errors.push(format!(
-                    "expected \"in\" for for-loop, but found unexpected \"{:?}\"",
-                    text
-                ));
+                self.error(
+                    ErrorCode::UnexpectedToken,
+                    format!(
+                        "expected \"in\" for for-loop, but found unexpected \"{:?}\"",
+                        text
+                    ),
+                );
                 return;
             }
             Some(tok) => {
                 let kind = tok.kind;
-                self.errors.push(format!(
-                    "expected name \"in\" for for-loop, but found unexpected \"{:?}\"",
-                    kind
-                ));
+                self.error(
+                    ErrorCode::UnexpectedToken,
+                    format!(
+                        "expected name \"in\" for for-loop, but found unexpected \"{:?}\"",
+                        kind
+                    ),
+                );
                 return;
             }
         }
@@ -338,8 +457,10 @@ impl Parser {
             None => {
                 self.builder
                     .start_node_at(block_checkpoint, StmtAssign.into());
-                self.errors
-                    .push("expected '=', '%}' or '|', not end of context".into());
+                self.error(
+                    ErrorCode::UnexpectedContextEnd,
+                    "expected '=', '%}' or '|', not end of context",
+                );
                 false
             }
             Some(TokenKind::Assign) => {
@@ -377,12 +498,17 @@ impl Parser {
         for _ in 0.. {
             match self.current() {
                 None => {
-                    self.errors.push("found EOF while parsing signature".into());
+                    self.error(
+                        ErrorCode::UnexpectedEof,
+                        "found EOF while parsing signature",
+                    );
                     break;
                 }
                 Some(kind) if Self::is_expression_end(kind) => {
-                    self.errors
-                        .push("found end of context while parsing signature".into());
+                    self.error(
+                        ErrorCode::UnexpectedContextEnd,
+                        "found end of context while parsing signature",
+                    );
                     break;
                 }
                 Some(TokenKind::RightParen) => {
@@ -391,7 +517,9 @@ impl Parser {
                 }
                 Some(_) => {
                     let checkpoint = self.builder.checkpoint();
+                    let start = self.nonws_offset();
                     self.parse_assign_target(AssignTargetNameMode::NameOnly);
+                    let arg_range = self.range_from(start);
                     self.skip_ws();
                     if self.current() == Some(TokenKind::Assign) {
                         seen_kwarg = true;
@@ -402,8 +530,11 @@ impl Parser {
                         self.builder.finish_node();
                     } else {
                         if seen_kwarg {
-                            self.errors
-                                .push("non-default argument following a default argument".into());
+                            self.error_at(
+                                arg_range,
+                                ErrorCode::InvalidArgumentOrder,
+                                "non-default argument following a default argument",
+                            );
                         }
                         self.builder.start_node_at(checkpoint, SignatureArg.into());
                         self.builder.finish_node();
@@ -412,8 +543,10 @@ impl Parser {
             }
             match self.error_until(&[TokenKind::Comma, TokenKind::RightParen]) {
                 None => {
-                    self.errors
-                        .push("expected ',' or ')', not end of context".into());
+                    self.error(
+                        ErrorCode::UnexpectedContextEnd,
+                        "expected ',' or ')', not end of context",
+                    );
                     break;
                 }
                 Some(TokenKind::Comma) => {
@@ -475,8 +608,9 @@ impl Parser {
         self.skip_ws();
         match self.error_until(&[TokenKind::LeftParen]) {
             None => {
-                self.errors.push(
-                    "expected function signature after macro name but found end of context".into(),
+                self.error(
+                    ErrorCode::MissingSignature,
+                    "expected function signature after macro name but found end of context",
                 );
             }
             Some(TokenKind::LeftParen) => {
@@ -506,14 +640,14 @@ impl Parser {
             self.skip_ws();
             match self.current_tok() {
                 None => {
-                    self.errors.push(
-                        "expected adapter specification for custom materialization but found EOF"
-                            .into(),
+                    self.error(
+                        ErrorCode::InvalidMaterialization,
+                        "expected adapter specification for custom materialization but found EOF",
                     );
                     break;
                 }
                 Some(t) if Self::is_expression_end(t.kind) => {
-                    self.errors.push("expected adapter specification for custom materialization but found end of context".into());
+                    self.error(ErrorCode::InvalidMaterialization, "expected adapter specification for custom materialization but found end of context");
                     break;
                 }
                 Some(t) if t.is_name("default") => {
@@ -527,7 +661,7 @@ impl Parser {
                     self.skip_ws();
                     match self.error_until(&[TokenKind::Assign]) {
                         None => {
-                            self.errors.push("expected '= <adapter>' after adapter specification in custom materialization, but found end of context".into());
+                            self.error(ErrorCode::InvalidMaterialization, "expected '= <adapter>' after adapter specification in custom materialization, but found end of context");
                             self.builder.finish_node();
                             break;
                         }
@@ -542,7 +676,7 @@ impl Parser {
                                     self.parse_string_literal();
                                 }
                                 kind => {
-                                    self.errors.push(format!(
+                                    self.error(ErrorCode::InvalidMaterialization, format!(
                                         "expected string literal specifying adapter, but found {:?}",
                                         kind
                                     ));
@@ -557,7 +691,7 @@ impl Parser {
                 }
                 Some(t) => {
                     let tok = t.clone();
-                    self.errors.push(format!("expected either \"default\" or \"adapter=<adapter>\" for custom materialization but found {:?}", tok));
+                    self.error(ErrorCode::InvalidMaterialization, format!("expected either \"default\" or \"adapter=<adapter>\" for custom materialization but found {:?}", tok));
                     break;
                 }
             }
@@ -577,8 +711,9 @@ impl Parser {
         self.skip_ws();
         match self.error_until(&[TokenKind::LeftParen]) {
             None => {
-                self.errors.push(
-                    "expected function signature after macro name but found end of context".into(),
+                self.error(
+                    ErrorCode::MissingSignature,
+                    "expected function signature after macro name but found end of context",
                 );
             }
             Some(TokenKind::LeftParen) => {
@@ -624,8 +759,7 @@ impl Parser {
             self.bump(); // 'endfor'
             true
         } else {
-            self.errors
-                .push(format!("found unmatched \"{}\" statement", endtag));
+            self.unmatched_endtag(endtag, tags_to_end);
             self.builder.start_node(StmtUnknown.into());
             self.bump();
             self.skip_ws();
@@ -642,6 +776,7 @@ impl Parser {
     /// Assumes `{%` is the next token
     fn parse_statement(&mut self) {
         assert!(self.current() == Some(TokenKind::BlockBegin));
+        let head = self.statement_head_range();
 
         // a statement may be {% endfor %}, which finishes an additional node
         let mut finished_block = false;
@@ -650,7 +785,7 @@ impl Parser {
         let next_tok = self.next_nonws_tok();
         match next_tok {
             None => {
-                self.errors.push("expected tag name, but found EOF".into());
+                self.error(ErrorCode::UnexpectedEof, "expected tag name, but found EOF");
                 self.builder.start_node(StmtUnknown.into());
                 self.bump();
                 self.error_until(&[]);
@@ -658,8 +793,10 @@ impl Parser {
                 return;
             }
             Some(t) if Self::is_expression_end(t.kind) => {
-                self.errors
-                    .push("expected tag name, but end of block".into());
+                self.error(
+                    ErrorCode::UnexpectedContextEnd,
+                    "expected tag name, but end of block",
+                );
                 self.builder.start_node(StmtUnknown.into());
                 self.bump();
                 self.skip_ws();
@@ -673,31 +810,33 @@ impl Parser {
         if tok.kind != TokenKind::Name {
             self.builder.start_node(StmtUnknown.into());
             self.bump();
-            self.errors.push(format!(
-                "expected tag token at the beginning of statement, not {:?}",
-                tok.kind
-            ));
+            self.error(
+                ErrorCode::UnexpectedToken,
+                format!(
+                    "expected tag token at the beginning of statement, not {:?}",
+                    tok.kind
+                ),
+            );
         } else {
             match tok.text.as_str() {
                 "for" => {
-                    self.tag_stack.push_back(Tag::For);
+                    self.push_tag(Tag::For, head);
                     self.parse_for();
                 }
                 "endfor" => {
                     finished_block = self.parse_endtag("endfor", ForEnd, &[Tag::For, Tag::ForElse]);
                 }
                 "if" => {
-                    self.tag_stack.push_back(Tag::If);
+                    self.push_tag(Tag::If, head);
                     self.parse_if();
                 }
                 "elif" => {
                     if self.empty_tag_stack_until(&[Tag::If, Tag::Elif]) {
-                        self.tag_stack.pop_back();
-                        self.tag_stack.push_back(Tag::Elif);
+                        let if_tag = self.tag_stack.pop_back().unwrap();
+                        self.push_tag(Tag::Elif, if_tag.range);
                         self.parse_elif();
                     } else {
-                        self.errors
-                            .push("found unmatched \"elif\" statement".into());
+                        self.unmatched_endtag("elif", &[Tag::If, Tag::Elif]);
                         self.builder.start_node(StmtUnknown.into());
                         self.bump();
                         self.skip_ws();
@@ -710,16 +849,16 @@ impl Parser {
                 "else" => {
                     if self.empty_tag_stack_until(&[Tag::For, Tag::If, Tag::Elif]) {
                         let last_tag = self.tag_stack.pop_back().unwrap();
-                        match last_tag {
+                        match last_tag.tag {
                             Tag::For => {
-                                self.tag_stack.push_back(Tag::ForElse);
+                                self.push_tag(Tag::ForElse, last_tag.range);
                                 self.builder.start_node(ForElse.into());
                                 self.bump(); // '{%'
                                 self.skip_ws();
                                 self.bump(); // 'else'
                             }
                             Tag::If | Tag::Elif => {
-                                self.tag_stack.push_back(Tag::IfElse);
+                                self.push_tag(Tag::IfElse, last_tag.range);
                                 self.builder.start_node(IfElse.into());
                                 self.bump(); // '{%'
                                 self.skip_ws();
@@ -728,8 +867,7 @@ impl Parser {
                             _ => unreachable!(),
                         }
                     } else {
-                        self.errors
-                            .push("found unmatched \"else\" statement".into());
+                        self.unmatched_endtag("else", &[Tag::For, Tag::If, Tag::Elif]);
                         self.builder.start_node(StmtUnknown.into());
                         self.bump();
                         self.skip_ws();
@@ -737,21 +875,21 @@ impl Parser {
                 }
                 "set" => {
                     if self.parse_set() {
-                        self.tag_stack.push_back(Tag::Set);
+                        self.push_tag(Tag::Set, head);
                     }
                 }
                 "endset" => {
                     finished_block = self.parse_endtag("endset", AssignBlockEnd, &[Tag::Set]);
                 }
                 "call" => {
-                    self.tag_stack.push_back(Tag::Call);
+                    self.push_tag(Tag::Call, head);
                     self.parse_call_block();
                 }
                 "endcall" => {
                     finished_block = self.parse_endtag("endcall", CallBlockEnd, &[Tag::Call]);
                 }
                 "filter" => {
-                    self.tag_stack.push_back(Tag::Filter);
+                    self.push_tag(Tag::Filter, head);
                     self.parse_filter_block();
                 }
                 "endfilter" => {
@@ -774,7 +912,7 @@ impl Parser {
                 // similar (outside of their name regex being pretty pared down)
                 "macro" => {
                     self.empty_tag_stack_until(&[Tag::Root]);
-                    self.tag_stack.push_back(Tag::Macro);
+                    self.push_tag(Tag::Macro, head);
                     self.parse_macro();
                 }
                 "endmacro" => {
@@ -782,7 +920,7 @@ impl Parser {
                 }
                 "materialization" => {
                     self.empty_tag_stack_until(&[Tag::Root]);
-                    self.tag_stack.push_back(Tag::Materialization);
+                    self.push_tag(Tag::Materialization, head);
                     self.parse_dbt_materialization();
                 }
                 "endmaterialization" => {
@@ -794,7 +932,7 @@ impl Parser {
                 }
                 "test" => {
                     self.empty_tag_stack_until(&[Tag::Root]);
-                    self.tag_stack.push_back(Tag::Test);
+                    self.push_tag(Tag::Test, head);
                     self.parse_dbt_test();
                 }
                 "endtest" => {
@@ -802,7 +940,7 @@ impl Parser {
                 }
                 "docs" => {
                     self.empty_tag_stack_until(&[Tag::Root]);
-                    self.tag_stack.push_back(Tag::Docs);
+                    self.push_tag(Tag::Docs, head);
                     self.parse_dbt_docs();
                 }
                 "enddocs" => {
@@ -810,7 +948,7 @@ impl Parser {
                 }
                 "snapshot" => {
                     self.empty_tag_stack_until(&[Tag::Root]);
-                    self.tag_stack.push_back(Tag::Snapshot);
+                    self.push_tag(Tag::Snapshot, head);
                     self.parse_dbt_snapshot();
                 }
                 "endsnapshot" => {
@@ -827,36 +965,62 @@ impl Parser {
                         ignored_tag @ ("block" | "endblock" | "extends" | "include" | "import"
                         | "from" | "with" | "endwith" | "autoescape"
                         | "endautoescape") => {
-                            self.errors.push(format!(
-                                "not parsing tag {:?}; currently unsupported",
-                                ignored_tag
-                            ));
+                            self.errors.push(ParseError {
+                                range: head,
+                                code: ErrorCode::UnsupportedTag,
+                                severity: Severity::Warning,
+                                message: format!(
+                                    "not parsing tag {:?}; currently unsupported",
+                                    ignored_tag
+                                ),
+                                related: Vec::new(),
+                            });
                         }
                         "raw" => {
-                            self.errors.push("raw tag can't have any arguments".into());
+                            self.error_at(
+                                head,
+                                ErrorCode::UnexpectedToken,
+                                "raw tag can't have any arguments",
+                            );
                         }
                         "endraw" => {
-                            self.errors.push("found unmatched endraw tag".into());
+                            self.error_at(
+                                head,
+                                ErrorCode::UnmatchedEndTag,
+                                "found unmatched endraw tag",
+                            );
                         }
                         _ => {
-                            self.errors
-                                .push(format!("found unknown tag {:?}", unknown_tag));
+                            self.error_at(
+                                head,
+                                ErrorCode::UnknownTag,
+                                format!("found unknown tag {:?}", unknown_tag),
+                            );
                         }
                     }
                 }
             }
         }
         self.skip_ws();
+        // the tag name is only left unconsumed when an error was already
+        // reported for the statement head, so don't report the rest of it
+        let head_reported = self.offset < head.end();
         // colon allowed for python compatibility (like why?)
-        match self.error_until(&[TokenKind::Colon, TokenKind::BlockEnd]) {
-            None => self
-                .errors
-                .push("expected ':' or '%}', but found EOF".into()),
+        let found = if head_reported {
+            self.skip_until(&[TokenKind::Colon, TokenKind::BlockEnd])
+        } else {
+            self.error_until(&[TokenKind::Colon, TokenKind::BlockEnd])
+        };
+        match found {
+            None => self.error(
+                ErrorCode::UnexpectedEof,
+                "expected ':' or '%}', but found EOF",
+            ),
             Some(TokenKind::Colon) => {
                 self.bump();
                 self.skip_ws();
                 match self.error_until(&[TokenKind::BlockEnd]) {
-                    None => self.errors.push("expected '%}', but found EOF".into()),
+                    None => self.error(ErrorCode::UnexpectedEof, "expected '%}', but found EOF"),
                     Some(TokenKind::BlockEnd) => self.bump(),
                     Some(_) => unreachable!(),
                 }
@@ -893,8 +1057,10 @@ impl Parser {
             self.skip_ws();
             match self.error_until(&[TokenKind::Comma, TokenKind::RightBracket]) {
                 None => {
-                    self.errors
-                        .push("expected ',' or ']', but found end of context".into());
+                    self.error(
+                        ErrorCode::UnexpectedContextEnd,
+                        "expected ',' or ']', but found end of context",
+                    );
                     break;
                 }
                 Some(TokenKind::Comma) => {
@@ -937,26 +1103,30 @@ impl Parser {
                 Some(TokenKind::RightBrace) => {
                     self.builder.finish_node();
                     self.bump();
-                    self.errors
-                        .push("dict key requires \": value\" to complete".into());
+                    self.error(
+                        ErrorCode::UnexpectedToken,
+                        "dict key requires \": value\" to complete",
+                    );
                     break;
                 }
                 Some(kind) if Self::is_expression_end(kind) => {
                     self.builder.finish_node();
-                    self.errors.push(format!(
-                        "expected end of dict, but found end of context {:?}",
-                        kind
-                    ));
+                    self.error(
+                        ErrorCode::UnexpectedContextEnd,
+                        format!("expected end of dict, but found end of context {:?}", kind),
+                    );
                     break;
                 }
                 Some(kind) => {
-                    self.errors
-                        .push(format!("expected ':', but found {:?}", kind));
+                    self.error(
+                        ErrorCode::UnexpectedToken,
+                        format!("expected ':', but found {:?}", kind),
+                    );
                     self.bump_error();
                 }
                 None => {
                     self.builder.finish_node();
-                    self.errors.push("expected ':', but found EOF".into());
+                    self.error(ErrorCode::UnexpectedEof, "expected ':', but found EOF");
                     break;
                 }
             }
@@ -967,8 +1137,10 @@ impl Parser {
             self.skip_ws();
             match self.error_until(&[TokenKind::Comma, TokenKind::RightBrace]) {
                 None => {
-                    self.errors
-                        .push(format!("expected ',' or '}}', but found end of context"));
+                    self.error(
+                        ErrorCode::UnexpectedContextEnd,
+                        "expected ',' or '}', but found end of context",
+                    );
                     break;
                 }
                 Some(TokenKind::Comma) => {
@@ -1030,8 +1202,10 @@ impl Parser {
                 if self.error_until(&[TokenKind::RightParen]).is_some() {
                     self.bump();
                 } else {
-                    self.errors
-                        .push("expected ')' before end of context".into());
+                    self.error(
+                        ErrorCode::UnexpectedContextEnd,
+                        "expected ')' before end of context",
+                    );
                 }
                 self.builder.finish_node();
             }
@@ -1041,7 +1215,7 @@ impl Parser {
             Some(TokenKind::LeftBrace) => {
                 self.parse_dict();
             }
-            _ => self.errors.push("invalid primary expression".into()),
+            _ => self.error(ErrorCode::UnexpectedToken, "invalid primary expression"),
         }
     }
 
@@ -1067,11 +1241,16 @@ impl Parser {
                 }
                 Some(TokenKind::Multiply) => {
                     if seen_dyn_args {
-                        self.errors.push("multiple dynamic args found".into());
+                        self.error(
+                            ErrorCode::InvalidArgumentOrder,
+                            "multiple dynamic args found",
+                        );
                     }
                     if seen_dyn_kwargs {
-                        self.errors
-                            .push("dynamic args found after dynamic kwargs".into());
+                        self.error(
+                            ErrorCode::InvalidArgumentOrder,
+                            "dynamic args found after dynamic kwargs",
+                        );
                     }
                     seen_dyn_args = true;
 
@@ -1083,7 +1262,10 @@ impl Parser {
                 }
                 Some(TokenKind::Power) => {
                     if seen_dyn_kwargs {
-                        self.errors.push("multiple dynamic kwargs found".into());
+                        self.error(
+                            ErrorCode::InvalidArgumentOrder,
+                            "multiple dynamic kwargs found",
+                        );
                     }
                     seen_dyn_kwargs = true;
 
@@ -1097,7 +1279,10 @@ impl Parser {
                     if self.next_nonws_tok().map(|t| t.kind) == Some(TokenKind::Assign) =>
                 {
                     if seen_dyn_kwargs {
-                        self.errors.push("kwarg found after dynamic kwargs".into());
+                        self.error(
+                            ErrorCode::InvalidArgumentOrder,
+                            "kwarg found after dynamic kwargs",
+                        );
                     }
                     seen_kwarg = true;
 
@@ -1109,22 +1294,28 @@ impl Parser {
                     self.builder.finish_node();
                 }
                 Some(kind) if Self::is_expression_end(kind) => {
-                    self.errors.push(format!(
-                        "incomplete call args before context end {:?}",
-                        kind
-                    ));
+                    self.error(
+                        ErrorCode::UnexpectedContextEnd,
+                        format!("incomplete call args before context end {:?}", kind),
+                    );
                     break;
                 }
                 None => break,
                 Some(_) => {
                     if seen_kwarg {
-                        self.errors.push("arg found after kwarg".into());
+                        self.error(ErrorCode::InvalidArgumentOrder, "arg found after kwarg");
                     }
                     if seen_dyn_args {
-                        self.errors.push("arg found after dynamic args".into());
+                        self.error(
+                            ErrorCode::InvalidArgumentOrder,
+                            "arg found after dynamic args",
+                        );
                     }
                     if seen_dyn_kwargs {
-                        self.errors.push("arg found after dynamic kwargs".into());
+                        self.error(
+                            ErrorCode::InvalidArgumentOrder,
+                            "arg found after dynamic kwargs",
+                        );
                     }
 
                     self.builder.start_node(CallStaticArg.into());
@@ -1135,8 +1326,10 @@ impl Parser {
 
             match self.error_until(&[TokenKind::Comma, TokenKind::RightParen]) {
                 None => {
-                    self.errors
-                        .push("expected ',' or ')', not end of context".into());
+                    self.error(
+                        ErrorCode::UnexpectedContextEnd,
+                        "expected ',' or ')', not end of context",
+                    );
                     break;
                 }
                 Some(TokenKind::Comma) => {
@@ -1172,7 +1365,7 @@ impl Parser {
                 self.parse_expression(true);
                 self.skip_ws();
                 match self.current() {
-                    Some(kind) if kind == TokenKind::Colon => self
+                    Some(TokenKind::Colon) => self
                         .builder
                         .start_node_at(slice_checkpoint, ExprSlice.into()),
                     _ => return,
@@ -1193,18 +1386,15 @@ impl Parser {
         }
 
         self.skip_ws();
-        match self.current() {
-            Some(TokenKind::Colon) => {
-                self.bump();
-                self.skip_ws();
-                match self.current() {
-                    Some(t) if t != TokenKind::RightBracket && t != TokenKind::Comma => {
-                        self.parse_expression(true);
-                    }
-                    _ => (),
+        if let Some(TokenKind::Colon) = self.current() {
+            self.bump();
+            self.skip_ws();
+            match self.current() {
+                Some(t) if t != TokenKind::RightBracket && t != TokenKind::Comma => {
+                    self.parse_expression(true);
                 }
+                _ => (),
             }
-            _ => (),
         }
 
         self.builder.finish_node();
@@ -1229,10 +1419,10 @@ impl Parser {
                             self.builder.finish_node();
                         }
                         kind => {
-                            self.errors.push(format!(
-                                "expected name or integer as subscript, not {:?}",
-                                kind
-                            ));
+                            self.error(
+                                ErrorCode::UnexpectedToken,
+                                format!("expected name or integer as subscript, not {:?}", kind),
+                            );
                         }
                     }
                 }
@@ -1252,9 +1442,10 @@ impl Parser {
                         self.skip_ws();
                         match self.error_until(&[TokenKind::RightBracket, TokenKind::Comma]) {
                             None => {
-                                self.errors.push(format!(
+                                self.error(
+                                    ErrorCode::UnexpectedContextEnd,
                                     "expected ']' for subscript, but found end of context",
-                                ));
+                                );
                                 break;
                             }
                             Some(TokenKind::RightBracket) => {
@@ -1303,7 +1494,10 @@ impl Parser {
         match self.current() {
             Some(TokenKind::Name) => self.bump(),
             kind => {
-                self.errors.push(format!("expected name, not {:?}", kind));
+                self.error(
+                    ErrorCode::UnexpectedToken,
+                    format!("expected name, not {:?}", kind),
+                );
                 return;
             }
         }
@@ -1321,7 +1515,10 @@ impl Parser {
                     match self.current() {
                         Some(TokenKind::Name) => self.bump(),
                         kind => {
-                            self.errors.push(format!("expected name, not {:?}", kind));
+                            self.error(
+                                ErrorCode::UnexpectedToken,
+                                format!("expected name, not {:?}", kind),
+                            );
                             break;
                         }
                     }
@@ -1359,11 +1556,8 @@ impl Parser {
             self.builder.start_node(ExprFilterName.into());
             self.parse_nested_name();
 
-            match self.current() {
-                Some(TokenKind::LeftParen) => {
-                    self.parse_call_args();
-                }
-                _ => (),
+            if let Some(TokenKind::LeftParen) = self.current() {
+                self.parse_call_args();
             }
             self.builder.finish_node();
 
@@ -1409,8 +1603,10 @@ impl Parser {
                             // Not sure why this is prohibited tbh. You can
                             // circumvent it if your test has args sooo I guess
                             // it's for clarity?
-                            self.errors
-                                .push("Chaining multiple tests is prohibited".into());
+                            self.error(
+                                ErrorCode::UnexpectedToken,
+                                "Chaining multiple tests is prohibited",
+                            );
                             false
                         }
                         _ => true,
@@ -1752,8 +1948,10 @@ impl Parser {
             // if we should wrap the expression as a tuple element or not
             match self.current_tok() {
                 None => {
-                    self.errors
-                        .push("unexpected EOF while parsing possible tuple".into());
+                    self.error(
+                        ErrorCode::UnexpectedEof,
+                        "unexpected EOF while parsing possible tuple",
+                    );
                     break;
                 }
                 Some(t) if Self::is_tuple_end(t, extra_end_rules) => {
@@ -1784,16 +1982,17 @@ impl Parser {
             match count {
                 0 => {
                     if !explicit_parentheses {
-                        self.errors.push("expression cannot be empty here".into());
+                        self.error(
+                            ErrorCode::EmptyExpression,
+                            "expression cannot be empty here",
+                        );
                         return;
                     }
                     self.builder.start_node(ExprTuple.into());
                     self.skip_ws();
                     self.builder.finish_node();
                 }
-                1 => {
-                    return;
-                }
+                1 => {}
                 _ => unreachable!(),
             }
         }
@@ -1810,8 +2009,10 @@ impl Parser {
         if self.error_until(&[TokenKind::VariableEnd]).is_some() {
             self.bump();
         } else {
-            self.errors
-                .push("incomplete variable, expected \"}}\"".into());
+            self.error(
+                ErrorCode::UnterminatedBlock,
+                "incomplete variable, expected \"}}\"",
+            );
         }
 
         self.builder.finish_node();
@@ -1822,7 +2023,7 @@ impl Parser {
         self.bump();
 
         match self.current() {
-            None => self.errors.push("incomplete comment".into()),
+            None => self.error(ErrorCode::UnterminatedBlock, "incomplete comment"),
             Some(TokenKind::CommentData) => self.bump(),
             Some(TokenKind::CommentEnd) => {
                 self.bump();
@@ -1832,9 +2033,10 @@ impl Parser {
             Some(_) => unreachable!(),
         }
         match self.current() {
-            None => self
-                .errors
-                .push("incomplete comment, expected \"#}\"".into()),
+            None => self.error(
+                ErrorCode::UnterminatedBlock,
+                "incomplete comment, expected \"#}\"",
+            ),
             Some(TokenKind::CommentEnd) => self.bump(),
             Some(_) => unreachable!(),
         }
@@ -1846,7 +2048,7 @@ impl Parser {
         self.bump();
 
         match self.current() {
-            None => self.errors.push("incomplete raw block".into()),
+            None => self.error(ErrorCode::UnterminatedBlock, "incomplete raw block"),
             Some(TokenKind::Data) => self.bump(),
             Some(TokenKind::RawEnd) => {
                 self.bump();
@@ -1856,9 +2058,10 @@ impl Parser {
             Some(_) => unreachable!(),
         }
         match self.current() {
-            None => self
-                .errors
-                .push(r#"incomplete raw block, expected "{% endraw %}""#.into()),
+            None => self.error(
+                ErrorCode::UnterminatedBlock,
+                r#"incomplete raw block, expected "{% endraw %}""#,
+            ),
             Some(TokenKind::RawEnd) => self.bump(),
             Some(_) => unreachable!(),
         }
@@ -1867,10 +2070,7 @@ impl Parser {
 
     // Utilities for traversing through token stream
     fn is_expression_end(kind: TokenKind) -> bool {
-        match kind {
-            TokenKind::VariableEnd | TokenKind::BlockEnd => true,
-            _ => false,
-        }
+        matches!(kind, TokenKind::VariableEnd | TokenKind::BlockEnd)
     }
 
     fn is_tuple_end(token: &Token, extra_end_rules: &[&'static str]) -> bool {
@@ -1917,6 +2117,7 @@ impl Parser {
 
     fn bump(&mut self) {
         let token = self.tokens.pop().unwrap();
+        self.offset += TextSize::of(token.text.as_str());
         self.builder
             .token(SyntaxKind::from(token.kind).into(), &token.text);
     }
@@ -1929,6 +2130,7 @@ impl Parser {
 
     fn bump_error(&mut self) {
         let token = self.tokens.pop().unwrap();
+        self.offset += TextSize::of(token.text.as_str());
         self.builder.token(SyntaxKind::Error.into(), &token.text);
     }
 
@@ -1940,29 +2142,122 @@ impl Parser {
 
     /// adds new tokens as syntax errors until the specified token is found.
     /// returns a boolean denoting if it successfully found the given token
+    ///
+    /// a single error is reported spanning all of the unexpected tokens
     fn error_until(&mut self, tokens: &[TokenKind]) -> Option<TokenKind> {
-        loop {
+        self.recover_until(tokens, true)
+    }
+
+    /// like [`Self::error_until`], but doesn't report anything. Used when an
+    /// error was already reported for the enclosing statement.
+    fn skip_until(&mut self, tokens: &[TokenKind]) -> Option<TokenKind> {
+        self.recover_until(tokens, false)
+    }
+
+    fn recover_until(&mut self, tokens: &[TokenKind], report: bool) -> Option<TokenKind> {
+        // first unexpected token, and the end of the last non-whitespace one
+        let mut unexpected: Option<(TokenKind, TextSize)> = None;
+        let mut end = self.offset;
+        let found = loop {
             match self.current() {
-                None => return None,
-                Some(t) if tokens.contains(&t) => {
-                    return Some(t);
-                }
-                Some(kind) if Self::is_expression_end(kind) => {
-                    return None;
-                }
+                None => break None,
+                Some(t) if tokens.contains(&t) => break Some(t),
+                Some(kind) if Self::is_expression_end(kind) => break None,
                 Some(kind) => {
-                    self.errors
-                        .push(format!("expected one of {:?}, not {:?}", tokens, kind));
+                    if kind != TokenKind::Whitespace {
+                        unexpected.get_or_insert((kind, self.offset));
+                        end = self.current_range().end();
+                    }
                     self.bump_error();
                 }
             }
+        };
+        if let (true, Some((kind, start))) = (report, unexpected) {
+            self.error_at(
+                TextRange::new(start, end),
+                ErrorCode::UnexpectedToken,
+                format!("expected one of {:?}, not {:?}", tokens, kind),
+            );
+        }
+        found
+    }
+
+    // utilities for reporting errors
+
+    /// range of the current token, or an empty range at EOF
+    fn current_range(&self) -> TextRange {
+        match self.current_tok() {
+            Some(tok) => TextRange::at(self.offset, TextSize::of(tok.text.as_str())),
+            None => TextRange::empty(self.offset),
+        }
+    }
+
+    /// offset of the next non-whitespace token
+    fn nonws_offset(&self) -> TextSize {
+        self.offset
+            + self
+                .tokens
+                .iter()
+                .rev()
+                .take_while(|t| t.kind == TokenKind::Whitespace)
+                .map(|t| TextSize::of(t.text.as_str()))
+                .sum::<TextSize>()
+    }
+
+    /// range from `start` until the end of the last consumed token
+    fn range_from(&self, start: TextSize) -> TextRange {
+        TextRange::new(start, self.offset)
+    }
+
+    /// Range covering the start of a statement up to and including its tag
+    /// name (e.g. `{%- endfor`).
+    ///
+    /// Assumes `{%` is the current token.
+    fn statement_head_range(&self) -> TextRange {
+        let mut tokens = self.tokens.iter().rev();
+        let mut end = self.offset;
+        if let Some(block_begin) = tokens.next() {
+            end += TextSize::of(block_begin.text.as_str());
+        }
+        let mut pending = TextSize::from(0);
+        for tok in tokens {
+            pending += TextSize::of(tok.text.as_str());
+            match tok.kind {
+                TokenKind::Whitespace => (),
+                TokenKind::Name => {
+                    end += pending;
+                    break;
+                }
+                _ => break,
+            }
         }
+        TextRange::new(self.offset, end)
+    }
+
+    fn error(&mut self, code: ErrorCode, message: impl Into<String>) {
+        let range = self.current_range();
+        self.error_at(range, code, message);
+    }
+
+    fn error_at(&mut self, range: TextRange, code: ErrorCode, message: impl Into<String>) {
+        self.errors.push(ParseError {
+            range,
+            code,
+            severity: Severity::Error,
+            message: message.into(),
+            related: Vec::new(),
+        });
     }
 
     // utilities for traversing the tag stack
 
+    fn push_tag(&mut self, tag: Tag, range: TextRange) {
+        self.tag_stack.push_back(OpenTag { tag, range });
+    }
+
     /// Finds the top-most entry matching the specified tag in the tag stack.
-    /// If found, the tag stack is truncated until that point.
+    /// If found, the tag stack is truncated until that point, and an error is
+    /// reported for every block that was implicitly closed.
     /// If no such tag is found, the tag stack is not truncated at all
     ///
     /// Returns whether the tag was found in the stack or not
@@ -1971,33 +2266,85 @@ impl Parser {
             .tag_stack
             .iter()
             .rev()
-            .enumerate()
-            .find_map(|(i, tag)| {
-                if end_tags.contains(tag) {
-                    Some(i)
-                } else {
-                    None
-                }
-            });
+            .position(|open| end_tags.contains(&open.tag));
         match top_tag {
             Some(i) => {
+                // if we're in the middle of a statement, that's what closed
+                // the blocks
+                let closed_by = if self.current() == Some(TokenKind::BlockBegin) {
+                    Some(self.statement_head_range())
+                } else {
+                    None
+                };
                 for _ in 0..i {
-                    let tag = self.tag_stack.pop_back().unwrap();
+                    let open = self.tag_stack.pop_back().unwrap();
                     self.builder.finish_node();
-                    self.errors
-                        .push(format!("expected tag {:?} to be closed", tag));
+                    self.errors.push(ParseError {
+                        range: open.range,
+                        code: ErrorCode::UnclosedTag,
+                        severity: Severity::Error,
+                        message: format!(
+                            "expected \"{}\" block to be closed with \"end{}\"",
+                            open.tag.name(),
+                            open.tag.name()
+                        ),
+                        related: closed_by
+                            .map(|range| RelatedRange {
+                                range,
+                                message: format!(
+                                    "\"{}\" block was implicitly closed here",
+                                    open.tag.name()
+                                ),
+                            })
+                            .into_iter()
+                            .collect(),
+                    });
+                    self.implicitly_closed.push(open);
                 }
                 true
             }
             None => false,
         }
     }
+
+    /// Reports an end tag (or `elif` / `else`) that doesn't match any open
+    /// block, pointing at the most recent matching block that was implicitly
+    /// closed if there is one.
+    ///
+    /// Assumes `{%` is the current token.
+    fn unmatched_endtag(&mut self, endtag: &'static str, tags_to_end: &[Tag]) {
+        let related = match self
+            .implicitly_closed
+            .iter()
+            .rposition(|open| tags_to_end.contains(&open.tag))
+        {
+            Some(i) => {
+                let open = self.implicitly_closed.remove(i);
+                vec![RelatedRange {
+                    range: open.range,
+                    message: format!(
+                        "closest \"{}\" block was already closed before this",
+                        open.tag.name()
+                    ),
+                }]
+            }
+            None => Vec::new(),
+        };
+        self.errors.push(ParseError {
+            range: self.statement_head_range(),
+            code: ErrorCode::UnmatchedEndTag,
+            severity: Severity::Error,
+            message: format!("found unmatched \"{}\" statement", endtag),
+            related,
+        });
+    }
 }
 
 pub struct Parse {
-    green_node: GreenNode,
-    #[allow(unused)]
-    errors: Vec<String>,
+    pub(crate) green_node: GreenNode,
+    pub(crate) errors: Vec<ParseError>,
+    /// names of the blocks still open at the end of the text, outermost first
+    pub(crate) open_blocks: Vec<&'static str>,
 }
 
 pub fn parse(tokens: Vec<Token>) -> Parse {
@@ -2006,13 +2353,38 @@ pub fn parse(tokens: Vec<Token>) -> Parse {
     Parser {
         tokens,
         tag_stack: VecDeque::new(),
+        implicitly_closed: Vec::new(),
+        offset: 0.into(),
         builder: GreenNodeBuilder::new(),
         errors: Vec::new(),
     }
     .parse()
 }
 
-type SyntaxNode = rowan::SyntaxNode<Lang>;
+/// The blocks that are still open at the end of `tokens`, outermost first,
+/// named after the tags that opened them (e.g. `for` for a `for` block that's
+/// reached its `else`). A statement that's still being typed at the end,
+/// with no `%}` yet, is left out, so this is what decides which end tag
+/// could come next.
+pub fn open_blocks(tokens: Vec<Token>) -> Vec<&'static str> {
+    let mut tokens = tokens;
+    let last_begin = tokens
+        .iter()
+        .rposition(|token| token.kind == TokenKind::BlockBegin);
+    let last_end = tokens
+        .iter()
+        .rposition(|token| token.kind == TokenKind::BlockEnd);
+    match (last_begin, last_end) {
+        (Some(begin), Some(end)) if end > begin => {}
+        (Some(begin), _) => tokens.truncate(begin),
+        (None, _) => {}
+    }
+    parse(tokens).open_blocks
+}
+
+pub type SyntaxNode = rowan::SyntaxNode<Lang>;
+pub type SyntaxToken = rowan::SyntaxToken<Lang>;
+pub type SyntaxElement = rowan::SyntaxElement<Lang>;
 
 impl Parse {
     pub fn green(&self) -> GreenNode {
@@ -2023,11 +2395,17 @@ impl Parse {
         SyntaxNode::new_root(self.green_node.clone())
     }
 
-    pub fn get_errors<'a>(&'a self) -> &'a [String] {
+    /// The blocks that are still open at the end of the text, outermost
+    /// first, named after the tags that opened them.
+    pub fn open_blocks(&self) -> &[&'static str] {
+        &self.open_blocks
+    }
+
+    pub fn get_errors(&self) -> &[ParseError] {
         &self.errors
     }
 
-    pub fn take_errors(self) -> Vec<String> {
+    pub fn take_errors(self) -> Vec<ParseError> {
         self.errors
     }
 }
@@ -2049,8 +2427,9 @@ pub fn print_node(node: SyntaxNode, indent: usize) {
 
 #[cfg(test)]
 mod tests {
-    use super::{parse, print_node};
+    use super::{open_blocks, parse, print_node, ErrorCode, ParseError, Severity};
     use crate::lexer::tokenize;
+    use rowan::TextRange;
 
     struct ParseTestCase {
         input: &'static str,
@@ -2074,6 +2453,101 @@ mod tests {
         };
     }
 
+    fn parse_errors(input: &str) -> Vec<ParseError> {
+        parse(tokenize(input)).take_errors()
+    }
+
+    fn range(start: u32, end: u32) -> TextRange {
+        TextRange::new(start.into(), end.into())
+    }
+
+    #[test]
+    fn test_error_unclosed_if() {
+        let errors = parse_errors("{% if true %} blah");
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].code, ErrorCode::UnclosedTag);
+        assert_eq!(errors[0].range, range(0, 5));
+        assert!(errors[0].related.is_empty());
+    }
+
+    #[test]
+    fn test_error_unclosed_if_in_for() {
+        let errors = parse_errors("{% for x in y %}{%- if x %}{% endfor %}");
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].code, ErrorCode::UnclosedTag);
+        assert_eq!(errors[0].range, range(16, 22));
+        assert_eq!(errors[0].related[0].range, range(27, 36));
+    }
+
+    #[test]
+    fn test_open_blocks() {
+        let open = |input| open_blocks(tokenize(input));
+        assert_eq!(open("select 1"), Vec::<&str>::new());
+        assert_eq!(
+            open("{% macro m() %}{% for x in y %}{% if x %}{% else %}"),
+            vec!["macro", "for", "if"]
+        );
+        assert_eq!(open("{% for x in y %}{% else %}"), vec!["for"]);
+        assert_eq!(open("{% if x %}{% endif %}{% set y %}"), vec!["set"]);
+        assert_eq!(open("{% set y = 1 %}"), Vec::<&str>::new());
+        // the statement being typed doesn't count
+        assert_eq!(open("{% if x %}{% endi"), vec!["if"]);
+        assert_eq!(open("{% for x in y %}{% if"), vec!["for"]);
+    }
+
+    #[test]
+    fn test_error_stray_endfor() {
+        let errors = parse_errors("select 1 {% endfor %}");
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].code, ErrorCode::UnmatchedEndTag);
+        assert_eq!(errors[0].range, range(9, 18));
+    }
+
+    #[test]
+    fn test_error_dangling_endmacro() {
+        let errors = parse_errors("{% macro a() %}{% macro b() %}{% endmacro %}{% endmacro %}");
+        assert_eq!(errors.len(), 2);
+        assert_eq!(errors[0].code, ErrorCode::UnclosedTag);
+        assert_eq!(errors[0].range, range(0, 8));
+        assert_eq!(errors[0].related[0].range, range(15, 23));
+        assert_eq!(errors[1].code, ErrorCode::UnmatchedEndTag);
+        assert_eq!(errors[1].range, range(44, 55));
+        assert_eq!(errors[1].related[0].range, range(0, 8));
+    }
+
+    #[test]
+    fn test_error_missing_signature() {
+        let errors = parse_errors("{% macro something %}{% endmacro %}");
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].code, ErrorCode::MissingSignature);
+        assert_eq!(errors[0].range, range(19, 21));
+    }
+
+    #[test]
+    fn test_error_signature_order() {
+        let errors = parse_errors("{% macro something(a=1, b) %}{% endmacro %}");
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].code, ErrorCode::InvalidArgumentOrder);
+        assert_eq!(errors[0].range, range(24, 25));
+    }
+
+    #[test]
+    fn test_error_unsupported_tag() {
+        let errors = parse_errors("{% include 'other.sql' %}");
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].code, ErrorCode::UnsupportedTag);
+        assert_eq!(errors[0].severity, Severity::Warning);
+        assert_eq!(errors[0].range, range(0, 10));
+    }
+
+    #[test]
+    fn test_error_unexpected_token() {
+        let errors = parse_errors("{{ ref('a') b c }}");
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].code, ErrorCode::UnexpectedToken);
+        assert_eq!(errors[0].range, range(12, 15));
+    }
+
     test_case!(test_basic_raw, "{% raw %}raw data{% endraw %}");
 
     test_case!(test_tuple, "{{ 1,2, 3}} test");
@@ -0,0 +1,351 @@
+//! Incremental reparsing.
+//!
+//! Most edits only touch a single top-level segment of a template: one
+//! `{{ }}`, `{% %}`, `{# #}` or chunk of data. The lexer always returns to the
+//! root context after a segment, so we can relex and reparse just that
+//! segment, and splice the new green node into the existing tree. Whenever that
+//! might give a different result than parsing the whole file (e.g. the edit
+//! changes the block structure, or spans multiple segments) we fall back to a
+//! full parse.
+
+use std::ops::Range;
+
+use rowan::{TextRange, TextSize};
+
+use crate::lexer::tokenize;
+use crate::parser::{parse, ErrorCode, Parse, ParseError, SyntaxKind, SyntaxNode};
+
+/// opening and closing tokens of each kind of delimited segment
+const SEGMENT_DELIMITERS: [(SyntaxKind, SyntaxKind); 4] = [
+    (SyntaxKind::BlockBegin, SyntaxKind::BlockEnd),
+    (SyntaxKind::VariableBegin, SyntaxKind::VariableEnd),
+    (SyntaxKind::CommentBegin, SyntaxKind::CommentEnd),
+    (SyntaxKind::RawBegin, SyntaxKind::RawEnd),
+];
+
+/// Error codes that depend on the statements around the one that reported
+/// them, so they can't be trusted when reparsing a segment on its own.
+const STRUCTURAL_ERRORS: [ErrorCode; 2] = [ErrorCode::UnclosedTag, ErrorCode::UnmatchedEndTag];
+
+impl Parse {
+    /// Returns the result of replacing the text in `delete` with `insert`,
+    /// reusing as much of the existing tree as possible.
+    pub fn reparse(&self, delete: TextRange, insert: &str) -> Parse {
+        self.reparse_with_range(delete, insert).0
+    }
+
+    /// Like [`Parse::reparse`], but also returns the range of the segment in
+    /// this tree that was reparsed, or `None` if the whole text was. Anything
+    /// worked out from the old tree only needs redoing for that segment.
+    pub fn reparse_with_range(
+        &self,
+        delete: TextRange,
+        insert: &str,
+    ) -> (Parse, Option<TextRange>) {
+        let root = self.syntax();
+        if let Some((parse, segment)) = self.reparse_segment(&root, delete, insert) {
+            return (parse, Some(segment));
+        }
+
+        let mut text = root.to_string();
+        let delete = clamp_range(&text, delete);
+        text.replace_range(delete, insert);
+        (parse(tokenize(&text)), None)
+    }
+
+    /// Tries to reparse only the segment containing the edit, returning
+    /// `None` if a full parse is needed.
+    pub(crate) fn reparse_segment(
+        &self,
+        root: &SyntaxNode,
+        delete: TextRange,
+        insert: &str,
+    ) -> Option<(Parse, TextRange)> {
+        if !root.text_range().contains_range(delete) {
+            return None;
+        }
+        // an insertion on the boundary of two segments could belong to
+        // either of them, so try both
+        let mut candidates: Vec<SyntaxNode> = Vec::new();
+        for token in root.token_at_offset(delete.start()) {
+            if let Some(segment) = token.parent_ancestors().find(is_segment) {
+                if segment.text_range().contains_range(delete) && !candidates.contains(&segment) {
+                    candidates.push(segment);
+                }
+            }
+        }
+        candidates.into_iter().find_map(|segment| {
+            let parse = self.reparse_in(&segment, delete, insert)?;
+            Some((parse, segment.text_range()))
+        })
+    }
+
+    fn reparse_in(&self, segment: &SyntaxNode, delete: TextRange, insert: &str) -> Option<Parse> {
+        let segment_range = segment.text_range();
+        let mut text = segment.to_string();
+        let local_delete = delete.checked_sub(segment_range.start())?;
+        if !text.is_char_boundary(local_delete.start().into())
+            || !text.is_char_boundary(local_delete.end().into())
+        {
+            return None;
+        }
+        text.replace_range(Range::<usize>::from(local_delete), insert);
+        // data ending in '{' could start a new segment with whatever follows
+        if segment.kind() == SyntaxKind::ExprData && text.ends_with('{') {
+            return None;
+        }
+
+        let fragment = parse(tokenize(&text));
+        let fragment_root = fragment.syntax();
+        let mut children = fragment_root.children_with_tokens();
+        let only_child = children.next()?.into_node()?;
+        if children.next().is_some() {
+            return None;
+        }
+        // block statements get wrapped in their statement node (e.g. an
+        // `IfStart` in a `StmtIf`), so dig down to the node that was replaced
+        let replacement = std::iter::successors(Some(only_child), |node| node.first_child())
+            .find(|node| node.kind() == segment.kind())?;
+        if replacement.text_range() != fragment_root.text_range() || !is_segment(&replacement) {
+            return None;
+        }
+
+        let fragment_errors = fragment.take_errors();
+        if fragment_errors
+            .iter()
+            .any(|error| error.code == ErrorCode::UnmatchedEndTag)
+        {
+            return None;
+        }
+
+        let mut errors = Vec::with_capacity(self.errors.len() + fragment_errors.len());
+        for error in &self.errors {
+            let placement = place(error.range, segment_range);
+            let related_placements: Vec<_> = error
+                .related
+                .iter()
+                .map(|related| place(related.range, segment_range))
+                .collect();
+            match placement {
+                Placement::Before | Placement::After => {
+                    // anything pointing into the segment depends on its
+                    // structure
+                    if related_placements
+                        .iter()
+                        .any(|p| !matches!(p, Placement::Before | Placement::After))
+                    {
+                        return None;
+                    }
+                    errors.push(shift_error(error, segment_range.end(), delete, insert));
+                }
+                Placement::Inside => {
+                    if STRUCTURAL_ERRORS.contains(&error.code) || !error.related.is_empty() {
+                        return None;
+                    }
+                    // replaced by the errors from the fragment
+                }
+                Placement::Overlapping => return None,
+            }
+        }
+        errors.extend(
+            fragment_errors
+                .into_iter()
+                // the rest of the block isn't part of the fragment
+                .filter(|error| error.code != ErrorCode::UnclosedTag)
+                .map(|mut error| {
+                    error.range += segment_range.start();
+                    for related in error.related.iter_mut() {
+                        related.range += segment_range.start();
+                    }
+                    error
+                }),
+        );
+        errors.sort_by_key(|error| error.range.start());
+
+        Some(Parse {
+            green_node: segment.replace_with(replacement.green().into_owned()),
+            errors,
+            // the replacement has the same kind as the segment, so it opens
+            // or closes the same blocks
+            open_blocks: self.open_blocks.clone(),
+        })
+    }
+}
+
+/// Whether a node is a single `{{ }}`, `{% %}`, `{# #}`, raw block or chunk
+/// of data
+fn is_segment(node: &SyntaxNode) -> bool {
+    if node.kind() == SyntaxKind::ExprData {
+        return true;
+    }
+    let (first, last) = match (node.first_child_or_token(), node.last_child_or_token()) {
+        (Some(rowan::NodeOrToken::Token(first)), Some(rowan::NodeOrToken::Token(last))) => {
+            (first, last)
+        }
+        _ => return false,
+    };
+    SEGMENT_DELIMITERS
+        .iter()
+        .any(|&(begin, end)| first.kind() == begin && last.kind() == end)
+}
+
+enum Placement {
+    Before,
+    Inside,
+    After,
+    Overlapping,
+}
+
+fn place(range: TextRange, segment: TextRange) -> Placement {
+    if range.end() <= segment.start() {
+        Placement::Before
+    } else if range.start() >= segment.end() {
+        Placement::After
+    } else if segment.contains_range(range) {
+        Placement::Inside
+    } else {
+        Placement::Overlapping
+    }
+}
+
+/// moves ranges after the edited segment to account for the edit
+fn shift_error(
+    error: &ParseError,
+    segment_end: TextSize,
+    delete: TextRange,
+    insert: &str,
+) -> ParseError {
+    let shift = |range: TextRange| {
+        if range.start() >= segment_end {
+            TextRange::at(
+                range.start() - delete.len() + TextSize::of(insert),
+                range.len(),
+            )
+        } else {
+            range
+        }
+    };
+    let mut error = error.clone();
+    error.range = shift(error.range);
+    for related in error.related.iter_mut() {
+        related.range = shift(related.range);
+    }
+    error
+}
+
+/// clamps a range to the text, keeping it on char boundaries
+fn clamp_range(text: &str, range: TextRange) -> Range<usize> {
+    let floor = |offset: TextSize| {
+        let mut offset = usize::from(offset).min(text.len());
+        while !text.is_char_boundary(offset) {
+            offset -= 1;
+        }
+        offset
+    };
+    floor(range.start())..floor(range.end())
+}
+
+#[cfg(test)]
+mod tests {
+    use rowan::{TextRange, TextSize};
+
+    use crate::lexer::tokenize;
+    use crate::parser::{parse, ParseError};
+
+    fn sorted_errors(mut errors: Vec<ParseError>) -> Vec<ParseError> {
+        errors.sort_by_key(|error| (error.range.start(), error.range.end()));
+        errors
+    }
+
+    /// replaces the first occurrence of `from` with `to`, checking the result
+    /// against a full parse
+    fn check_edit(before: &str, from: &str, to: &str, incremental: bool) {
+        let start = before.find(from).unwrap();
+        let delete = TextRange::at(TextSize::from(start as u32), TextSize::of(from));
+        let after = before.replacen(from, to, 1);
+
+        let old = parse(tokenize(before));
+        let expected = parse(tokenize(&after));
+        let (_, segment) = old.reparse_with_range(delete, to);
+        if let Some(segment) = segment {
+            assert!(segment.contains_range(delete));
+        }
+        assert_eq!(
+            segment.is_some(),
+            incremental,
+            "expected incremental={} for {:?} -> {:?}",
+            incremental,
+            before,
+            after
+        );
+        let reparsed = old.reparse(delete, to);
+        assert_eq!(reparsed.syntax().to_string(), after);
+        assert_eq!(reparsed.green(), expected.green());
+        assert_eq!(reparsed.open_blocks(), expected.open_blocks());
+        assert_eq!(
+            sorted_errors(reparsed.take_errors()),
+            sorted_errors(expected.take_errors())
+        );
+    }
+
+    #[test]
+    fn test_reparse_variable() {
+        check_edit("select * from {{ ref('a') }}", "'a'", "'abc'", true);
+        check_edit(
+            "{% if x %}{{ ref('a') }}{% endif %} b",
+            "ref",
+            "source",
+            true,
+        );
+    }
+
+    #[test]
+    fn test_reparse_data() {
+        check_edit("select 1 {{ x }} from t", "from", "from other", true);
+        check_edit("{% for x in y %} a {% endfor %}", " a ", " b ", true);
+    }
+
+    #[test]
+    fn test_reparse_statement_head() {
+        check_edit(
+            "{% macro foo(a) %}{{ a }}{% endmacro %}",
+            "foo(a)",
+            "bar(a, b=1)",
+            true,
+        );
+        check_edit("{% if x %}a{% elif y %}b{% endif %}", "x", "x and z", true);
+        check_edit("{% set x = 1 %}{{ x }}", "1", "2", true);
+    }
+
+    #[test]
+    fn test_reparse_comment() {
+        check_edit("a {# some comment #} b", "some", "other", true);
+    }
+
+    #[test]
+    fn test_reparse_keeps_errors() {
+        check_edit("{{ ref('a') b }} {{ c d }}", "c d", "c", true);
+        check_edit("{{ a b }} {{ c }} {% endfor %}", "c", "c + 1", true);
+        check_edit("{% if x %}{{ c }}", "c", "c +", true);
+    }
+
+    #[test]
+    fn test_reparse_fallback() {
+        // new block structure
+        check_edit("select {{ x }} from t", "from", "{% if y %}from", false);
+        check_edit("{% set x = 1 %}{{ x }}", " = 1", "", false);
+        check_edit("{% if x %}a{% endif %}", "endif", "endfor", false);
+        // unterminated segments
+        check_edit("{{ x }} from t", "}}", "", false);
+        check_edit("data {{ x }}", "data ", "data{", false);
+        // spanning multiple segments
+        check_edit("{{ x }} a {{ y }}", "x }} a {{ y", "z", false);
+        // the edited block is referenced by other errors
+        check_edit(
+            "{% if x %}{% macro a() %}{% endmacro %}",
+            "if x",
+            "if y",
+            false,
+        );
+    }
+}
@@ -0,0 +1,648 @@
+use dbt_jinja_parser::ast::{
+    AstNode, ExprCall, ExprName, Signature, StmtAssign, StmtAssignBlock, StmtFor, StmtMacro,
+    StmtTest,
+};
+use dbt_jinja_parser::lexer::tokenize;
+use dbt_jinja_parser::parser::{open_blocks, SyntaxKind, SyntaxNode, SyntaxToken};
+use rowan::{TextRange, TextSize, TokenAtOffset};
+use std::path::PathBuf;
+use tower_lsp::lsp_types::{
+    CompletionItem, CompletionItemKind, CompletionTextEdit, InsertTextFormat, Position, TextEdit,
+};
+
+use crate::config::get_config_argument;
+use crate::entity::{DbtProject, BUILTINS};
+use crate::line_index::{LineIndex, PositionEncoding};
+
+/// The tags that open a block, with the snippet for what comes after the
+/// tag's name and whether it needs an end tag. `set` can be either.
+const BLOCK_TAGS: [(&str, &str, bool); 10] = [
+    ("if", " ${1:condition}", true),
+    ("for", " ${1:item} in ${2:items}", true),
+    ("set", " ${1:name} = ${2:value}", false),
+    ("set", " ${1:name}", true),
+    ("macro", " ${1:name}($2)", true),
+    ("call", " ${1:macro}($2)", true),
+    ("materialization", " ${1:name}, ${2:default}", true),
+    ("test", " ${1:name}(${2:model}, ${3:column_name})", true),
+    ("docs", " ${1:name}", true),
+    ("snapshot", " ${1:name}", true),
+];
+
+/// Completes the name of a `{% %}` tag while it's being typed: the tags
+/// that open a block, as snippets that include their end tag, along with
+/// the end tag (and `elif` or `else`) of the innermost block that's open.
+/// `None` if the cursor isn't on a tag's name.
+pub fn get_tag_completion(
+    syntax_tree: &SyntaxNode,
+    line_index: &LineIndex,
+    encoding: PositionEncoding,
+    offset: TextSize,
+) -> Option<Vec<CompletionItem>> {
+    let name_start = get_tag_name_start(syntax_tree, offset)?;
+    let text = syntax_tree.text().to_string();
+    let rest = &text[usize::from(offset)..];
+    let name_end = offset
+        + TextSize::of(
+            rest.find(|c: char| !is_name_char(c))
+                .map_or(rest, |end| &rest[..end]),
+        );
+
+    // the `%}` might be there already, in which case the snippet replaces
+    // it, keeping its whitespace control. If anything else is, like the
+    // condition of an `if` whose name is being changed, only the name is.
+    let after = rest[usize::from(name_end - offset)..]
+        .split('\n')
+        .next()
+        .unwrap_or_default();
+    let rest_of_tag = after.trim_start();
+    let (replace_end, close, snippets) = match ["-%}", "%}"]
+        .into_iter()
+        .find(|close| rest_of_tag.starts_with(close))
+    {
+        Some(close) => (
+            name_end + TextSize::of(after) - TextSize::of(rest_of_tag) + TextSize::of(close),
+            close,
+            true,
+        ),
+        None => (name_end, "%}", rest_of_tag.is_empty()),
+    };
+    let range = line_index.range(TextRange::new(name_start, replace_end), encoding);
+    let item =
+        |label: String, new_text: String, detail: String, sort_text: String| CompletionItem {
+            filter_text: Some(label.split(' ').next().unwrap_or_default().to_string()),
+            label,
+            kind: Some(CompletionItemKind::KEYWORD),
+            detail: Some(detail),
+            sort_text: Some(sort_text),
+            insert_text_format: Some(if snippets {
+                InsertTextFormat::SNIPPET
+            } else {
+                InsertTextFormat::PLAIN_TEXT
+            }),
+            text_edit: Some(CompletionTextEdit::Edit(TextEdit { range, new_text })),
+            ..Default::default()
+        };
+
+    let mut items = Vec::new();
+    let open = open_blocks(tokenize(&text[..usize::from(name_start)]));
+    if let Some(&innermost) = open.last() {
+        let end_tag = format!("end{}", innermost);
+        let new_text = if snippets {
+            format!("{} {}", end_tag, close)
+        } else {
+            end_tag.clone()
+        };
+        items.push(item(
+            end_tag,
+            new_text,
+            format!("closes the \"{}\" block", innermost),
+            "0".to_string(),
+        ));
+        let branches: &[(&str, &str)] = match innermost {
+            "if" => &[("elif", " ${1:condition}"), ("else", "")],
+            "for" => &[("else", "")],
+            _ => &[],
+        };
+        for (name, arguments) in branches {
+            let new_text = if snippets {
+                format!("{}{} {}", name, arguments, close)
+            } else {
+                name.to_string()
+            };
+            items.push(item(
+                name.to_string(),
+                new_text,
+                format!("branch of the \"{}\" block", innermost),
+                format!("1{}", name),
+            ));
+        }
+    }
+    for (i, (name, arguments, is_block)) in BLOCK_TAGS.iter().enumerate() {
+        if !snippets && i > 0 && BLOCK_TAGS[i - 1].0 == *name {
+            continue;
+        }
+        let label = if *is_block && *name == "set" {
+            "set block".to_string()
+        } else {
+            name.to_string()
+        };
+        let (new_text, detail) = match (snippets, is_block) {
+            (false, _) => (name.to_string(), format!("{{% {} %}}", name)),
+            (true, false) => (
+                format!("{}{} {}", name, arguments, close),
+                format!("{{% {} %}}", name),
+            ),
+            (true, true) => (
+                format!("{}{} {}\n\t$0\n{{% end{} %}}", name, arguments, close, name),
+                format!("{{% {} %}} … {{% end{} %}}", name, name),
+            ),
+        };
+        items.push(item(label, new_text, detail, format!("2{:02}", i)));
+    }
+    Some(items)
+}
+
+fn is_name_char(c: char) -> bool {
+    c.is_ascii_alphanumeric() || c == '_'
+}
+
+/// where the name of the tag under the cursor starts, if the cursor is on
+/// it (or right after the `{%`, before anything's been typed)
+fn get_tag_name_start(syntax_tree: &SyntaxNode, offset: TextSize) -> Option<TextSize> {
+    let mut token = match syntax_tree.token_at_offset(offset) {
+        TokenAtOffset::None => return None,
+        TokenAtOffset::Single(token) => token,
+        TokenAtOffset::Between(left, _) => left,
+    };
+    // names the parser doesn't know are errors, and so is whitespace after
+    // them
+    while token.kind() != SyntaxKind::BlockBegin {
+        match token.kind() {
+            SyntaxKind::Whitespace | SyntaxKind::Name | SyntaxKind::Error => {
+                token = token.prev_token()?
+            }
+            _ => return None,
+        }
+    }
+    let tag_start = token.text_range().end();
+    if offset < tag_start {
+        return None;
+    }
+    let typed = syntax_tree
+        .text()
+        .slice(TextRange::new(tag_start, offset))
+        .to_string();
+    let name = typed.trim_start();
+    if !name.chars().all(is_name_char) {
+        return None;
+    }
+    Some(offset - TextSize::of(name))
+}
+
+/// Whether the cursor is where a `{% %}` tag takes an expression, like the
+/// condition of an `if` or the value of a `set`, as opposed to where it
+/// declares a name. `token` is the one that ends at or contains the cursor.
+pub fn is_tag_expression(token: &SyntaxToken, offset: TextSize) -> bool {
+    let is_variable = match token.kind() {
+        SyntaxKind::Name => token
+            .parent()
+            .is_some_and(|parent| parent.kind() == SyntaxKind::ExprName),
+        SyntaxKind::Dot | SyntaxKind::StringLiteral => false,
+        _ => true,
+    };
+    if !is_variable {
+        return false;
+    }
+    let tag = token.parent_ancestors().find(|node| {
+        matches!(
+            node.kind(),
+            SyntaxKind::StmtAssign
+                | SyntaxKind::IfStart
+                | SyntaxKind::IfElif
+                | SyntaxKind::ForStart
+                | SyntaxKind::StmtDo
+                | SyntaxKind::CallBlockStart
+        )
+    });
+    let tag = match tag {
+        None => return false,
+        Some(tag) => tag,
+    };
+    let tokens = tag
+        .children_with_tokens()
+        .filter_map(|child| child.into_token())
+        .take_while(|token| token.text_range().end() <= offset);
+    // the expression comes after `=` or `in`, or straight after the tag's
+    // name, but not after the `%}`
+    let mut is_expression = false;
+    let mut seen_name = false;
+    for token in tokens {
+        match token.kind() {
+            SyntaxKind::BlockEnd => return false,
+            SyntaxKind::Assign if tag.kind() == SyntaxKind::StmtAssign => is_expression = true,
+            SyntaxKind::Name if !seen_name => {
+                seen_name = true;
+                is_expression =
+                    !matches!(tag.kind(), SyntaxKind::StmtAssign | SyntaxKind::ForStart);
+            }
+            SyntaxKind::Name if token.text() == "in" && tag.kind() == SyntaxKind::ForStart => {
+                is_expression = true
+            }
+            _ => {}
+        }
+    }
+    is_expression
+}
+
+/// The variables that can be used at `offset`: the arguments of the macro
+/// or test it's in, the loop variables of the for loops it's in, and what's
+/// been set before it in the same scope
+pub fn get_local_names(syntax_tree: &SyntaxNode, offset: TextSize) -> Vec<CompletionItem> {
+    let mut items: Vec<CompletionItem> = Vec::new();
+    let mut push = |names: Vec<String>, detail: String| {
+        for name in names {
+            if items.iter().all(|item| item.label != name) {
+                items.push(CompletionItem {
+                    label: name,
+                    kind: Some(CompletionItemKind::VARIABLE),
+                    detail: Some(detail.clone()),
+                    ..Default::default()
+                });
+            }
+        }
+    };
+    for node in syntax_tree.descendants() {
+        if let Some(stmt) = StmtMacro::cast(node.clone()) {
+            if is_inside(&node, offset) {
+                let name = stmt.name().and_then(|name| name.name());
+                push(
+                    signature_names(stmt.signature()),
+                    format!("argument of {}", name.unwrap_or_default()),
+                );
+            }
+        } else if let Some(stmt) = StmtTest::cast(node.clone()) {
+            if is_inside(&node, offset) {
+                let name = stmt.name().and_then(|name| name.name());
+                push(
+                    signature_names(stmt.signature()),
+                    format!("argument of test {}", name.unwrap_or_default()),
+                );
+            }
+        } else if let Some(stmt) = StmtFor::cast(node.clone()) {
+            if is_in_loop_body(&stmt, offset) {
+                push(names(stmt.names()), "loop variable".to_string());
+                push(
+                    vec!["loop".to_string()],
+                    "the current iteration of the loop".to_string(),
+                );
+            }
+        } else if node.text_range().end() <= offset && is_in_scope(&node, offset) {
+            let assigned = match (StmtAssign::cast(node.clone()), StmtAssignBlock::cast(node)) {
+                (Some(assign), _) => assign.names(),
+                (_, Some(assign_block)) => assign_block.names(),
+                (None, None) => continue,
+            };
+            push(names(assigned), "variable".to_string());
+        }
+    }
+    items
+}
+
+fn names(names: Vec<ExprName>) -> Vec<String> {
+    names.iter().filter_map(|name| name.name()).collect()
+}
+
+fn signature_names(signature: Option<Signature>) -> Vec<String> {
+    let signature = match signature {
+        None => return Vec::new(),
+        Some(signature) => signature,
+    };
+    signature
+        .args()
+        .filter_map(|arg| arg.name()?.name())
+        .chain(
+            signature
+                .default_args()
+                .filter_map(|arg| arg.name()?.name()),
+        )
+        .collect()
+}
+
+/// whether `offset` is somewhere the loop variables of `stmt` can be used
+fn is_in_loop_body(stmt: &StmtFor, offset: TextSize) -> bool {
+    // not until the `%}`
+    let after_start = stmt.start().is_some_and(|start| {
+        start.syntax().text_range().end() <= offset
+            && start
+                .syntax()
+                .last_token()
+                .is_some_and(|last| last.kind() == SyntaxKind::BlockEnd)
+    });
+    after_start && is_inside(stmt.syntax(), offset)
+}
+
+/// whether `loop` can be used where `token` is
+pub fn is_in_loop(token: &SyntaxToken, offset: TextSize) -> bool {
+    token
+        .parent_ancestors()
+        .filter_map(StmtFor::cast)
+        .any(|stmt| is_in_loop_body(&stmt, offset))
+}
+
+/// The object whose attribute is being typed, as the chain of names that
+/// leads to it, e.g. `["modules", "datetime"]` in `{{ modules.datetime.`.
+/// `token` is the one that ends at or contains the cursor. It's `None` if
+/// the cursor isn't after a `.`, but the chain is empty if it's after one
+/// on something other than names, like a string.
+pub fn get_member_receiver(token: &SyntaxToken) -> Option<Vec<String>> {
+    let receiver = match token.kind() {
+        // nothing has been typed after the `.` yet
+        SyntaxKind::Dot => token.prev_sibling_or_token()?.into_node(),
+        SyntaxKind::Name => {
+            let subscript = token
+                .parent()
+                .filter(|parent| parent.kind() == SyntaxKind::Subscript)?;
+            subscript
+                .parent()
+                .filter(|get_attr| get_attr.kind() == SyntaxKind::ExprGetAttr)?
+                .first_child()
+        }
+        _ => return None,
+    };
+    Some(
+        receiver
+            .and_then(|receiver| attribute_chain(&receiver))
+            .unwrap_or_default(),
+    )
+}
+
+/// what comes after `|` or `is`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FilterOrTest {
+    Filter,
+    Test,
+}
+
+/// Whether the cursor is where the name of a filter or test goes, e.g.
+/// after `x |` or `x is not`. `token` is the one that ends at or contains
+/// the cursor.
+pub fn get_filter_or_test_position(token: &SyntaxToken) -> Option<FilterOrTest> {
+    let parent_kind = |token: &SyntaxToken| token.parent().map(|parent| parent.kind());
+    match token.kind() {
+        SyntaxKind::Pipe => Some(FilterOrTest::Filter),
+        // the name being typed, rather than one in the filter's arguments
+        SyntaxKind::Name => match parent_kind(token)? {
+            SyntaxKind::ExprFilterName => token
+                .parent()?
+                .first_token()
+                .filter(|first| first == token)
+                .map(|_| FilterOrTest::Filter),
+            SyntaxKind::ExprTest if !matches!(token.text(), "is" | "not") => {
+                Some(FilterOrTest::Test)
+            }
+            _ => None,
+        },
+        // `is` and `not` are only done with once there's a space after them
+        SyntaxKind::Whitespace => {
+            let previous = previous_significant_token(token)?;
+            match (previous.kind(), previous.text()) {
+                (SyntaxKind::Pipe, _) => Some(FilterOrTest::Filter),
+                (SyntaxKind::Name, "filter")
+                    if parent_kind(&previous) == Some(SyntaxKind::FilterBlockStart) =>
+                {
+                    Some(FilterOrTest::Filter)
+                }
+                (SyntaxKind::Name, "is") => Some(FilterOrTest::Test),
+                (SyntaxKind::Name, "not") => previous_significant_token(&previous)
+                    .filter(|is| is.kind() == SyntaxKind::Name && is.text() == "is")
+                    .map(|_| FilterOrTest::Test),
+                _ => None,
+            }
+        }
+        _ => None,
+    }
+}
+
+fn previous_significant_token(token: &SyntaxToken) -> Option<SyntaxToken> {
+    let mut previous = token.prev_token()?;
+    while previous.kind() == SyntaxKind::Whitespace {
+        previous = previous.prev_token()?;
+    }
+    Some(previous)
+}
+
+/// `a.b.c` as `["a", "b", "c"]`, if it's only names and attributes
+fn attribute_chain(expr: &SyntaxNode) -> Option<Vec<String>> {
+    match expr.kind() {
+        SyntaxKind::ExprName => Some(vec![ExprName::cast(expr.clone())?.name()?]),
+        SyntaxKind::ExprGetAttr => {
+            let mut chain = attribute_chain(&expr.first_child()?)?;
+            let attribute = expr
+                .children()
+                .find(|child| child.kind() == SyntaxKind::Subscript)?
+                .first_token()?;
+            chain.push(attribute.text().to_string());
+            Some(chain)
+        }
+        _ => None,
+    }
+}
+
+/// whether `offset` is inside a block, including right at the end of one
+/// that hasn't been closed yet
+fn is_inside(block: &SyntaxNode, offset: TextSize) -> bool {
+    let range = block.text_range();
+    let is_closed = block.last_child().is_some_and(|last| {
+        matches!(
+            last.kind(),
+            SyntaxKind::ForEnd
+                | SyntaxKind::IfEnd
+                | SyntaxKind::AssignBlockEnd
+                | SyntaxKind::CallBlockEnd
+                | SyntaxKind::FilterBlockEnd
+                | SyntaxKind::MacroBlockEnd
+                | SyntaxKind::MaterializationBlockEnd
+                | SyntaxKind::TestBlockEnd
+                | SyntaxKind::DocsBlockEnd
+                | SyntaxKind::SnapshotBlockEnd
+        )
+    });
+    range.start() < offset && (offset < range.end() || (offset == range.end() && !is_closed))
+}
+
+/// Whether what's set by `assignment` can be seen at `offset`. Loops, macros
+/// and the like have their own scope, but `if` doesn't.
+fn is_in_scope(assignment: &SyntaxNode, offset: TextSize) -> bool {
+    assignment
+        .ancestors()
+        .skip(1)
+        .filter(|ancestor| {
+            matches!(
+                ancestor.kind(),
+                SyntaxKind::StmtFor
+                    | SyntaxKind::StmtMacro
+                    | SyntaxKind::StmtTest
+                    | SyntaxKind::StmtMaterialization
+                    | SyntaxKind::StmtCallBlock
+                    | SyntaxKind::StmtFilterBlock
+            )
+        })
+        .all(|scope| is_inside(&scope, offset))
+}
+
+/// Given the token right before the cursor, finds the call whose arguments
+/// the cursor is in and the index of the argument it's at
+fn get_argument_position(left: &SyntaxToken) -> Option<(ExprCall, usize)> {
+    let mut token = left.clone();
+    while token.kind() == SyntaxKind::Whitespace {
+        token = token.prev_token()?;
+    }
+    if !matches!(token.kind(), SyntaxKind::LeftParen | SyntaxKind::Comma) {
+        return None;
+    }
+    let arguments = token
+        .parent()
+        .filter(|parent| parent.kind() == SyntaxKind::CallArguments)?;
+    let argument = arguments
+        .children_with_tokens()
+        .filter(|child| {
+            child.kind() == SyntaxKind::Comma
+                && child.text_range().end() <= token.text_range().end()
+        })
+        .count();
+    Some((ExprCall::cast(arguments.parent()?)?, argument))
+}
+
+impl DbtProject {
+    /// completions for the `argument`th argument of a call
+    fn get_function_completion(
+        &self,
+        call_node: &ExprCall,
+        argument: usize,
+    ) -> Vec<CompletionItem> {
+        match (call_node.callee_name().as_deref(), argument) {
+            (Some("ref"), 0) => self
+                .get_model_names()
+                .into_iter()
+                .map(|name| CompletionItem {
+                    label: name.clone(),
+                    insert_text: Some(format!("'{}'", &name)),
+                    kind: Some(CompletionItemKind::FILE),
+                    detail: Some("Model".to_string()),
+                    sort_text: Some(format!("'{}'", &name)),
+                    ..Default::default()
+                })
+                .collect(),
+            (Some("source"), 0) => self.get_source_completion(),
+            (Some("source"), 1) => match call_node
+                .static_args()
+                .next()
+                .and_then(|arg| arg.string_value())
+            {
+                None => vec![],
+                Some(source_name) => self.get_source_table_completion(&source_name),
+            },
+            _ => vec![],
+        }
+    }
+
+    fn get_macro_completion(&self) -> Vec<CompletionItem> {
+        let mut to_return: Vec<CompletionItem> = self
+            .get_macros()
+            .into_iter()
+            .filter_map(|mac| mac.get_completion_items(None))
+            .collect();
+
+        to_return.extend(
+            BUILTINS
+                .context
+                .iter()
+                .map(|builtin| builtin.get_completion_item(false)),
+        );
+
+        to_return.extend(self.packages.iter().flat_map(|project| {
+            let project_name = &project.spec.name;
+            project
+                .get_macros()
+                .into_iter()
+                .filter_map(|mac| mac.get_completion_items(Some(project_name)))
+                .collect::<Vec<_>>()
+        }));
+
+        to_return
+    }
+
+    pub fn get_completion_items(&self, path: PathBuf, position: Position) -> Vec<CompletionItem> {
+        let mut completion_items = Vec::new();
+
+        let file = match self.get_jinja_file(&path) {
+            None => return completion_items,
+            Some(file) => file,
+        };
+        let (syntax_tree, line_index) = (file.syntax(), file.line_index());
+        let offset = line_index.offset(position, self.position_encoding);
+        tracing::debug!(
+            message = "map from position to offset",
+            position = ?position,
+            offset = ?offset
+        );
+        if let Some(items) =
+            get_tag_completion(&syntax_tree, line_index, self.position_encoding, offset)
+        {
+            return items;
+        }
+        let token = syntax_tree.token_at_offset(offset);
+        tracing::debug!(message = "current token", token = ?token);
+        // in the middle of a token, what's before the cursor is part of it
+        let left = match token {
+            rowan::TokenAtOffset::None => return completion_items,
+            rowan::TokenAtOffset::Single(leaf) if leaf.kind() == SyntaxKind::StringLiteral => {
+                return completion_items
+            }
+            rowan::TokenAtOffset::Single(leaf) => leaf,
+            rowan::TokenAtOffset::Between(left, _right) => left,
+        };
+        if let Some(receiver) = get_member_receiver(&left) {
+            return self.get_member_completion(&receiver, is_in_loop(&left, offset));
+        }
+        if let Some(filter_or_test) = get_filter_or_test_position(&left) {
+            let builtins = match filter_or_test {
+                FilterOrTest::Filter => &BUILTINS.filters,
+                FilterOrTest::Test => &BUILTINS.tests,
+            };
+            return builtins
+                .iter()
+                .map(|builtin| builtin.get_filter_completion_item())
+                .collect();
+        }
+        if let Some((call_node, argument)) = get_argument_position(&left) {
+            completion_items.extend(self.get_function_completion(&call_node, argument));
+        }
+        if let Some((call_node, argument)) = get_config_argument(&left) {
+            completion_items.extend(self.get_config_completion(&call_node, &argument));
+        }
+        if left
+            .parent_ancestors()
+            .any(|ancestor| ancestor.kind() == SyntaxKind::Variable)
+            || is_tag_expression(&left, offset)
+        {
+            tracing::debug!(message = "looking for macros", macros = ?self.macros);
+            completion_items.extend(get_local_names(&syntax_tree, offset));
+            completion_items.extend(self.get_macro_completion());
+        }
+
+        completion_items
+    }
+
+    /// The macros of the package `receiver` names, or otherwise the
+    /// attributes and methods of the builtin object it is
+    fn get_member_completion(&self, receiver: &[String], in_loop: bool) -> Vec<CompletionItem> {
+        if let [namespace] = receiver {
+            let macros = self.resolve_macro_with(&self.spec.name, Some(namespace), |project| {
+                project
+                    .get_macros()
+                    .into_iter()
+                    .filter_map(|mac| mac.get_completion_items(None))
+                    .collect()
+            });
+            if !macros.is_empty() {
+                return macros;
+            }
+        }
+        if receiver.first().is_some_and(|first| first == "loop") && !in_loop {
+            return Vec::new();
+        }
+        let path: Vec<&str> = receiver.iter().map(String::as_str).collect();
+        BUILTINS
+            .resolve(&path)
+            .map(|object| {
+                object
+                    .members
+                    .iter()
+                    .map(|member| member.get_completion_item(true))
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+}
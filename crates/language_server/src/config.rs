@@ -0,0 +1,169 @@
+use dbt_jinja_parser::ast::{AstNode, CallStaticKwarg, ExprCall};
+use dbt_jinja_parser::parser::{Severity, SyntaxKind, SyntaxNode, SyntaxToken};
+use std::collections::HashSet;
+use tower_lsp::lsp_types::{CompletionItem, CompletionItemKind};
+
+use crate::diagnostics::ProjectError;
+use crate::entity::{Builtin, DbtProject, BUILTINS};
+use crate::utils::edit_distance;
+
+/// Where the cursor is in the arguments of `config()`
+pub(crate) enum ConfigArgument {
+    /// where the name of a config goes
+    Key,
+    /// the value of the config with this name
+    Value(String),
+}
+
+pub(crate) fn is_config_call(call_node: &ExprCall) -> bool {
+    call_node.callee_name().as_deref() == Some("config")
+}
+
+/// Given the token right before the cursor, finds the `config()` call whose
+/// arguments the cursor is in and whether it's at a config's name or value
+pub(crate) fn get_config_argument(left: &SyntaxToken) -> Option<(ExprCall, ConfigArgument)> {
+    let mut token = left.clone();
+    while token.kind() == SyntaxKind::Whitespace {
+        token = token.prev_token()?;
+    }
+    let parent = token.parent()?;
+    let (arguments, argument) = match (token.kind(), parent.kind()) {
+        (SyntaxKind::LeftParen | SyntaxKind::Comma, SyntaxKind::CallArguments) => {
+            (parent, ConfigArgument::Key)
+        }
+        // a name that's still being typed, so parsed as a positional argument
+        (SyntaxKind::Name, SyntaxKind::ExprName) if &token == left => {
+            let static_arg = parent
+                .parent()
+                .filter(|static_arg| static_arg.kind() == SyntaxKind::CallStaticArg)?;
+            (static_arg.parent()?, ConfigArgument::Key)
+        }
+        (SyntaxKind::Assign, SyntaxKind::CallStaticKwarg) => {
+            let name = CallStaticKwarg::cast(parent.clone())?.name()?;
+            (parent.parent()?, ConfigArgument::Value(name))
+        }
+        _ => return None,
+    };
+    let call_node = ExprCall::cast(arguments.parent()?)?;
+    if is_config_call(&call_node) {
+        Some((call_node, argument))
+    } else {
+        None
+    }
+}
+
+/// Warns about the arguments of `config()` that aren't configs dbt knows
+/// of, suggesting the closest one if it looks like a typo. Adapters and
+/// packages can read configs of their own, so it's only a warning.
+pub(crate) fn get_config_errors(syntax_tree: &SyntaxNode) -> Vec<ProjectError> {
+    syntax_tree
+        .descendants()
+        .filter_map(ExprCall::cast)
+        .filter(is_config_call)
+        .flat_map(|call_node| call_node.arguments())
+        .flat_map(|arguments| arguments.static_kwargs())
+        .filter_map(|kwarg| {
+            let name = kwarg.ident()?;
+            if BUILTINS.get_config(name.text()).is_some() {
+                return None;
+            }
+            let suggestion = BUILTINS
+                .configs
+                .iter()
+                .map(|config| (edit_distance(name.text(), &config.name), &config.name))
+                .min()
+                .filter(|(distance, _)| *distance <= name.text().len().max(3) / 3);
+            let message = match suggestion {
+                Some((_, config)) => format!(
+                    "'{}' isn't a config dbt knows of, did you mean '{}'?",
+                    name.text(),
+                    config
+                ),
+                None => format!("'{}' isn't a config dbt knows of", name.text()),
+            };
+            Some(ProjectError {
+                range: name.text_range(),
+                severity: Severity::Warning,
+                code: "unknown-config",
+                message,
+            })
+        })
+        .collect()
+}
+
+impl DbtProject {
+    /// the configs that aren't set yet in a `config()` call, or the values
+    /// of the one whose value the cursor is at
+    pub(crate) fn get_config_completion(
+        &self,
+        call_node: &ExprCall,
+        argument: &ConfigArgument,
+    ) -> Vec<CompletionItem> {
+        match argument {
+            ConfigArgument::Key => {
+                let set: HashSet<String> = call_node
+                    .arguments()
+                    .into_iter()
+                    .flat_map(|arguments| arguments.static_kwargs())
+                    .filter_map(|kwarg| kwarg.name())
+                    .collect();
+                BUILTINS
+                    .configs
+                    .iter()
+                    .filter(|config| !set.contains(&config.name))
+                    .map(|config| {
+                        config.get_config_completion_item(&self.get_config_values(config))
+                    })
+                    .collect()
+            }
+            ConfigArgument::Value(name) => match BUILTINS.get_config(name) {
+                None => vec![],
+                Some(config) => self
+                    .get_config_values(config)
+                    .into_iter()
+                    .enumerate()
+                    .map(|(index, value)| CompletionItem {
+                        label: value.trim_matches('\'').to_string(),
+                        insert_text: Some(value.clone()),
+                        kind: Some(CompletionItemKind::VALUE),
+                        detail: Some(format!("{} config", name)),
+                        // in the order the catalog gives them, before macros
+                        sort_text: Some(format!("0{:03}", index)),
+                        ..Default::default()
+                    })
+                    .collect(),
+            },
+        }
+    }
+
+    /// what `config` can be set to, which for `materialized` includes the
+    /// materializations of the project and its packages
+    fn get_config_values(&self, config: &Builtin) -> Vec<String> {
+        let mut values = config.values.clone();
+        if config.name == "materialized" {
+            let mut names = self.get_materialization_names();
+            names.extend(self.flat_map_packages(|project| project.get_materialization_names()));
+            names.sort();
+            for name in names {
+                let value = format!("'{}'", name);
+                if !values.contains(&value) {
+                    values.push(value);
+                }
+            }
+        }
+        values
+    }
+
+    fn get_materialization_names(&self) -> Vec<String> {
+        self.macros
+            .iter()
+            .flat_map(|macro_file| {
+                macro_file
+                    .materializations
+                    .iter()
+                    .filter_map(|materialization| materialization.name.clone())
+                    .collect::<Vec<_>>()
+            })
+            .collect()
+    }
+}
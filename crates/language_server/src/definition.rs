@@ -0,0 +1,246 @@
+use dbt_jinja_parser::ast::{AstNode, ExprCall};
+use rowan::{TextRange, TextSize};
+use std::path::{Path, PathBuf};
+use tower_lsp::lsp_types::{LocationLink, Position, Range, Url};
+
+use crate::entity::{get_ref_target, DbtProject};
+use crate::files::property_file::PropertyFile;
+use crate::line_index::LineIndex;
+
+/// Somewhere go-to-definition can jump to
+struct Target {
+    path: PathBuf,
+    /// all of the definition
+    range: Range,
+    /// the part of the definition to reveal, e.g. the name of a macro
+    selection_range: Range,
+}
+
+fn get_location_links(origin: Range, targets: Vec<Target>) -> Vec<LocationLink> {
+    targets
+        .into_iter()
+        .filter_map(|target| match Url::from_file_path(&target.path) {
+            Ok(target_uri) => Some(LocationLink {
+                origin_selection_range: Some(origin),
+                target_uri,
+                target_range: target.range,
+                target_selection_range: target.selection_range,
+            }),
+            Err(_) => {
+                tracing::error!(message = "couldn't convert path to uri", path = ?target.path);
+                None
+            }
+        })
+        .collect()
+}
+
+impl DbtProject {
+    fn get_model_declaration(
+        &self,
+        call_node: &ExprCall,
+        line_index: &LineIndex,
+    ) -> Vec<LocationLink> {
+        if call_node.callee_name().as_deref() != Some("ref") {
+            return vec![];
+        }
+        let (package_name, model_name, origin) = match get_ref_target(call_node) {
+            Some((package_name, model_name, model_arg)) => match model_arg.expr() {
+                None => return vec![],
+                Some(expr) => (package_name, model_name, expr.syntax().text_range()),
+            },
+            None => return vec![],
+        };
+        get_location_links(
+            line_index.range(origin, self.position_encoding),
+            self.resolve_ref(package_name.as_deref(), &model_name),
+        )
+    }
+
+    pub fn get_declaration(&self, path: PathBuf, position: Position) -> Vec<LocationLink> {
+        let mut locations = Vec::new();
+
+        let file = match self.get_jinja_file(&path) {
+            None => return locations,
+            Some(file) => file,
+        };
+        let (syntax_tree, line_index) = (file.syntax(), file.line_index());
+        let offset = line_index.offset(position, self.position_encoding);
+        tracing::debug!(message = "position to offset", ?position, ?offset);
+        // the cursor may be right between two tokens, e.g. just after the
+        // closing quote of `ref('model')`, in which case either could be meant
+        for leaf in syntax_tree.token_at_offset(offset) {
+            tracing::debug!(message = "token at offset", ?leaf);
+            if let Some(node) = leaf.parent_ancestors().find_map(ExprCall::cast) {
+                locations.extend(self.get_model_declaration(&node, line_index));
+                locations.extend(self.get_source_declaration(&node, offset, line_index));
+                locations.extend(self.get_macro_declaration(&node, offset, line_index));
+            }
+            if !locations.is_empty() {
+                break;
+            }
+        }
+
+        locations
+    }
+
+    /// Finds the models a `ref` points to.
+    fn resolve_ref(&self, package_name: Option<&str>, model_name: &str) -> Vec<Target> {
+        self.resolve_ref_with(package_name, |project| project.find_model(model_name))
+    }
+
+    /// this project's model called `model_name`, if any
+    fn find_model(&self, model_name: &str) -> Option<Target> {
+        self.models.iter().find_map(|model| {
+            if model.value().name == model_name {
+                let range = model.value().line_index.range(
+                    model.parsed_repr.syntax().text_range(),
+                    self.position_encoding,
+                );
+                Some(Target {
+                    path: model.key().clone(),
+                    range,
+                    selection_range: Range::new(range.start, range.start),
+                })
+            } else {
+                None
+            }
+        })
+    }
+
+    fn get_macro_declaration(
+        &self,
+        call_node: &ExprCall,
+        offset: TextSize,
+        line_index: &LineIndex,
+    ) -> Vec<LocationLink> {
+        let origin = match call_node.callee() {
+            None => return vec![],
+            Some(callee) => callee.syntax().text_range(),
+        };
+        // the cursor has to be on the macro's name rather than its arguments
+        if !origin.contains_inclusive(offset) {
+            return vec![];
+        }
+        let (namespace, macro_name) = match call_node.qualified_callee_name() {
+            None => return vec![],
+            Some(qualified_name) => qualified_name,
+        };
+        get_location_links(
+            line_index.range(origin, self.position_encoding),
+            self.resolve_macro(namespace.as_deref(), &macro_name),
+        )
+    }
+
+    /// Finds the macros a call could refer to.
+    fn resolve_macro(&self, namespace: Option<&str>, macro_name: &str) -> Vec<Target> {
+        self.resolve_macro_with(&self.spec.name, namespace, |project| {
+            project.find_macros(macro_name)
+        })
+    }
+
+    /// this project's macros called `macro_name`, ordered by path
+    fn find_macros(&self, macro_name: &str) -> Vec<Target> {
+        let mut macros: Vec<Target> = self
+            .macros
+            .iter()
+            .flat_map(|macro_file| {
+                macro_file
+                    .macros
+                    .iter()
+                    .filter(|mac| mac.name.as_deref() == Some(macro_name))
+                    .map(|mac| Target {
+                        path: macro_file.key().clone(),
+                        range: macro_file
+                            .line_index
+                            .range(mac.declaration, self.position_encoding),
+                        selection_range: macro_file
+                            .line_index
+                            .range(mac.declaration_selection, self.position_encoding),
+                    })
+                    .collect::<Vec<_>>()
+            })
+            .collect();
+        macros.sort_by(|a, b| a.path.cmp(&b.path));
+        macros
+    }
+
+    /// `source('source', 'table')` goes to the table's entry, unless the
+    /// cursor is on the source's name
+    fn get_source_declaration(
+        &self,
+        call_node: &ExprCall,
+        offset: TextSize,
+        line_index: &LineIndex,
+    ) -> Vec<LocationLink> {
+        if call_node.callee_name().as_deref() != Some("source") {
+            return vec![];
+        }
+        let args: Vec<_> = call_node
+            .static_args()
+            .map(|arg| Some((arg.string_value()?, arg.expr()?.syntax().text_range())))
+            .collect();
+        let (source_name, source_origin) = match args.first() {
+            Some(Some(source_arg)) => source_arg,
+            _ => return vec![],
+        };
+        let table_arg = match args.get(1) {
+            Some(Some(table_arg)) if !source_origin.contains_inclusive(offset) => table_arg,
+            _ => {
+                let targets = self.flat_map_sources(&|path, property_file, source| {
+                    if &source.name == source_name {
+                        Some(self.get_target(path, property_file, source.range, source.name_range))
+                    } else {
+                        None
+                    }
+                });
+                return get_location_links(
+                    line_index.range(*source_origin, self.position_encoding),
+                    targets,
+                );
+            }
+        };
+        let (table_name, table_origin) = table_arg;
+        let targets = self
+            .flat_map_sources(&|path, property_file, source| {
+                if &source.name != source_name {
+                    return None;
+                }
+                Some(
+                    source
+                        .tables
+                        .iter()
+                        .filter(|table| &table.name == table_name)
+                        .map(|table| {
+                            self.get_target(path, property_file, table.range, table.name_range)
+                        })
+                        .collect::<Vec<_>>(),
+                )
+            })
+            .into_iter()
+            .flatten()
+            .collect();
+        get_location_links(
+            line_index.range(*table_origin, self.position_encoding),
+            targets,
+        )
+    }
+
+    /// a target in a property file
+    fn get_target(
+        &self,
+        path: &Path,
+        property_file: &PropertyFile,
+        range: TextRange,
+        selection_range: TextRange,
+    ) -> Target {
+        Target {
+            path: path.to_path_buf(),
+            range: property_file
+                .line_index
+                .range(range, self.position_encoding),
+            selection_range: property_file
+                .line_index
+                .range(selection_range, self.position_encoding),
+        }
+    }
+}
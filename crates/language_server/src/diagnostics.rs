@@ -0,0 +1,96 @@
+use dbt_jinja_parser::parser::{Parse, ParseError, Severity};
+use rowan::TextRange;
+use tower_lsp::lsp_types::{
+    Diagnostic, DiagnosticRelatedInformation, DiagnosticSeverity, Location, NumberOrString, Url,
+};
+
+use crate::line_index::{LineIndex, PositionEncoding};
+
+const DIAGNOSTIC_SOURCE: &str = "dbt-jinja";
+/// for problems that only show up when looking at the rest of the project
+const PROJECT_DIAGNOSTIC_SOURCE: &str = "dbt";
+
+/// A problem with how a file uses the rest of the project, e.g. a `source()`
+/// of a table that isn't declared anywhere
+#[derive(Debug, Clone)]
+pub struct ProjectError {
+    pub range: TextRange,
+    pub severity: Severity,
+    pub code: &'static str,
+    pub message: String,
+}
+
+fn to_diagnostic_severity(severity: Severity) -> DiagnosticSeverity {
+    match severity {
+        Severity::Error => DiagnosticSeverity::ERROR,
+        Severity::Warning => DiagnosticSeverity::WARNING,
+    }
+}
+
+fn to_diagnostic(
+    error: &ParseError,
+    line_index: &LineIndex,
+    encoding: PositionEncoding,
+    uri: &Url,
+) -> Diagnostic {
+    let related_information = if error.related.is_empty() {
+        None
+    } else {
+        Some(
+            error
+                .related
+                .iter()
+                .map(|related| DiagnosticRelatedInformation {
+                    location: Location {
+                        uri: uri.clone(),
+                        range: line_index.range(related.range, encoding),
+                    },
+                    message: related.message.clone(),
+                })
+                .collect(),
+        )
+    };
+    Diagnostic {
+        range: line_index.range(error.range, encoding),
+        severity: Some(to_diagnostic_severity(error.severity)),
+        code: Some(NumberOrString::String(error.code.as_str().to_string())),
+        source: Some(DIAGNOSTIC_SOURCE.to_string()),
+        message: error.message.clone(),
+        related_information,
+        ..Default::default()
+    }
+}
+
+/// Converts the errors found while parsing a file into LSP diagnostics
+pub fn get_parse_diagnostics(
+    parse: &Parse,
+    line_index: &LineIndex,
+    encoding: PositionEncoding,
+    uri: &Url,
+) -> Vec<Diagnostic> {
+    parse
+        .get_errors()
+        .iter()
+        .map(|error| to_diagnostic(error, line_index, encoding, uri))
+        .collect()
+}
+
+/// Converts the errors found while checking a file against the rest of its
+/// project into LSP diagnostics
+pub fn get_project_diagnostics(
+    errors: &[ProjectError],
+    line_index: &LineIndex,
+    encoding: PositionEncoding,
+) -> Vec<Diagnostic> {
+    errors
+        .iter()
+        .map(|error| Diagnostic {
+            range: line_index.range(error.range, encoding),
+            severity: Some(to_diagnostic_severity(error.severity)),
+            code: Some(NumberOrString::String(error.code.to_string())),
+            source: Some(PROJECT_DIAGNOSTIC_SOURCE.to_string()),
+            message: error.message.clone(),
+            ..Default::default()
+        })
+        .collect()
+}
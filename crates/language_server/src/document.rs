@@ -0,0 +1,70 @@
+use rowan::TextRange;
+use tower_lsp::lsp_types::TextDocumentContentChangeEvent;
+
+use crate::line_index::{LineIndex, PositionEncoding};
+
+/// A single change to a document, relative to the text right before it
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DocumentEdit {
+    pub delete: TextRange,
+    pub insert: String,
+}
+
+/// A document opened in the editor, tracked by its line breaks rather than
+/// its text, which the project keeps
+#[derive(Debug)]
+pub struct TextDocument {
+    version: i32,
+    line_index: LineIndex,
+    encoding: PositionEncoding,
+}
+
+impl TextDocument {
+    pub fn new(text: &str, version: i32, encoding: PositionEncoding) -> Self {
+        Self {
+            line_index: LineIndex::new(text),
+            encoding,
+            version,
+        }
+    }
+
+    pub fn version(&self) -> i32 {
+        self.version
+    }
+
+    /// Applies the changes from a `textDocument/didChange` notification in
+    /// order, returning the equivalent edits in terms of offsets.
+    ///
+    /// Changes for a version older than (or the same as) the current one are
+    /// rejected, since they've either been applied already or were sent
+    /// before something we've already seen.
+    pub fn apply_changes(
+        &mut self,
+        version: i32,
+        changes: Vec<TextDocumentContentChangeEvent>,
+    ) -> Result<Vec<DocumentEdit>, String> {
+        if version <= self.version {
+            return Err(format!(
+                "received stale change for version={} when document is at version={}",
+                version, self.version
+            ));
+        }
+        let edits = changes
+            .into_iter()
+            .map(|change| {
+                let delete = match change.range {
+                    None => TextRange::up_to(self.line_index.text_len()),
+                    Some(range) => self.line_index.text_range(range, self.encoding),
+                };
+                let edit = DocumentEdit {
+                    delete,
+                    insert: change.text,
+                };
+                self.line_index.apply_edit(edit.delete, &edit.insert);
+                edit
+            })
+            .collect();
+        self.version = version;
+        Ok(edits)
+    }
+}
@@ -0,0 +1,406 @@
+use dbt_jinja_parser::ast::ArgumentPosition;
+use lazy_static::lazy_static;
+use serde::Deserialize;
+use tower_lsp::lsp_types::{
+    CompletionItem, CompletionItemKind, Documentation, InsertTextFormat, MarkupContent, MarkupKind,
+    SignatureInformation,
+};
+
+use super::macr::{build_args_snippet, build_signature_information};
+
+/// The functions, objects and variables of the dbt Jinja context, and the
+/// filters and tests that can be used with them, as listed in
+/// `meta/builtins.toml`
+#[derive(Debug, Deserialize)]
+pub struct BuiltinCatalog {
+    /// the dbt version the catalog was last checked against
+    pub version: String,
+    pub context: Vec<Builtin>,
+    /// `loop`, which is only there inside a for loop
+    #[serde(rename = "loop")]
+    pub loop_variable: Builtin,
+    pub filters: Vec<Builtin>,
+    pub tests: Vec<Builtin>,
+    /// what `config()` can set
+    pub configs: Vec<Builtin>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct Builtin {
+    pub name: String,
+    /// other names it goes by, like `d` for the `default` filter
+    #[serde(default)]
+    pub aliases: Vec<String>,
+    /// the first dbt version it's in
+    pub since: String,
+    pub summary: String,
+    pub docs: Option<String>,
+    /// the ways it can be called, if it can be
+    #[serde(default)]
+    pub signatures: Vec<BuiltinSignature>,
+    /// attributes and methods, for objects like `adapter`
+    #[serde(default)]
+    pub members: Vec<Builtin>,
+    /// for configs that take one of a few values, those values as written in
+    /// Jinja, e.g. `'table'`
+    #[serde(default)]
+    pub values: Vec<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct BuiltinSignature {
+    pub params: Vec<BuiltinParam>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct BuiltinParam {
+    pub name: String,
+    /// the default value as written, for optional parameters
+    pub default: Option<String>,
+    #[serde(default)]
+    pub doc: String,
+}
+
+lazy_static! {
+    pub static ref BUILTINS: BuiltinCatalog =
+        toml::from_str(include_str!("../../meta/builtins.toml"))
+            .expect("meta/builtins.toml should be a valid catalog");
+}
+
+impl BuiltinCatalog {
+    /// A member of the context, like `ref` or `target`, or with a
+    /// `namespace`, a member of one of its objects, like `adapter.dispatch`
+    pub fn get(&self, namespace: Option<&str>, name: &str) -> Option<&Builtin> {
+        match namespace {
+            None => find(&self.context, name),
+            Some(namespace) => self.resolve(&[namespace, name]),
+        }
+    }
+
+    /// What a chain of attributes leads to, e.g. `modules.datetime` for
+    /// `["modules", "datetime"]`. It can start with `loop`, which is up to
+    /// the caller to check is inside a for loop.
+    pub fn resolve(&self, path: &[&str]) -> Option<&Builtin> {
+        let (first, rest) = path.split_first()?;
+        let object = match *first {
+            "loop" => &self.loop_variable,
+            name => find(&self.context, name)?,
+        };
+        rest.iter()
+            .try_fold(object, |object, name| find(&object.members, name))
+    }
+
+    pub fn get_filter(&self, name: &str) -> Option<&Builtin> {
+        find(&self.filters, name)
+    }
+
+    pub fn get_test(&self, name: &str) -> Option<&Builtin> {
+        find(&self.tests, name)
+    }
+
+    pub fn get_config(&self, name: &str) -> Option<&Builtin> {
+        find(&self.configs, name)
+    }
+}
+
+fn find<'a>(builtins: &'a [Builtin], name: &str) -> Option<&'a Builtin> {
+    builtins
+        .iter()
+        .find(|builtin| builtin.name == name || builtin.aliases.iter().any(|alias| alias == name))
+}
+
+impl BuiltinSignature {
+    /// the parameters as names and as written with any default values
+    fn parameters(&self) -> Vec<(String, String)> {
+        self.params
+            .iter()
+            .map(|param| {
+                let written = match &param.default {
+                    Some(default) => format!("{}={}", param.name, default),
+                    None => param.name.clone(),
+                };
+                (param.name.clone(), written)
+            })
+            .collect()
+    }
+
+    /// whether `argument` can be passed to this signature
+    fn accepts(&self, argument: &ArgumentPosition) -> bool {
+        let is_variadic = |prefix: &str| self.params.iter().any(|p| p.name.starts_with(prefix));
+        match argument {
+            ArgumentPosition::Positional(index) => *index < self.params.len() || is_variadic("*"),
+            ArgumentPosition::Keyword(keyword) => {
+                self.params.iter().any(|param| &param.name == keyword) || is_variadic("**")
+            }
+        }
+    }
+}
+
+impl Builtin {
+    pub fn is_callable(&self) -> bool {
+        !self.signatures.is_empty()
+    }
+
+    /// e.g. `var(variable, default=none)`, or just the name if it can't be
+    /// called
+    fn signature_labels(&self) -> Vec<String> {
+        if self.signatures.is_empty() {
+            return vec![self.name.clone()];
+        }
+        self.signatures
+            .iter()
+            .map(|signature| {
+                let parameters: Vec<_> = signature
+                    .parameters()
+                    .into_iter()
+                    .map(|(_, written)| written)
+                    .collect();
+                format!("{}({})", self.name, parameters.join(", "))
+            })
+            .collect()
+    }
+
+    /// the summary, with the version it's been in dbt since and a link to
+    /// its documentation
+    fn documentation(&self) -> String {
+        let mut documentation = format!("{}\n\n*Since dbt {}*", self.summary, self.since);
+        if let Some(docs) = &self.docs {
+            documentation.push_str(&format!(" · [Documentation]({})", docs));
+        }
+        documentation
+    }
+
+    /// the parameters of the first signature that have to be given
+    fn required_params(&self) -> Vec<&str> {
+        self.signatures
+            .first()
+            .map(|signature| {
+                signature
+                    .params
+                    .iter()
+                    .filter(|param| param.default.is_none() && !param.name.starts_with('*'))
+                    .map(|param| param.name.as_str())
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    /// Completes a member of the context, or of an object if `is_member`.
+    /// Functions are completed with their required parameters as
+    /// placeholders.
+    pub fn get_completion_item(&self, is_member: bool) -> CompletionItem {
+        let (kind, insert_text, insert_text_format) = match self.signatures.first() {
+            Some(_) => {
+                let required = self.required_params();
+                (
+                    if is_member {
+                        CompletionItemKind::METHOD
+                    } else {
+                        CompletionItemKind::FUNCTION
+                    },
+                    build_args_snippet(self.name.clone(), &required),
+                    InsertTextFormat::SNIPPET,
+                )
+            }
+            None if !self.members.is_empty() => (
+                CompletionItemKind::MODULE,
+                self.name.clone(),
+                InsertTextFormat::PLAIN_TEXT,
+            ),
+            None => (
+                if is_member {
+                    CompletionItemKind::PROPERTY
+                } else {
+                    CompletionItemKind::VARIABLE
+                },
+                self.name.clone(),
+                InsertTextFormat::PLAIN_TEXT,
+            ),
+        };
+        CompletionItem {
+            label: self.name.clone(),
+            kind: Some(kind),
+            detail: self.signature_labels().first().cloned(),
+            documentation: Some(Documentation::MarkupContent(MarkupContent {
+                kind: MarkupKind::Markdown,
+                value: self.documentation(),
+            })),
+            insert_text: Some(insert_text),
+            insert_text_format: Some(insert_text_format),
+            ..Default::default()
+        }
+    }
+
+    /// Completes a filter or test, which only needs parentheses when it
+    /// has arguments that have to be given
+    pub fn get_filter_completion_item(&self) -> CompletionItem {
+        let mut item = self.get_completion_item(false);
+        item.kind = Some(CompletionItemKind::FUNCTION);
+        if self.required_params().is_empty() {
+            item.insert_text = Some(self.name.clone());
+            item.insert_text_format = Some(InsertTextFormat::PLAIN_TEXT);
+        }
+        item
+    }
+
+    /// Completes a config as a keyword argument, offering `values` to pick
+    /// from if there are any
+    pub fn get_config_completion_item(&self, values: &[String]) -> CompletionItem {
+        let insert_text = if values.is_empty() {
+            format!("{}=$0", self.name)
+        } else {
+            let choices: Vec<_> = values.iter().map(|value| escape_choice(value)).collect();
+            format!("{}=${{1|{}|}}", self.name, choices.join(","))
+        };
+        CompletionItem {
+            label: self.name.clone(),
+            kind: Some(CompletionItemKind::PROPERTY),
+            detail: Some("Config".to_string()),
+            documentation: Some(Documentation::MarkupContent(MarkupContent {
+                kind: MarkupKind::Markdown,
+                value: self.documentation(),
+            })),
+            // before the macros that can also go in the arguments
+            sort_text: Some(format!("0{}", self.name)),
+            insert_text: Some(insert_text),
+            insert_text_format: Some(InsertTextFormat::SNIPPET),
+            ..Default::default()
+        }
+    }
+
+    pub fn get_hover_markdown(&self) -> String {
+        let mut markdown = format!(
+            "```jinja\n{}\n```\n\n{}",
+            self.signature_labels().join("\n"),
+            self.documentation()
+        );
+        // the same parameter is often in several signatures
+        let mut documented: Vec<&str> = Vec::new();
+        for param in self.signatures.iter().flat_map(|s| &s.params) {
+            if !param.doc.is_empty() && !documented.contains(&param.name.as_str()) {
+                if documented.is_empty() {
+                    markdown.push('\n');
+                }
+                markdown.push_str(&format!("\n- `{}`: {}", param.name, param.doc));
+                documented.push(&param.name);
+            }
+        }
+        markdown
+    }
+
+    /// every signature, and the index of the first one `argument` fits
+    pub fn get_signature_information(
+        &self,
+        argument: &ArgumentPosition,
+    ) -> (Vec<SignatureInformation>, usize) {
+        let signatures = self
+            .signatures
+            .iter()
+            .map(|signature| {
+                let mut information = build_signature_information(
+                    &self.name,
+                    &signature.parameters(),
+                    self.documentation(),
+                    argument,
+                );
+                for (parameter, param) in information
+                    .parameters
+                    .iter_mut()
+                    .flatten()
+                    .zip(&signature.params)
+                {
+                    if !param.doc.is_empty() {
+                        parameter.documentation = Some(Documentation::String(param.doc.clone()));
+                    }
+                }
+                information
+            })
+            .collect();
+        let active = self
+            .signatures
+            .iter()
+            .position(|signature| signature.accepts(argument))
+            .unwrap_or(0);
+        (signatures, active)
+    }
+}
+
+/// escapes what would end an option of a snippet's `${1|a,b|}` choice
+fn escape_choice(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace(',', "\\,")
+        .replace('|', "\\|")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn param_names(builtin: &Builtin) -> Vec<Vec<&str>> {
+        builtin
+            .signatures
+            .iter()
+            .map(|signature| signature.params.iter().map(|p| p.name.as_str()).collect())
+            .collect()
+    }
+
+    #[test]
+    fn test_catalog_loads() {
+        // the catalog is only parsed on first use, so force it here
+        assert!(!BUILTINS.version.is_empty());
+        assert!(!BUILTINS.context.is_empty());
+        assert!(!BUILTINS.filters.is_empty());
+        assert!(!BUILTINS.tests.is_empty());
+        assert!(!BUILTINS.configs.is_empty());
+    }
+
+    #[test]
+    fn test_context_signatures() {
+        let reference = BUILTINS.get(None, "ref").unwrap();
+        assert_eq!(
+            param_names(reference),
+            vec![vec!["model_name"], vec!["package_name", "model_name"]]
+        );
+        let source = BUILTINS.get(None, "source").unwrap();
+        assert_eq!(param_names(source), vec![vec!["source_name", "table_name"]]);
+        let config = BUILTINS.get(None, "config").unwrap();
+        assert_eq!(param_names(config), vec![vec!["**kwargs"]]);
+        assert!(BUILTINS.get(Some("config"), "get").is_some());
+    }
+
+    #[test]
+    fn test_filters_and_tests() {
+        let default = BUILTINS.get_filter("d").unwrap();
+        assert_eq!(default.name, "default");
+        assert!(BUILTINS.get_filter("upper").is_some());
+        assert!(BUILTINS.get_test("defined").is_some());
+        assert_eq!(BUILTINS.get_test("==").unwrap().name, "eq");
+        assert!(BUILTINS.get_filter("not_a_filter").is_none());
+    }
+
+    #[test]
+    fn test_loop_members() {
+        let members: Vec<_> = BUILTINS
+            .loop_variable
+            .members
+            .iter()
+            .map(|member| member.name.as_str())
+            .collect();
+        for name in ["index", "index0", "first", "last", "length", "cycle"] {
+            assert!(members.contains(&name), "loop.{} is missing", name);
+        }
+        assert!(BUILTINS.resolve(&["loop", "cycle"]).unwrap().is_callable());
+        assert!(BUILTINS.resolve(&["modules", "datetime"]).is_some());
+    }
+
+    #[test]
+    fn test_config_values() {
+        let materialized = BUILTINS.get_config("materialized").unwrap();
+        for value in ["'view'", "'table'", "'incremental'", "'ephemeral'"] {
+            assert!(materialized.values.iter().any(|v| v == value));
+        }
+        assert!(BUILTINS.get_config("unique_key").unwrap().values.is_empty());
+        assert!(BUILTINS.get_config("materialised").is_none());
+    }
+}
@@ -0,0 +1,455 @@
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+
+use rowan::TextRange;
+
+/// A model or source in the project graph
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum GraphNode {
+    Model { package: String, name: String },
+    Source { source: String, table: String },
+}
+
+/// A dependency of a model on another node, from a `ref()` or `source()`
+#[derive(Debug, Clone)]
+pub struct GraphEdge {
+    pub node: GraphNode,
+    /// the call in the dependent model
+    pub range: TextRange,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Direction {
+    Upstream,
+    Downstream,
+}
+
+/// The dependencies between the models and sources of a project, including
+/// its installed packages
+#[derive(Debug, Default)]
+pub struct ProjectGraph {
+    /// the file declaring each node
+    paths: HashMap<GraphNode, PathBuf>,
+    upstream: HashMap<GraphNode, Vec<GraphEdge>>,
+    downstream: HashMap<GraphNode, Vec<GraphNode>>,
+    /// the result of `cycles`, kept until an edge changes
+    cycles: OnceLock<Vec<Vec<GraphNode>>>,
+}
+
+impl ProjectGraph {
+    pub fn add_node(&mut self, node: GraphNode, path: PathBuf) {
+        self.paths.insert(node, path);
+    }
+
+    /// drops the nodes declared in a file, keeping any edges to them
+    pub fn remove_nodes_at(&mut self, path: &Path) {
+        self.paths.retain(|_, declared_at| declared_at != path);
+    }
+
+    /// Replaces the dependencies of `dependent`, returning whether it now
+    /// depends on different nodes. The cached cycles are only thrown away if
+    /// it does, since most edits to a model just move its calls around.
+    pub fn set_dependencies(&mut self, dependent: GraphNode, edges: Vec<GraphEdge>) -> bool {
+        let old_edges = self.upstream.remove(&dependent).unwrap_or_default();
+        let mut old_targets: Vec<&GraphNode> = old_edges.iter().map(|edge| &edge.node).collect();
+        let mut new_targets: Vec<&GraphNode> = edges.iter().map(|edge| &edge.node).collect();
+        old_targets.sort();
+        new_targets.sort();
+        let changed = old_targets != new_targets;
+        if changed {
+            for target in old_targets {
+                if let Some(dependents) = self.downstream.get_mut(target) {
+                    if let Some(i) = dependents.iter().position(|node| node == &dependent) {
+                        dependents.swap_remove(i);
+                    }
+                    if dependents.is_empty() {
+                        self.downstream.remove(target);
+                    }
+                }
+            }
+            for target in new_targets {
+                self.downstream
+                    .entry(target.clone())
+                    .or_default()
+                    .push(dependent.clone());
+            }
+            self.cycles = OnceLock::new();
+        }
+        if !edges.is_empty() {
+            self.upstream.insert(dependent, edges);
+        }
+        changed
+    }
+
+    pub fn contains(&self, node: &GraphNode) -> bool {
+        self.paths.contains_key(node)
+    }
+
+    pub fn path(&self, node: &GraphNode) -> Option<&Path> {
+        self.paths.get(node).map(|path| path.as_path())
+    }
+
+    /// the direct dependencies of a node
+    pub fn dependencies(&self, node: &GraphNode) -> &[GraphEdge] {
+        self.upstream.get(node).map_or(&[], |edges| edges)
+    }
+
+    fn neighbours(&self, node: &GraphNode, direction: Direction) -> Vec<&GraphNode> {
+        match direction {
+            Direction::Upstream => self
+                .dependencies(node)
+                .iter()
+                .map(|edge| &edge.node)
+                .collect(),
+            Direction::Downstream => self
+                .downstream
+                .get(node)
+                .map_or(vec![], |nodes| nodes.iter().collect()),
+        }
+    }
+
+    /// Every node reachable from `node` in the given direction, along with
+    /// how many edges away it is. Nodes further than `max_depth` away are
+    /// left out.
+    pub fn traverse(
+        &self,
+        node: &GraphNode,
+        direction: Direction,
+        max_depth: Option<usize>,
+    ) -> Vec<(GraphNode, usize)> {
+        let mut seen: HashSet<&GraphNode> = HashSet::from([node]);
+        let mut queue = VecDeque::from([(node, 0)]);
+        let mut found = Vec::new();
+        while let Some((current, depth)) = queue.pop_front() {
+            if max_depth.is_some_and(|max_depth| depth >= max_depth) {
+                continue;
+            }
+            for neighbour in self.neighbours(current, direction) {
+                if seen.insert(neighbour) {
+                    found.push((neighbour.clone(), depth + 1));
+                    queue.push_back((neighbour, depth + 1));
+                }
+            }
+        }
+        found.sort_by(|(a, a_depth), (b, b_depth)| a_depth.cmp(b_depth).then(a.cmp(b)));
+        found
+    }
+
+    /// The shortest chain of dependencies from `from` to `to`, including both
+    fn find_path(&self, from: &GraphNode, to: &GraphNode) -> Option<Vec<GraphNode>> {
+        let mut previous: HashMap<&GraphNode, &GraphNode> = HashMap::new();
+        let mut queue = VecDeque::from([from]);
+        while let Some(current) = queue.pop_front() {
+            if current == to {
+                let mut path = vec![current.clone()];
+                let mut node = current;
+                while let Some(&prev) = previous.get(node) {
+                    path.push(prev.clone());
+                    node = prev;
+                }
+                path.reverse();
+                return Some(path);
+            }
+            for neighbour in self.neighbours(current, Direction::Upstream) {
+                if neighbour != from && !previous.contains_key(neighbour) {
+                    previous.insert(neighbour, current);
+                    queue.push_back(neighbour);
+                }
+            }
+        }
+        None
+    }
+
+    /// Groups of nodes that depend on each other, which dbt refuses to run.
+    /// A node that depends on itself is a group of one.
+    pub fn cycles(&self) -> &[Vec<GraphNode>] {
+        self.cycles.get_or_init(|| self.find_cycles())
+    }
+
+    fn find_cycles(&self) -> Vec<Vec<GraphNode>> {
+        // Tarjan's strongly connected components
+        struct State<'a> {
+            index: usize,
+            indices: HashMap<&'a GraphNode, usize>,
+            low_links: HashMap<&'a GraphNode, usize>,
+            stack: Vec<&'a GraphNode>,
+            on_stack: HashSet<&'a GraphNode>,
+            components: Vec<Vec<GraphNode>>,
+        }
+
+        fn visit<'a>(graph: &'a ProjectGraph, node: &'a GraphNode, state: &mut State<'a>) {
+            state.indices.insert(node, state.index);
+            state.low_links.insert(node, state.index);
+            state.index += 1;
+            state.stack.push(node);
+            state.on_stack.insert(node);
+
+            for neighbour in graph.neighbours(node, Direction::Upstream) {
+                if !state.indices.contains_key(neighbour) {
+                    visit(graph, neighbour, state);
+                    let low_link = state.low_links[neighbour].min(state.low_links[node]);
+                    state.low_links.insert(node, low_link);
+                } else if state.on_stack.contains(neighbour) {
+                    let low_link = state.indices[neighbour].min(state.low_links[node]);
+                    state.low_links.insert(node, low_link);
+                }
+            }
+
+            if state.low_links[node] == state.indices[node] {
+                let mut component = Vec::new();
+                while let Some(member) = state.stack.pop() {
+                    state.on_stack.remove(member);
+                    component.push(member.clone());
+                    if member == node {
+                        break;
+                    }
+                }
+                let is_cycle = component.len() > 1
+                    || graph
+                        .dependencies(node)
+                        .iter()
+                        .any(|edge| &edge.node == node);
+                if is_cycle {
+                    component.sort();
+                    state.components.push(component);
+                }
+            }
+        }
+
+        let mut state = State {
+            index: 0,
+            indices: HashMap::new(),
+            low_links: HashMap::new(),
+            stack: Vec::new(),
+            on_stack: HashSet::new(),
+            components: Vec::new(),
+        };
+        let mut nodes: Vec<&GraphNode> = self.upstream.keys().collect();
+        nodes.sort();
+        for node in nodes {
+            if !state.indices.contains_key(node) {
+                visit(self, node, &mut state);
+            }
+        }
+        state.components
+    }
+
+    /// For each dependency of `node` that's part of a cycle, the call that
+    /// creates it and the cycle itself, starting and ending with `node`
+    pub fn get_cycles_through(&self, node: &GraphNode) -> Vec<(TextRange, Vec<GraphNode>)> {
+        let component = match self
+            .cycles()
+            .iter()
+            .find(|component| component.contains(node))
+        {
+            None => return vec![],
+            Some(component) => component,
+        };
+        self.dependencies(node)
+            .iter()
+            .filter(|edge| component.contains(&edge.node))
+            .filter_map(|edge| {
+                let mut cycle = vec![node.clone()];
+                cycle.extend(self.find_path(&edge.node, node)?);
+                Some((edge.range, cycle))
+            })
+            .collect()
+    }
+}
+
+impl std::fmt::Display for GraphNode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            GraphNode::Model { name, .. } => write!(f, "{}", name),
+            GraphNode::Source { source, table } => write!(f, "{}.{}", source, table),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn model(name: &str) -> GraphNode {
+        GraphNode::Model {
+            package: "jaffle_shop".to_string(),
+            name: name.to_string(),
+        }
+    }
+
+    fn edge(node: GraphNode, start: u32) -> GraphEdge {
+        GraphEdge {
+            node,
+            range: TextRange::new(start.into(), (start + 1).into()),
+        }
+    }
+
+    /// a graph where each model depends on the ones listed after it
+    fn graph(dependencies: &[(&str, &[&str])]) -> ProjectGraph {
+        let mut graph = ProjectGraph::default();
+        for (name, _) in dependencies {
+            graph.add_node(model(name), PathBuf::from(format!("models/{}.sql", name)));
+        }
+        for (name, upstream) in dependencies {
+            let edges = upstream
+                .iter()
+                .enumerate()
+                .map(|(i, upstream)| edge(model(upstream), i as u32 * 10))
+                .collect();
+            graph.set_dependencies(model(name), edges);
+        }
+        graph
+    }
+
+    #[test]
+    fn test_traverse_upstream() {
+        let graph = graph(&[
+            ("orders", &["stg_orders", "stg_payments"]),
+            ("stg_orders", &["raw_orders"]),
+            ("stg_payments", &["raw_orders"]),
+            ("raw_orders", &[]),
+        ]);
+        assert_eq!(
+            graph.traverse(&model("orders"), Direction::Upstream, None),
+            vec![
+                (model("stg_orders"), 1),
+                (model("stg_payments"), 1),
+                (model("raw_orders"), 2),
+            ]
+        );
+        assert_eq!(
+            graph.traverse(&model("raw_orders"), Direction::Upstream, None),
+            vec![]
+        );
+    }
+
+    #[test]
+    fn test_traverse_downstream() {
+        let graph = graph(&[
+            ("orders", &["stg_orders", "stg_payments"]),
+            ("stg_orders", &["raw_orders"]),
+            ("stg_payments", &["raw_orders"]),
+            ("raw_orders", &[]),
+        ]);
+        assert_eq!(
+            graph.traverse(&model("raw_orders"), Direction::Downstream, None),
+            vec![
+                (model("stg_orders"), 1),
+                (model("stg_payments"), 1),
+                (model("orders"), 2),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_traverse_depth_limit() {
+        let graph = graph(&[("a", &["b"]), ("b", &["c"]), ("c", &["d"]), ("d", &[])]);
+        assert_eq!(
+            graph.traverse(&model("a"), Direction::Upstream, Some(2)),
+            vec![(model("b"), 1), (model("c"), 2)]
+        );
+        assert_eq!(
+            graph.traverse(&model("d"), Direction::Downstream, Some(1)),
+            vec![(model("c"), 1)]
+        );
+        assert_eq!(
+            graph.traverse(&model("a"), Direction::Upstream, Some(0)),
+            vec![]
+        );
+    }
+
+    #[test]
+    fn test_traverse_cycle() {
+        let graph = graph(&[("a", &["b"]), ("b", &["a"])]);
+        assert_eq!(
+            graph.traverse(&model("a"), Direction::Upstream, None),
+            vec![(model("b"), 1)]
+        );
+    }
+
+    #[test]
+    fn test_cycles() {
+        let graph = graph(&[
+            ("a", &["b"]),
+            ("b", &["c"]),
+            ("c", &["a"]),
+            ("d", &["d"]),
+            ("e", &["a"]),
+        ]);
+        assert_eq!(
+            graph.cycles(),
+            &[vec![model("a"), model("b"), model("c")], vec![model("d")]]
+        );
+        assert!(graph.get_cycles_through(&model("e")).is_empty());
+    }
+
+    #[test]
+    fn test_get_cycles_through() {
+        let graph = graph(&[("a", &["x", "b"]), ("b", &["c"]), ("c", &["a"]), ("x", &[])]);
+        let range = TextRange::new(10.into(), 11.into());
+        assert_eq!(
+            graph.get_cycles_through(&model("a")),
+            vec![(range, vec![model("a"), model("b"), model("c"), model("a")])]
+        );
+        assert_eq!(
+            graph.get_cycles_through(&model("c")),
+            vec![(
+                TextRange::new(0.into(), 1.into()),
+                vec![model("c"), model("a"), model("b"), model("c")]
+            )]
+        );
+    }
+
+    #[test]
+    fn test_get_cycles_through_self() {
+        let graph = graph(&[("a", &["a"])]);
+        assert_eq!(
+            graph.get_cycles_through(&model("a")),
+            vec![(
+                TextRange::new(0.into(), 1.into()),
+                vec![model("a"), model("a")]
+            )]
+        );
+    }
+
+    #[test]
+    fn test_set_dependencies() {
+        let mut graph = graph(&[("a", &["b"]), ("b", &[])]);
+        assert!(graph.cycles().is_empty());
+
+        assert!(graph.set_dependencies(model("b"), vec![edge(model("a"), 5)]));
+        assert_eq!(graph.cycles(), &[vec![model("a"), model("b")]]);
+        assert_eq!(
+            graph.traverse(&model("a"), Direction::Downstream, None),
+            vec![(model("b"), 1)]
+        );
+
+        // moving the call keeps the cycle but reports the new range
+        assert!(!graph.set_dependencies(model("b"), vec![edge(model("a"), 20)]));
+        assert_eq!(
+            graph.get_cycles_through(&model("b")),
+            vec![(
+                TextRange::new(20.into(), 21.into()),
+                vec![model("b"), model("a"), model("b")]
+            )]
+        );
+
+        assert!(graph.set_dependencies(model("b"), vec![]));
+        assert!(graph.cycles().is_empty());
+        assert!(graph.dependencies(&model("b")).is_empty());
+        assert_eq!(
+            graph.traverse(&model("a"), Direction::Downstream, None),
+            vec![]
+        );
+    }
+
+    #[test]
+    fn test_remove_nodes_at() {
+        let mut graph = graph(&[("a", &["b"]), ("b", &[])]);
+        graph.remove_nodes_at(Path::new("models/b.sql"));
+        assert!(graph.contains(&model("a")));
+        assert!(!graph.contains(&model("b")));
+        assert_eq!(graph.dependencies(&model("a")).len(), 1);
+    }
+}
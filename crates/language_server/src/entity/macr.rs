@@ -1,101 +1,70 @@
-use std::path::PathBuf;
-
-use lazy_static::lazy_static;
+use dbt_jinja_parser::ast::ArgumentPosition;
 use rowan::TextRange;
-use tower_lsp::lsp_types::{CompletionItem, CompletionItemKind, InsertTextFormat, Location, Range};
+use tower_lsp::lsp_types::{
+    CompletionItem, CompletionItemKind, Documentation, InsertTextFormat, MarkupContent, MarkupKind,
+    ParameterInformation, ParameterLabel, SignatureInformation,
+};
 
-fn build_args_snippet(func_name: String, arg_names: &Vec<&str>) -> String {
+pub(super) fn build_args_snippet(func_name: String, arg_names: &Vec<&str>) -> String {
     let mut insert_text = func_name + "(";
     let mut i = 0;
     for arg in arg_names {
         if i > 0 {
             insert_text.push_str(", ");
         }
-        i = i + 1;
+        i += 1;
         insert_text.push_str(&format!("${{{}:{}}}", i, arg));
     }
     insert_text.push(')');
     insert_text
 }
 
-#[derive(Debug, Clone)]
-pub struct BuiltinMacro {
-    pub name: &'static str,
-    pub args: Option<Vec<&'static str>>,
-    pub docs_url: &'static str,
-}
-
-lazy_static! {
-    pub static ref BUILTIN_MACROS: [BuiltinMacro; 9] = [
-        BuiltinMacro {
-            name: "source",
-            args: Some(vec!["source_name", "table_name"]),
-            docs_url: "https://docs.getdbt.com/reference/dbt-jinja-functions/source",
-        },
-        BuiltinMacro {
-            name: "env_var",
-            args: Some(vec!["ENV_VAR"]),
-            docs_url: "https://docs.getdbt.com/reference/dbt-jinja-functions/env_var",
-        },
-        BuiltinMacro {
-            name: "var",
-            args: Some(vec!["variable"]),
-            docs_url: "https://docs.getdbt.com/reference/dbt-jinja-functions/var",
-        },
-        BuiltinMacro {
-            name: "fromjson",
-            args: Some(vec!["json_str"]),
-            docs_url: "https://docs.getdbt.com/reference/dbt-jinja-functions/fromjson",
-        },
-        BuiltinMacro {
-            name: "fromyaml",
-            args: Some(vec!["yaml_str"]),
-            docs_url: "https://docs.getdbt.com/reference/dbt-jinja-functions/fromyaml",
-        },
-        BuiltinMacro {
-            name: "tojson",
-            args: Some(vec!["object"]),
-            docs_url: "https://docs.getdbt.com/reference/dbt-jinja-functions/tojson",
-        },
-        BuiltinMacro {
-            name: "toyaml",
-            args: Some(vec!["object"]),
-            docs_url: "https://docs.getdbt.com/reference/dbt-jinja-functions/toyaml",
-        },
-        // special cases we should handle separately
-        BuiltinMacro {
-            name: "ref",
-            args: None,
-            docs_url: "https://docs.getdbt.com/reference/dbt-jinja-functions/ref",
-        },
-        BuiltinMacro {
-            name: "config",
-            args: None,
-            docs_url: "https://docs.getdbt.com/reference/dbt-jinja-functions/config",
-        },
-    ];
-}
-
-impl BuiltinMacro {
-    pub fn get_completion_items(&self) -> CompletionItem {
-        let insert_text = {
-            match &self.args {
-                Some(args) => build_args_snippet(self.name.to_string(), args),
-                None => self.name.to_string(),
-            }
-        };
-        CompletionItem {
-            label: self.name.to_string(),
-            kind: Some(CompletionItemKind::FUNCTION),
-            insert_text_format: Some(InsertTextFormat::SNIPPET),
-            insert_text: Some(insert_text),
-            detail: Some("Builtin macro".to_string()),
-            ..Default::default()
+/// Describes a call to `name` with the given parameters, as names and as
+/// written, marking the one `argument` is for. Parameters are highlighted
+/// by their position in the label.
+pub(super) fn build_signature_information(
+    name: &str,
+    parameters: &[(String, String)],
+    documentation: String,
+    argument: &ArgumentPosition,
+) -> SignatureInformation {
+    let mut label = format!("{}(", name);
+    let mut parameter_information = Vec::new();
+    for (i, (_, parameter)) in parameters.iter().enumerate() {
+        if i > 0 {
+            label.push_str(", ");
         }
+        // offsets are in UTF-16 code units, like the rest of the label
+        let start = label.encode_utf16().count() as u32;
+        label.push_str(parameter);
+        let end = label.encode_utf16().count() as u32;
+        parameter_information.push(ParameterInformation {
+            label: ParameterLabel::LabelOffsets([start, end]),
+            documentation: None,
+        });
+    }
+    label.push(')');
+    // pointing past the last parameter highlights none of them
+    let active_parameter = match argument {
+        ArgumentPosition::Positional(index) => *index,
+        ArgumentPosition::Keyword(keyword) => parameters
+            .iter()
+            .position(|(name, _)| name == keyword)
+            .unwrap_or(parameters.len()),
+    };
+    SignatureInformation {
+        label,
+        documentation: Some(Documentation::MarkupContent(MarkupContent {
+            kind: MarkupKind::Markdown,
+            value: documentation,
+        })),
+        parameters: Some(parameter_information),
+        active_parameter: Some(active_parameter as u32),
     }
 }
 
 #[derive(Debug, Clone)]
+#[allow(dead_code)]
 pub struct Macro {
     pub declaration_selection: TextRange,
     pub declaration: TextRange,
@@ -105,6 +74,47 @@ pub struct Macro {
 }
 
 impl Macro {
+    /// e.g. `star(from, except=[])`
+    pub fn signature(&self) -> Option<String> {
+        let name = self.name.as_ref()?;
+        let parameters: Vec<String> = self
+            .parameters()
+            .into_iter()
+            .map(|(_, parameter)| parameter)
+            .collect();
+        Some(format!("{}({})", name, parameters.join(", ")))
+    }
+
+    /// the macro's arguments, as names and as written with any default
+    /// values
+    fn parameters(&self) -> Vec<(String, String)> {
+        self.args
+            .iter()
+            .flatten()
+            .map(|arg| (arg.clone(), arg.clone()))
+            .chain(self.default_args.iter().filter_map(|(arg, default)| {
+                let arg = arg.as_ref()?;
+                Some(match default {
+                    Some(default) => (arg.clone(), format!("{}={}", arg, default.trim())),
+                    None => (arg.clone(), arg.clone()),
+                })
+            }))
+            .collect()
+    }
+
+    pub fn get_signature_information(
+        &self,
+        documentation: String,
+        argument: &ArgumentPosition,
+    ) -> Option<SignatureInformation> {
+        Some(build_signature_information(
+            self.name.as_ref()?,
+            &self.parameters(),
+            documentation,
+            argument,
+        ))
+    }
+
     pub fn get_completion_items(self, package_name: Option<&str>) -> Option<CompletionItem> {
         self.name.map(|macro_name| {
             let identifier = {
@@ -118,7 +128,7 @@ impl Macro {
                 &(self
                     .args
                     .iter()
-                    .map(|arg| arg.as_ref().map_or("", |s| &s))
+                    .map(|arg| arg.as_ref().map_or("", |s| s))
                     .collect()),
             );
             CompletionItem {
@@ -1,7 +1,13 @@
+mod builtins;
+mod graph;
 mod macr;
 mod model;
 mod project;
+mod symbol_index;
 
+pub use builtins::*;
+pub use graph::*;
 pub use macr::*;
 pub use model::*;
 pub use project::*;
+pub use symbol_index::*;
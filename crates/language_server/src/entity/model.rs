@@ -1,28 +1,152 @@
-use std::path::PathBuf;
-
 use rowan::TextRange;
-use tower_lsp::lsp_types::{CompletionItem, CompletionItemKind, InsertTextFormat, Location, Range};
+use tower_lsp::lsp_types::Range;
+
+use crate::files::yaml::YamlNode;
 
 /**
  * Inter-file metadata
  */
 #[derive(Debug)]
+#[allow(dead_code)]
 pub struct Materialization {
     pub name: Option<String>,
     pub adapter: String,
 }
 
-#[derive(Debug)]
-pub struct Source {
-    name: String,
-    definition: Location,
+/// A `ref()` or `source()` call in a model, which it depends on
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Dependency {
+    Ref {
+        package: Option<String>,
+        name: String,
+        /// the whole call
+        range: TextRange,
+    },
+    Source {
+        source: String,
+        table: String,
+        range: TextRange,
+    },
 }
 
-#[derive(Debug)]
+impl Dependency {
+    /// the whole call
+    pub fn range(&self) -> TextRange {
+        match self {
+            Dependency::Ref { range, .. } | Dependency::Source { range, .. } => *range,
+        }
+    }
+}
+
+/// A generic test applied to a resource or column in a property file, e.g.
+/// `unique` or `accepted_values: {values: [...]}`
+#[derive(Debug, Clone)]
+#[allow(dead_code)]
 pub struct GenericTest {
-    name: String,
-    args: Vec<String>,
-    definition: Location,
+    /// possibly namespaced, e.g. `dbt_utils.expression_is_true`
+    pub name: String,
+    pub name_range: TextRange,
+    pub args: Vec<String>,
+    pub range: TextRange,
+}
+
+#[derive(Debug, Clone)]
+#[allow(dead_code)]
+pub struct Column {
+    pub name: String,
+    pub name_range: TextRange,
+    pub range: TextRange,
+    pub description: Option<String>,
+    pub data_type: Option<String>,
+    pub tests: Vec<GenericTest>,
+    pub config: Option<YamlNode>,
+    pub meta: Option<YamlNode>,
+}
+
+/// The properties of a model, seed or snapshot
+#[derive(Debug, Clone)]
+#[allow(dead_code)]
+pub struct NodeProperties {
+    pub name: String,
+    pub name_range: TextRange,
+    pub range: TextRange,
+    pub description: Option<String>,
+    pub columns: Vec<Column>,
+    pub tests: Vec<GenericTest>,
+    pub config: Option<YamlNode>,
+    pub meta: Option<YamlNode>,
+}
+
+#[derive(Debug, Clone)]
+#[allow(dead_code)]
+pub struct Source {
+    pub name: String,
+    pub name_range: TextRange,
+    pub range: TextRange,
+    pub description: Option<String>,
+    pub database: Option<String>,
+    pub schema: Option<String>,
+    pub loaded_at_field: Option<String>,
+    /// the `freshness` block, which may be `null` to turn checks off
+    pub freshness: Option<YamlNode>,
+    pub tables: Vec<SourceTable>,
+    pub config: Option<YamlNode>,
+    pub meta: Option<YamlNode>,
+}
+
+#[derive(Debug, Clone)]
+#[allow(dead_code)]
+pub struct SourceTable {
+    pub name: String,
+    pub name_range: TextRange,
+    pub range: TextRange,
+    /// the table's name in the database, if it isn't `name`
+    pub identifier: Option<String>,
+    pub description: Option<String>,
+    /// inherited from the source unless the table overrides it
+    pub loaded_at_field: Option<String>,
+    /// inherited from the source unless the table overrides it
+    pub freshness: Option<YamlNode>,
+    pub columns: Vec<Column>,
+    pub tests: Vec<GenericTest>,
+    pub config: Option<YamlNode>,
+    pub meta: Option<YamlNode>,
+}
+
+#[derive(Debug, Clone)]
+#[allow(dead_code)]
+pub struct MacroProperties {
+    pub name: String,
+    pub name_range: TextRange,
+    pub range: TextRange,
+    pub description: Option<String>,
+    pub arguments: Vec<MacroArgument>,
+    pub meta: Option<YamlNode>,
+}
+
+#[derive(Debug, Clone)]
+#[allow(dead_code)]
+pub struct MacroArgument {
+    pub name: String,
+    pub name_range: TextRange,
+    pub data_type: Option<String>,
+    pub description: Option<String>,
+}
+
+#[derive(Debug, Clone)]
+#[allow(dead_code)]
+pub struct Exposure {
+    pub name: String,
+    pub name_range: TextRange,
+    pub range: TextRange,
+    pub exposure_type: Option<String>,
+    pub label: Option<String>,
+    pub description: Option<String>,
+    pub url: Option<String>,
+    /// e.g. `ref('model')` or `source('source', 'table')`, as written
+    pub depends_on: Vec<(String, TextRange)>,
+    pub config: Option<YamlNode>,
+    pub meta: Option<YamlNode>,
 }
 
 /**
@@ -30,6 +154,7 @@ pub struct GenericTest {
  */
 
 #[derive(Debug)]
+#[allow(dead_code)]
 pub struct Object {
     name: String,
     declaration: Range,
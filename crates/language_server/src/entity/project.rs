@@ -1,63 +1,284 @@
-use dashmap::DashMap;
-use dbt_jinja_parser::parser::SyntaxKind;
+use dashmap::mapref::one::Ref;
+use dashmap::{DashMap, DashSet};
+use dbt_jinja_parser::ast::{CallStaticArg, ExprCall};
+use dbt_jinja_parser::parser::SyntaxNode;
 use derivative::Derivative;
-use futures::future::{self, try_join_all};
-use futures::{TryFuture, TryFutureExt};
-use std::fs::FileType;
+use futures::future::try_join_all;
+use std::collections::HashSet;
 use std::path::{Path, PathBuf};
+use std::sync::{RwLock, RwLockReadGuard};
 use tower_lsp::lsp_types::{
-    CompletionItem, CompletionItemKind, InsertTextFormat, LocationLink, Position,
+    Diagnostic, DocumentSymbol, FoldingRange, Position, Range, SelectionRange, SemanticToken, Url,
 };
 use walkdir::WalkDir;
 
-use crate::entity::{Macro, BUILTIN_MACROS};
+use crate::config::get_config_errors;
+use crate::diagnostics::{get_parse_diagnostics, get_project_diagnostics};
+use crate::document::DocumentEdit;
+use crate::entity::{Macro, MacroProperties, ProjectGraph, SymbolIndex};
 use crate::files::macro_file::MacroFile;
 use crate::files::model_file::ModelFile;
 use crate::files::project_yml::DbtProjectSpec;
-use crate::utils::{get_child_of_kind, is_sql_file, SyntaxNode, TraverseOrder};
+use crate::files::property_file::PropertyFile;
+use crate::folding_ranges::get_folding_ranges;
+use crate::line_index::{LineIndex, PositionEncoding};
+use crate::selection_ranges::get_selection_range;
+use crate::semantic_tokens::get_semantic_tokens;
+use crate::symbols::get_document_symbols;
+use crate::utils::{is_csv_file, is_markdown_file, is_sql_file, is_yaml_file, read_file};
 
 #[derive(Derivative)]
 #[derivative(Debug)]
 pub struct DbtProject {
-    root_path: PathBuf,
-    spec: DbtProjectSpec,
+    pub(crate) root_path: PathBuf,
+    pub(crate) spec: DbtProjectSpec,
     /// Concurrent hashmap from model file path to the in-memory
     /// parsed information for the model.
     pub models: DashMap<PathBuf, ModelFile>,
     /// Concurrent hashmap from macro file path to the in-memory
     /// parsed information for the macros.
     pub macros: DashMap<PathBuf, MacroFile>,
+    /// Concurrent hashmap from `.yml` property file path to the properties
+    /// it declares.
+    pub properties: DashMap<PathBuf, PropertyFile>,
     /// Installed packages
     pub packages: DashMap<PathBuf, DbtProject>,
+    /// the `.csv` files in the seed paths, which are seeds whether or not a
+    /// property file declares them
+    pub(crate) seed_files: DashSet<PathBuf>,
+    /// the models, macros and so on in each file, for finding them by name
+    pub(crate) symbols: SymbolIndex,
+    /// the models and sources of the project and its installed packages,
+    /// kept up to date as files change
+    pub(crate) graph: RwLock<ProjectGraph>,
+    /// What LSP positions are measured in, as negotiated with the client
+    pub(crate) position_encoding: PositionEncoding,
 }
 
-fn get_sql_files_in_paths(root_path: &Path, paths: &[String]) -> Vec<PathBuf> {
+fn get_files_in_paths(
+    root_path: &Path,
+    paths: &[String],
+    is_wanted: fn(&Path) -> bool,
+) -> Vec<PathBuf> {
     paths
         .iter()
-        .map(|path| {
+        .flat_map(|path| {
             let sub_root = root_path.join(path);
-            WalkDir::new(sub_root).into_iter().filter_map(|e| match e {
-                Err(_) => None,
-                Ok(e) => {
-                    if is_sql_file(e.path()) {
-                        Some(e.path().to_path_buf())
-                    } else {
-                        None
+            WalkDir::new(sub_root)
+                .into_iter()
+                .filter_map(move |e| match e {
+                    Err(_) => None,
+                    Ok(e) => {
+                        if is_wanted(e.path()) {
+                            Some(e.path().to_path_buf())
+                        } else {
+                            None
+                        }
                     }
-                }
-            })
+                })
         })
-        .flatten()
         .collect()
 }
 
+fn get_sql_files_in_paths(root_path: &Path, paths: &[String]) -> Vec<PathBuf> {
+    get_files_in_paths(root_path, paths, is_sql_file)
+}
+
+/// A model or macro file borrowed from a project
+pub(crate) enum JinjaFile<'a> {
+    Model(Ref<'a, PathBuf, ModelFile>),
+    Macro(Ref<'a, PathBuf, MacroFile>),
+}
+
+impl JinjaFile<'_> {
+    pub(crate) fn syntax(&self) -> SyntaxNode {
+        match self {
+            JinjaFile::Model(model_file) => model_file.parsed_repr.syntax(),
+            JinjaFile::Macro(macro_file) => macro_file.parsed_repr.syntax(),
+        }
+    }
+
+    pub(crate) fn line_index(&self) -> &LineIndex {
+        match self {
+            JinjaFile::Model(model_file) => &model_file.line_index,
+            JinjaFile::Macro(macro_file) => &macro_file.line_index,
+        }
+    }
+}
+
+/// The package and model a `ref()` names, along with the argument naming
+/// the model
+pub(crate) fn get_ref_target(
+    call_node: &ExprCall,
+) -> Option<(Option<String>, String, CallStaticArg)> {
+    let args: Vec<_> = call_node.static_args().collect();
+    match args.as_slice() {
+        [model_arg] => Some((None, model_arg.string_value()?, model_arg.clone())),
+        [package_arg, model_arg] => Some((
+            Some(package_arg.string_value()?),
+            model_arg.string_value()?,
+            model_arg.clone(),
+        )),
+        _ => None,
+    }
+}
+
+/// The models and macros of a project and its installed packages, for
+/// working out which package a name refers to
+pub(crate) struct ProjectNames {
+    /// the root project followed by installed packages, ordered by path
+    packages: Vec<String>,
+    pub(crate) models: HashSet<(String, String)>,
+    pub(crate) macros: HashSet<(String, String)>,
+}
+
+impl ProjectNames {
+    pub(crate) fn new(projects: &[&DbtProject]) -> Self {
+        let mut names = ProjectNames {
+            packages: Vec::new(),
+            models: HashSet::new(),
+            macros: HashSet::new(),
+        };
+        for project in projects {
+            let package = &project.spec.name;
+            names.packages.push(package.clone());
+            for model in project.models.iter() {
+                names.models.insert((package.clone(), model.name.clone()));
+            }
+            for macro_file in project.macros.iter() {
+                for name in macro_file.macros.iter().filter_map(|mac| mac.name.clone()) {
+                    names.macros.insert((package.clone(), name));
+                }
+            }
+        }
+        names
+    }
+
+    /// the package of the model a `ref` in `caller` points to
+    pub(crate) fn resolve_model(
+        &self,
+        caller: &str,
+        package: Option<&str>,
+        name: &str,
+    ) -> Option<String> {
+        let packages: Vec<&str> = self.packages.iter().map(String::as_str).collect();
+        resolve_ref_in(&packages, caller, package, |i| {
+            let package = packages[i].to_string();
+            if self.models.contains(&(package.clone(), name.to_string())) {
+                Some(package)
+            } else {
+                None
+            }
+        })
+        .into_iter()
+        .next()
+    }
+
+    /// the package of the macro a call in `caller` refers to
+    pub(crate) fn resolve_macro(
+        &self,
+        caller: &str,
+        namespace: Option<&str>,
+        name: &str,
+    ) -> Option<String> {
+        let packages: Vec<&str> = self.packages.iter().map(String::as_str).collect();
+        resolve_macro_in(&packages, caller, namespace, |i| {
+            let package = packages[i].to_string();
+            if self.macros.contains(&(package.clone(), name.to_string())) {
+                vec![package]
+            } else {
+                vec![]
+            }
+        })
+        .into_iter()
+        .next()
+    }
+}
+
+/// Resolves a `ref` used in the package `caller` the way dbt does.
+/// `ref('package', 'model')` only looks in the given package, while
+/// `ref('model')` looks in the caller's package, then the root project,
+/// then the other installed packages. dbt refuses to pick between other
+/// packages that define the same model, so all of them are returned.
+///
+/// `packages` starts with the root project, and `find` looks for the model
+/// in the package at an index of it.
+pub(crate) fn resolve_ref_in<T>(
+    packages: &[&str],
+    caller: &str,
+    package: Option<&str>,
+    find: impl Fn(usize) -> Option<T>,
+) -> Vec<T> {
+    let indices = |name: &str| {
+        packages
+            .iter()
+            .enumerate()
+            .filter(|(_, package)| **package == name)
+            .map(|(i, _)| i)
+            .collect::<Vec<_>>()
+    };
+    match package {
+        Some(package) => indices(package).into_iter().filter_map(&find).collect(),
+        None => {
+            let root = packages.first().copied().unwrap_or_default();
+            for preferred in [caller, root] {
+                if let Some(found) = indices(preferred).into_iter().find_map(&find) {
+                    return vec![found];
+                }
+            }
+            (1..packages.len())
+                .filter(|i| packages[*i] != caller)
+                .filter_map(find)
+                .collect()
+        }
+    }
+}
+
+/// Resolves a macro called in the package `caller` the way dbt does. A
+/// namespaced call like `dbt_utils.star()` only looks in that package, while
+/// a name without one prefers the root project, then the caller's package,
+/// then the other installed packages in order. Builtins come after all of
+/// them, but aren't defined in any package.
+///
+/// `packages` starts with the root project, and `find` looks for the macro
+/// in the package at an index of it. Only what the first package to define
+/// it has is returned.
+pub(crate) fn resolve_macro_in<T>(
+    packages: &[&str],
+    caller: &str,
+    namespace: Option<&str>,
+    find: impl Fn(usize) -> Vec<T>,
+) -> Vec<T> {
+    let indices: Vec<usize> = match namespace {
+        Some(namespace) => (0..packages.len())
+            .filter(|i| packages[*i] == namespace)
+            .collect(),
+        None => {
+            // the caller's package moves ahead of the others, but not the root
+            let mut indices: Vec<usize> = (0..packages.len()).collect();
+            if let Some(others) = indices.get_mut(1..) {
+                others.sort_by_key(|i| packages[*i] != caller);
+            }
+            indices
+        }
+    };
+    indices
+        .into_iter()
+        .map(find)
+        .find(|found| !found.is_empty())
+        .unwrap_or_default()
+}
+
 impl DbtProject {
     /// searches for a single project at the root path (since dbt sucks at
     /// disambiguating multiple projects)
-    pub async fn find_single_project(root_path: &Path) -> Result<Self, String> {
+    pub async fn find_single_project(
+        root_path: &Path,
+        position_encoding: PositionEncoding,
+    ) -> Result<Self, String> {
         let entry = root_path.join("dbt_project.yml");
         if entry.exists() {
-            match DbtProject::from_root(entry.as_path()).await {
+            match DbtProject::from_root(entry.as_path(), position_encoding).await {
                 Ok(project) => Ok(project),
                 Err(msg) => Err(msg),
             }
@@ -66,7 +287,10 @@ impl DbtProject {
         }
     }
 
-    async fn parse_package(project_path: &Path) -> Result<Self, String> {
+    async fn parse_package(
+        project_path: &Path,
+        position_encoding: PositionEncoding,
+    ) -> Result<Self, String> {
         let spec = DbtProjectSpec::from_file_path(project_path).await?;
         let root_path = match project_path.parent() {
             None => return Err("unexpected filesystem state".to_string()),
@@ -88,10 +312,7 @@ impl DbtProject {
                 Err(e) => return Err(format!("failed to parse models: {}", e)),
             };
 
-            found_model_paths
-                .into_iter()
-                .zip(parsed_models.into_iter())
-                .collect()
+            found_model_paths.into_iter().zip(parsed_models).collect()
         };
 
         tracing::debug!("parsing macros");
@@ -109,84 +330,165 @@ impl DbtProject {
                 Err(e) => return Err(format!("failed to parse macros: {}", e)),
             };
 
-            found_macro_paths
+            found_macro_paths.into_iter().zip(parsed_macros).collect()
+        };
+
+        tracing::debug!("parsing property files");
+        let properties = {
+            let found_property_paths =
+                get_files_in_paths(&root_path, &spec.property_paths(), is_yaml_file);
+
+            let parsed_properties = match try_join_all(
+                found_property_paths
+                    .iter()
+                    .map(|property_path| PropertyFile::from_file_path(property_path)),
+            )
+            .await
+            {
+                Ok(properties) => properties,
+                Err(e) => return Err(format!("failed to parse property files: {}", e)),
+            };
+
+            found_property_paths
                 .into_iter()
-                .zip(parsed_macros.into_iter())
+                .zip(parsed_properties)
                 .collect()
         };
 
-        Ok(Self {
+        let seed_files = get_files_in_paths(&root_path, &spec.seed_paths, is_csv_file)
+            .into_iter()
+            .collect();
+        // only docs and snapshot blocks are wanted from these, so they're
+        // indexed without being kept
+        let text_paths: Vec<PathBuf> =
+            get_files_in_paths(&root_path, &spec.property_paths(), is_markdown_file)
+                .into_iter()
+                .chain(get_sql_files_in_paths(&root_path, &spec.snapshot_paths))
+                .collect();
+
+        let project = Self {
             root_path,
             spec,
             models,
             macros,
+            properties,
             packages: DashMap::new(),
-        })
+            seed_files,
+            symbols: SymbolIndex::default(),
+            graph: RwLock::default(),
+            position_encoding,
+        };
+        let paths: Vec<PathBuf> = project
+            .models
+            .iter()
+            .map(|model| model.key().clone())
+            .chain(
+                project
+                    .macros
+                    .iter()
+                    .map(|macro_file| macro_file.key().clone()),
+            )
+            .chain(
+                project
+                    .properties
+                    .iter()
+                    .map(|property_file| property_file.key().clone()),
+            )
+            .collect();
+        for path in paths {
+            project.index_file(&path);
+        }
+        for path in text_paths {
+            match read_file(&path).await {
+                Ok(contents) => project.index_text_file(&path, Some(&contents)),
+                Err(e) => tracing::warn!(message = "failed to read file", ?path, error = ?e),
+            }
+        }
+        project.index_seeds();
+        Ok(project)
     }
 
     // TODO: better errors
-    async fn from_root(project_path: &Path) -> Result<Self, String> {
-        let mut project = Self::parse_package(project_path).await?;
-
-        project.packages = {
-            let mut packages = vec![];
-            let install_path = Path::new(&project.spec.packages_install_path);
-            let entries = match install_path.read_dir() {
-                Ok(entries) => entries,
-                Err(e) => return Err(format!("failed to get installed packages: {}", e)),
-            };
-            for entry in entries {
-                match entry {
-                    Ok(entry) => match entry.file_type() {
-                        Ok(file_type) => {
-                            if file_type.is_dir() {
-                                let possible_package = entry.path().join("dbt_project.yml");
-                                tracing::debug!(?possible_package);
-                                if possible_package.exists() {
-                                    match DbtProject::parse_package(&possible_package).await {
-                                        Ok(package) => {
-                                            packages.push((entry.path().to_owned(), package))
-                                        }
-                                        Err(e) => {
-                                            tracing::error!(
-                                                message = "failed to parse package",
-                                                path = ?possible_package,
-                                                error = ?e
-                                            );
-                                        }
+    async fn from_root(
+        project_path: &Path,
+        position_encoding: PositionEncoding,
+    ) -> Result<Self, String> {
+        let mut project = Self::parse_package(project_path, position_encoding).await?;
+        let install_path = project.root_path.join(&project.spec.packages_install_path);
+        project.packages = Self::parse_packages(&install_path, position_encoding).await?;
+        project.rebuild_graph();
+        Ok(project)
+    }
+
+    /// the packages installed at `install_path`, by the directory they're in
+    async fn parse_packages(
+        install_path: &Path,
+        position_encoding: PositionEncoding,
+    ) -> Result<DashMap<PathBuf, DbtProject>, String> {
+        let mut packages = vec![];
+        let entries = match install_path.read_dir() {
+            Ok(entries) => entries,
+            // nothing has been installed yet
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                tracing::debug!(message = "no installed packages", ?install_path);
+                return Ok(DashMap::new());
+            }
+            Err(e) => return Err(format!("failed to get installed packages: {}", e)),
+        };
+        for entry in entries {
+            match entry {
+                Ok(entry) => match entry.file_type() {
+                    Ok(file_type) => {
+                        if file_type.is_dir() {
+                            let possible_package = entry.path().join("dbt_project.yml");
+                            tracing::debug!(?possible_package);
+                            if possible_package.exists() {
+                                match DbtProject::parse_package(
+                                    &possible_package,
+                                    position_encoding,
+                                )
+                                .await
+                                {
+                                    Ok(package) => {
+                                        packages.push((entry.path().to_owned(), package))
+                                    }
+                                    Err(e) => {
+                                        tracing::error!(
+                                            message = "failed to parse package",
+                                            path = ?possible_package,
+                                            error = ?e
+                                        );
                                     }
-                                } else {
-                                    tracing::warn!(
-                                        message = "couldn't find dbt_project.yml",
-                                        ?entry
-                                    );
                                 }
                             } else {
-                                tracing::debug!(
-                                    message = "found non-directory in packages install",
-                                    ?entry,
-                                    ?file_type
-                                );
+                                tracing::warn!(message = "couldn't find dbt_project.yml", ?entry);
                             }
+                        } else {
+                            tracing::debug!(
+                                message = "found non-directory in packages install",
+                                ?entry,
+                                ?file_type
+                            );
                         }
-                        Err(e) => {
-                            tracing::error!(message = "unable to read file type for entry", ?entry, error = ?e);
-                        }
-                    },
-                    Err(ref e) => {
-                        tracing::warn!(message = "failed to get entry after readdir", ?entry, error = ?e);
                     }
+                    Err(e) => {
+                        tracing::error!(message = "unable to read file type for entry", ?entry, error = ?e);
+                    }
+                },
+                Err(ref e) => {
+                    tracing::warn!(message = "failed to get entry after readdir", ?entry, error = ?e);
                 }
             }
-            packages.into_iter().collect()
-        };
-
-        Ok(project)
+        }
+        Ok(packages.into_iter().collect())
     }
 
-    pub fn on_file_open(&self, path: &Path, file_contents: &str) -> Result<(), String> {
-        if self.is_file_model(&path) {
-            match ModelFile::from_file(&path, &file_contents) {
+    /// Handles a file being opened with `file_contents`. Like
+    /// [`DbtProject::on_file_change`] and [`DbtProject::on_file_close`], it
+    /// returns whether the diagnostics of other files may have changed.
+    pub fn on_file_open(&self, path: &Path, file_contents: &str) -> Result<bool, String> {
+        let result = if self.is_file_model(path) {
+            match ModelFile::from_file(path, file_contents) {
                 Ok(model) => {
                     self.models.insert(path.to_path_buf(), model);
                     Ok(())
@@ -196,8 +498,8 @@ impl DbtProject {
                     path, e
                 )),
             }
-        } else if self.is_file_macro(&path) {
-            match MacroFile::from_file(&file_contents) {
+        } else if self.is_file_macro(path) {
+            match MacroFile::from_file(file_contents) {
                 Ok(macro_file) => {
                     self.macros.insert(path.to_path_buf(), macro_file);
                     Ok(())
@@ -207,45 +509,83 @@ impl DbtProject {
                     path, e
                 )),
             }
+        } else if self.is_file_properties(path) {
+            match PropertyFile::from_file(file_contents) {
+                Ok(property_file) => {
+                    self.properties.insert(path.to_path_buf(), property_file);
+                    Ok(())
+                }
+                Err(e) => Err(format!(
+                    "couldn't parse property file with path={:?} due to {:?}",
+                    path, e
+                )),
+            }
+        } else if self.is_file_seed(path) {
+            self.seed_files.insert(path.to_path_buf());
+            Ok(())
         } else {
+            self.index_text_file(path, Some(file_contents));
             Ok(())
-        }
+        };
+        self.index_file(path);
+        let changed = self.update_graph(path);
+        result.map(|()| changed)
     }
 
-    pub fn on_file_change(&self, path: &Path, file_contents: &str) -> Result<(), String> {
-        if self.is_file_model(&path) {
+    pub fn on_file_change(&self, path: &Path, edits: &[DocumentEdit]) -> Result<bool, String> {
+        let result = if self.is_file_model(path) {
             match self.models.get_mut(&path.to_path_buf()) {
                 None => Err(format!(
                     "couldn't find entry for model file with path={:?}",
                     path
                 )),
                 Some(mut m) => {
-                    m.refresh(file_contents);
+                    for edit in edits {
+                        m.apply_edit(edit.delete, &edit.insert);
+                    }
                     Ok(())
                 }
             }
-        } else if self.is_file_macro(&path) {
+        } else if self.is_file_macro(path) {
             match self.macros.get_mut(&path.to_path_buf()) {
                 None => Err(format!(
                     "couldn't find entry for macro file with path={:?}",
                     path
                 )),
                 Some(mut m) => {
-                    m.refresh(file_contents);
+                    for edit in edits {
+                        m.apply_edit(edit.delete, &edit.insert);
+                    }
+                    Ok(())
+                }
+            }
+        } else if self.is_file_properties(path) {
+            match self.properties.get_mut(&path.to_path_buf()) {
+                None => Err(format!(
+                    "couldn't find entry for property file with path={:?}",
+                    path
+                )),
+                Some(mut p) => {
+                    for edit in edits {
+                        p.apply_edit(edit.delete, &edit.insert);
+                    }
                     Ok(())
                 }
             }
         } else {
             Ok(())
-        }
+        };
+        self.index_file(path);
+        let changed = self.update_graph(path);
+        result.map(|()| changed)
     }
 
     pub fn on_file_close(
         &self,
         path: PathBuf,
         file_contents: &Option<String>,
-    ) -> Result<(), String> {
-        if self.is_file_model(&path) {
+    ) -> Result<bool, String> {
+        let result = if self.is_file_model(&path) {
             match file_contents {
                 None => {
                     self.models.remove(&path);
@@ -266,7 +606,7 @@ impl DbtProject {
             match file_contents {
                 None => {
                     self.macros.remove(&path);
-                    return Ok(());
+                    Ok(())
                 }
                 Some(contents) => match self.macros.get_mut(&path) {
                     None => Err(format!(
@@ -279,265 +619,293 @@ impl DbtProject {
                     }
                 },
             }
+        } else if self.is_file_properties(&path) {
+            match file_contents {
+                None => {
+                    self.properties.remove(&path);
+                    Ok(())
+                }
+                Some(contents) => match self.properties.get_mut(&path) {
+                    None => Err(format!(
+                        "couldn't find entry for property file with path={:?}",
+                        path
+                    )),
+                    Some(mut p) => {
+                        p.refresh(contents);
+                        Ok(())
+                    }
+                },
+            }
+        } else if self.is_file_seed(&path) {
+            if file_contents.is_none() {
+                self.seed_files.remove(&path);
+            }
+            Ok(())
         } else {
+            self.index_text_file(&path, file_contents.as_deref());
             Ok(())
-        }
+        };
+        self.index_file(&path);
+        let changed = self.update_graph(&path);
+        result.map(|()| changed)
     }
 
-    fn get_function_completion(&self, call_node: &SyntaxNode) -> Vec<CompletionItem> {
-        match call_node
-            .children()
-            .find(|child| child.kind() == SyntaxKind::ExprName)
-        {
-            None => vec![],
-            Some(name_node) => match name_node.last_child_or_token().unwrap() {
-                rowan::NodeOrToken::Node(_) => unreachable!(),
-                rowan::NodeOrToken::Token(token) => {
-                    if token.text() == "ref" {
-                        self.get_model_names()
-                            .into_iter()
-                            .map(|name| CompletionItem {
-                                label: name.clone(),
-                                insert_text: Some(format!("'{}'", &name)),
-                                kind: Some(CompletionItemKind::FILE),
-                                detail: Some("Model".to_string()),
-                                sort_text: Some(format!("'{}'", &name)),
-                                ..Default::default()
-                            })
-                            .collect()
-                    } else {
-                        vec![]
-                    }
-                }
-            },
-        }
+    /// The foldable blocks and comments of a model or macro file
+    pub fn get_folding_ranges(&self, path: &Path) -> Option<Vec<FoldingRange>> {
+        let file = self.get_jinja_file(path)?;
+        let (syntax_tree, line_index) = (file.syntax(), file.line_index());
+        Some(get_folding_ranges(
+            &syntax_tree,
+            line_index,
+            self.position_encoding,
+        ))
     }
 
-    fn get_macro_completion(&self) -> Vec<CompletionItem> {
-        let mut to_return: Vec<CompletionItem> = self
-            .get_macros()
-            .into_iter()
-            .filter_map(|mac| mac.get_completion_items(None))
-            .collect();
-
-        to_return.extend(BUILTIN_MACROS.iter().map(|m| m.get_completion_items()));
-
-        to_return.extend(self.packages.iter().flat_map(|project| {
-            let project_name = &project.spec.name;
-            project
-                .get_macros()
-                .into_iter()
-                .filter_map(|mac| mac.get_completion_items(Some(&project_name)))
-                .collect::<Vec<_>>()
-        }));
+    /// How the selection at each position in a model or macro file expands
+    pub fn get_selection_ranges(
+        &self,
+        path: &Path,
+        positions: &[Position],
+    ) -> Option<Vec<SelectionRange>> {
+        let file = self.get_jinja_file(path)?;
+        let (syntax_tree, line_index) = (file.syntax(), file.line_index());
+        Some(
+            positions
+                .iter()
+                .map(|position| {
+                    let offset = line_index.offset(*position, self.position_encoding);
+                    get_selection_range(&syntax_tree, line_index, self.position_encoding, offset)
+                })
+                .collect(),
+        )
+    }
 
-        to_return
+    /// The semantic tokens of a model or macro file, optionally only those
+    /// in `range`
+    pub fn get_semantic_tokens(
+        &self,
+        path: &Path,
+        range: Option<Range>,
+    ) -> Option<Vec<SemanticToken>> {
+        let file = self.get_jinja_file(path)?;
+        let (syntax_tree, line_index) = (file.syntax(), file.line_index());
+        let range = range.map(|range| line_index.text_range(range, self.position_encoding));
+        let packages: HashSet<String> = self.with_projects(|projects| {
+            projects
+                .iter()
+                .map(|project| project.spec.name.clone())
+                .collect()
+        });
+        Some(get_semantic_tokens(
+            &syntax_tree,
+            line_index,
+            self.position_encoding,
+            range,
+            &packages,
+        ))
     }
 
-    pub fn get_completion_items(&self, path: PathBuf, position: Position) -> Vec<CompletionItem> {
-        let mut completion_items = Vec::new();
+    /// The outline of a model or macro file, or `None` if the file isn't
+    /// one of this project's
+    pub fn get_document_symbols(&self, path: &Path) -> Option<Vec<DocumentSymbol>> {
+        let file = self.get_jinja_file(path)?;
+        let (syntax_tree, line_index) = (file.syntax(), file.line_index());
+        Some(get_document_symbols(
+            &syntax_tree,
+            line_index,
+            self.position_encoding,
+        ))
+    }
 
-        let (offset, syntax_tree) = {
-            if self.is_file_model(&path) {
-                match self.models.get(&path) {
-                    None => {
-                        tracing::error!(
-                            message = "couldn't find model corresponding to path",
-                            path = ?path
-                        );
-                        return completion_items;
-                    }
-                    Some(model_file) => (
-                        model_file.position_finder.get_offset(position),
-                        model_file.parsed_repr.syntax(),
-                    ),
-                }
-            } else if self.is_file_macro(&path) {
-                match self.macros.get(&path) {
-                    None => {
-                        tracing::error!(message = "couldn't find macro corresponding to path", path = ?path);
-                        return completion_items;
-                    }
-                    Some(macro_file) => (
-                        macro_file.position_finder.get_offset(position),
-                        macro_file.parsed_repr.syntax(),
-                    ),
-                }
-            } else {
-                return completion_items;
-            }
-        };
-        tracing::debug!(
-            message = "map from position to offset",
-            position = ?position,
-            offset = ?offset
-        );
-        let token = syntax_tree.token_at_offset(offset.into());
-        tracing::debug!(message = "current token", token = ?token);
-        match token {
-            rowan::TokenAtOffset::None => (),
-            rowan::TokenAtOffset::Single(leaf) => {}
-            rowan::TokenAtOffset::Between(left, right) => {
-                if left.kind() == SyntaxKind::LeftParen {
-                    let call_node = left
-                        .ancestors()
-                        .find(|ancestor| ancestor.kind() == SyntaxKind::ExprCall);
-                    match call_node {
-                        None => (),
-                        Some(node) => completion_items.extend(self.get_function_completion(&node)),
-                    }
-                }
-                if left
-                    .ancestors()
-                    .find(|ancestor| match ancestor.kind() {
-                        SyntaxKind::Variable => true,
-                        _ => false,
-                    })
-                    .is_some()
-                {
-                    tracing::debug!(message = "looking for macros", macros = ?self.macros);
-                    completion_items.extend(self.get_macro_completion());
-                }
-            }
+    /// Returns the diagnostics for the file at the given path, or
+    /// `None` if the file isn't tracked by this project
+    pub fn get_diagnostics(&self, path: &Path, uri: &Url) -> Option<Vec<Diagnostic>> {
+        if self.is_file_model(path) {
+            // built before looking up the model, since it needs every model
+            let cycle_errors = self.get_cycle_errors(path);
+            self.models.get(path).map(|model_file| {
+                let mut diagnostics = get_parse_diagnostics(
+                    &model_file.parsed_repr,
+                    &model_file.line_index,
+                    self.position_encoding,
+                    uri,
+                );
+                diagnostics.extend(get_project_diagnostics(
+                    &self.get_source_errors(&model_file.parsed_repr.syntax()),
+                    &model_file.line_index,
+                    self.position_encoding,
+                ));
+                diagnostics.extend(get_project_diagnostics(
+                    &get_config_errors(&model_file.parsed_repr.syntax()),
+                    &model_file.line_index,
+                    self.position_encoding,
+                ));
+                diagnostics.extend(get_project_diagnostics(
+                    &cycle_errors,
+                    &model_file.line_index,
+                    self.position_encoding,
+                ));
+                diagnostics
+            })
+        } else if self.is_file_macro(path) {
+            self.macros.get(path).map(|macro_file| {
+                let mut diagnostics = get_parse_diagnostics(
+                    &macro_file.parsed_repr,
+                    &macro_file.line_index,
+                    self.position_encoding,
+                    uri,
+                );
+                diagnostics.extend(get_project_diagnostics(
+                    &self.get_source_errors(&macro_file.parsed_repr.syntax()),
+                    &macro_file.line_index,
+                    self.position_encoding,
+                ));
+                diagnostics.extend(get_project_diagnostics(
+                    &get_config_errors(&macro_file.parsed_repr.syntax()),
+                    &macro_file.line_index,
+                    self.position_encoding,
+                ));
+                diagnostics
+            })
+        } else {
+            None
         }
+    }
 
-        completion_items
-    }
-
-    fn get_model_declaration(&self, call_node: &SyntaxNode) -> Vec<LocationLink> {
-        match get_child_of_kind(call_node, SyntaxKind::ExprName, TraverseOrder::Forward) {
-            None => vec![],
-            Some(name_node) => match name_node.last_child_or_token().unwrap() {
-                rowan::NodeOrToken::Node(_) => unreachable!(),
-                rowan::NodeOrToken::Token(token) => {
-                    if token.text() == "ref" {
-                        match call_node
-                            .descendants()
-                            .find(|node| node.kind() == SyntaxKind::CallStaticArg)
-                        {
-                            None => vec![],
-                            Some(arg_node) => {
-                                match get_child_of_kind(
-                                    &arg_node,
-                                    SyntaxKind::ExprConstantString,
-                                    TraverseOrder::Forward,
-                                ) {
-                                    None => vec![],
-                                    Some(model_name_node) => {
-                                        let model_name = model_name_node.text().to_string();
-                                        let model_path =
-                                            self.get_model_path_from_string(model_name.trim());
-                                        match model_path {
-                                            None => vec![],
-                                            Some(path) => {
-                                                vec![LocationLink {
-                                                    origin_selection_range: todo!(),
-                                                    target_uri: todo!(),
-                                                    target_range: todo!(),
-                                                    target_selection_range: todo!(),
-                                                }]
-                                            }
-                                        }
-                                    }
-                                }
-                            }
-                        }
-                    } else {
-                        vec![]
-                    }
-                }
-            },
-        }
+    /// the graph of every model and source in the project and its installed
+    /// packages
+    pub fn get_graph(&self) -> RwLockReadGuard<'_, ProjectGraph> {
+        self.graph.read().unwrap()
     }
 
-    pub fn get_declaration(&self, path: PathBuf, position: Position) -> Vec<LocationLink> {
-        let mut locations = Vec::new();
+    /// Calls `f` with this project followed by its installed packages,
+    /// ordered by path so that results are stable
+    pub(crate) fn with_projects<T>(&self, f: impl FnOnce(&[&DbtProject]) -> T) -> T {
+        let mut packages: Vec<_> = self.packages.iter().collect();
+        packages.sort_by(|a, b| a.key().cmp(b.key()));
+        let projects: Vec<&DbtProject> = std::iter::once(self)
+            .chain(packages.iter().map(|package| package.value()))
+            .collect();
+        f(&projects)
+    }
 
-        let (offset, syntax_tree) = {
-            if self.is_file_model(&path) {
-                match self.models.get(&path) {
-                    None => {
-                        tracing::error!(
-                            message = "couldn't find model corresponding to path",
-                            path = ?path
-                        );
-                        return locations;
-                    }
-                    Some(model_file) => (
-                        model_file.position_finder.get_offset(position),
-                        model_file.parsed_repr.syntax(),
-                    ),
-                }
-            } else if self.is_file_macro(&path) {
-                match self.macros.get(&path) {
-                    None => {
-                        tracing::error!(message= "couldn't find macro corresponding to path", path=?path);
-                        return locations;
-                    }
-                    Some(macro_file) => (
-                        macro_file.position_finder.get_offset(position),
-                        macro_file.parsed_repr.syntax(),
-                    ),
+    /// a model or macro file, which stays locked for reading while borrowed
+    pub(crate) fn get_jinja_file(&self, path: &Path) -> Option<JinjaFile<'_>> {
+        if self.is_file_model(path) {
+            match self.models.get(path) {
+                None => {
+                    tracing::error!(message = "couldn't find model corresponding to path", ?path);
+                    None
                 }
-            } else {
-                return locations;
+                Some(model_file) => Some(JinjaFile::Model(model_file)),
             }
-        };
-        tracing::debug!(message = "position to offset", ?position, ?offset);
-        let token = syntax_tree.token_at_offset(offset.into());
-        tracing::debug!(message = "token at offset", ?token);
-        match token {
-            rowan::TokenAtOffset::None => (),
-            rowan::TokenAtOffset::Single(leaf) => {
-                let call_node = leaf
-                    .ancestors()
-                    .find(|ancestor| ancestor.kind() == SyntaxKind::ExprCall);
-                match call_node {
-                    None => (),
-                    Some(node) => locations.extend(self.get_model_declaration(&node)),
+        } else if self.is_file_macro(path) {
+            match self.macros.get(path) {
+                None => {
+                    tracing::error!(message = "couldn't find macro corresponding to path", ?path);
+                    None
                 }
+                Some(macro_file) => Some(JinjaFile::Macro(macro_file)),
             }
-            rowan::TokenAtOffset::Between(left, right) => (),
+        } else {
+            None
         }
+    }
 
-        locations
+    /// this project's macros called `macro_name` and the files they're in,
+    /// ordered by path
+    pub(crate) fn find_macro_definitions(&self, macro_name: &str) -> Vec<(PathBuf, Macro)> {
+        let mut macros: Vec<(PathBuf, Macro)> = self
+            .macros
+            .iter()
+            .flat_map(|macro_file| {
+                macro_file
+                    .macros
+                    .iter()
+                    .filter(|mac| mac.name.as_deref() == Some(macro_name))
+                    .map(|mac| (macro_file.key().clone(), mac.clone()))
+                    .collect::<Vec<_>>()
+            })
+            .collect();
+        macros.sort_by(|(a, _), (b, _)| a.cmp(b));
+        macros
+    }
+
+    /// the first entry for a macro in this project's property files
+    pub(crate) fn find_macro_properties(&self, macro_name: &str) -> Option<MacroProperties> {
+        let mut property_files: Vec<_> = self.properties.iter().collect();
+        property_files.sort_by(|a, b| a.key().cmp(b.key()));
+        property_files.iter().find_map(|property_file| {
+            property_file
+                .macros
+                .iter()
+                .find(|mac| mac.name == macro_name)
+                .cloned()
+        })
+    }
+
+    /// Looks for a model with `f` in the projects a `ref` in one of this
+    /// project's files could point to, in the same order as the graph and
+    /// references resolve them
+    pub(crate) fn resolve_ref_with<T>(
+        &self,
+        package_name: Option<&str>,
+        f: impl Fn(&DbtProject) -> Option<T>,
+    ) -> Vec<T> {
+        self.with_projects(|projects| {
+            let packages: Vec<&str> = projects
+                .iter()
+                .map(|project| project.spec.name.as_str())
+                .collect();
+            resolve_ref_in(&packages, &self.spec.name, package_name, |i| f(projects[i]))
+        })
     }
 
-    fn get_model_paths(&self) -> Vec<PathBuf> {
-        get_sql_files_in_paths(&self.root_path, &self.spec.model_paths)
+    /// Looks for macros with `f` in the projects a call in the package
+    /// `caller` could refer to, in the same order as references and renames
+    /// resolve them
+    pub(crate) fn resolve_macro_with<T>(
+        &self,
+        caller: &str,
+        namespace: Option<&str>,
+        f: impl Fn(&DbtProject) -> Vec<T>,
+    ) -> Vec<T> {
+        self.with_projects(|projects| {
+            let packages: Vec<&str> = projects
+                .iter()
+                .map(|project| project.spec.name.as_str())
+                .collect();
+            resolve_macro_in(&packages, caller, namespace, |i| f(projects[i]))
+        })
     }
 
-    fn get_macro_paths(&self) -> Vec<PathBuf> {
-        get_sql_files_in_paths(&self.root_path, &self.spec.macro_paths)
+    /// Collects the results of `f` for every installed package, ordered by
+    /// the package's path so that results are stable
+    pub(crate) fn flat_map_packages<T>(&self, f: impl Fn(&DbtProject) -> Vec<T>) -> Vec<T> {
+        let mut results: Vec<(PathBuf, Vec<T>)> = self
+            .packages
+            .iter()
+            .map(|package| (package.key().clone(), f(package.value())))
+            .collect();
+        results.sort_by(|(a, _), (b, _)| a.cmp(b));
+        results.into_iter().flat_map(|(_, result)| result).collect()
     }
 
-    fn get_model_names(&self) -> Vec<String> {
+    pub(crate) fn get_model_names(&self) -> Vec<String> {
         self.models
             .iter()
             .map(|model| model.value().name.clone())
             .collect()
     }
 
-    fn get_model_path_from_string(&self, literal_str: &str) -> Option<PathBuf> {
-        self.models.iter().find_map(|model| {
-            let name = &model.value().name;
-            if &format!("'{literal_str}'") == name || &format!("\"{literal_str}\"") == name {
-                Some(model.key().clone())
-            } else {
-                None
-            }
-        })
-    }
-
-    fn get_macros(&self) -> Vec<Macro> {
+    pub(crate) fn get_macros(&self) -> Vec<Macro> {
         self.macros
             .iter()
-            .map(|macro_file| macro_file.macros.clone())
-            .flatten()
+            .flat_map(|macro_file| macro_file.macros.clone())
             .collect()
     }
 
-    fn is_file_model(&self, path: &Path) -> bool {
+    pub(crate) fn is_file_model(&self, path: &Path) -> bool {
         if !is_sql_file(path) {
             false
         } else {
@@ -558,4 +926,153 @@ impl DbtProject {
                 .any(|macro_root| path.starts_with(self.root_path.join(macro_root)))
         }
     }
+
+    /// a `.md` file that can hold `{% docs %}` blocks
+    pub(crate) fn is_file_docs(&self, path: &Path) -> bool {
+        if !is_markdown_file(path) {
+            false
+        } else {
+            self.spec
+                .property_paths()
+                .iter()
+                .any(|docs_root| path.starts_with(self.root_path.join(docs_root)))
+        }
+    }
+
+    pub(crate) fn is_file_snapshot(&self, path: &Path) -> bool {
+        if !is_sql_file(path) {
+            false
+        } else {
+            self.spec
+                .snapshot_paths
+                .iter()
+                .any(|snapshot_root| path.starts_with(self.root_path.join(snapshot_root)))
+        }
+    }
+
+    pub(crate) fn is_file_seed(&self, path: &Path) -> bool {
+        if !is_csv_file(path) {
+            false
+        } else {
+            self.spec
+                .seed_paths
+                .iter()
+                .any(|seed_root| path.starts_with(self.root_path.join(seed_root)))
+        }
+    }
+
+    pub(crate) fn is_file_properties(&self, path: &Path) -> bool {
+        if !is_yaml_file(path) {
+            false
+        } else {
+            self.spec
+                .property_paths()
+                .iter()
+                .any(|property_root| path.starts_with(self.root_path.join(property_root)))
+        }
+    }
+}
+
+#[cfg(test)]
+impl DbtProject {
+    /// A project called `name` at `root_path` with the given files, as paths
+    /// relative to it and their contents, without reading anything from disk
+    pub(crate) fn from_files(name: &str, root_path: &Path, files: &[(&str, &str)]) -> Self {
+        let project = Self {
+            root_path: root_path.to_path_buf(),
+            spec: DbtProjectSpec::from_file(&format!("name: {}", name)).unwrap(),
+            models: DashMap::new(),
+            macros: DashMap::new(),
+            properties: DashMap::new(),
+            packages: DashMap::new(),
+            seed_files: DashSet::new(),
+            symbols: SymbolIndex::default(),
+            graph: RwLock::default(),
+            position_encoding: PositionEncoding::Utf16,
+        };
+        for (path, contents) in files {
+            project
+                .on_file_open(&root_path.join(path), contents)
+                .unwrap();
+        }
+        project
+    }
+}
+
+/// where `needle` first starts in `text`
+#[cfg(test)]
+pub(crate) fn position_of(text: &str, needle: &str) -> Position {
+    let offset = text.find(needle).unwrap();
+    let line = text[..offset].matches('\n').count();
+    let line_start = text[..offset].rfind('\n').map_or(0, |i| i + 1);
+    Position::new(line as u32, (offset - line_start) as u32)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// the packages that `ref` resolves to, given which ones define it
+    fn resolve(caller: &str, package: Option<&str>, defined: &[&str]) -> Vec<String> {
+        let packages = ["root", "a", "b", "c"];
+        resolve_ref_in(&packages, caller, package, |i| {
+            if defined.contains(&packages[i]) {
+                Some(packages[i].to_string())
+            } else {
+                None
+            }
+        })
+    }
+
+    #[test]
+    fn test_resolve_ref_order() {
+        // the caller's package comes first, then the root project
+        assert_eq!(resolve("a", None, &["root", "a", "b"]), vec!["a"]);
+        assert_eq!(resolve("a", None, &["root", "b"]), vec!["root"]);
+        assert_eq!(resolve("root", None, &["root", "a"]), vec!["root"]);
+        // other packages are ambiguous, so all of them are returned
+        assert_eq!(resolve("a", None, &["b", "c"]), vec!["b", "c"]);
+        assert_eq!(resolve("root", None, &[]), Vec::<String>::new());
+    }
+
+    /// the packages whose macro a call resolves to, given which ones define it
+    fn resolve_macro(caller: &str, namespace: Option<&str>, defined: &[&str]) -> Vec<String> {
+        let packages = ["root", "a", "b", "c"];
+        resolve_macro_in(&packages, caller, namespace, |i| {
+            if defined.contains(&packages[i]) {
+                vec![packages[i].to_string()]
+            } else {
+                vec![]
+            }
+        })
+    }
+
+    #[test]
+    fn test_resolve_macro_order() {
+        // the root project comes first, then the caller's package, then the
+        // others in order
+        assert_eq!(resolve_macro("b", None, &["root", "a", "b"]), vec!["root"]);
+        assert_eq!(resolve_macro("b", None, &["a", "b", "c"]), vec!["b"]);
+        assert_eq!(resolve_macro("root", None, &["b", "c"]), vec!["b"]);
+        assert_eq!(resolve_macro("a", None, &[]), Vec::<String>::new());
+        // a namespace only looks in that package
+        assert_eq!(
+            resolve_macro("b", Some("c"), &["root", "b", "c"]),
+            vec!["c"]
+        );
+        assert_eq!(
+            resolve_macro("b", Some("a"), &["root", "b"]),
+            Vec::<String>::new()
+        );
+    }
+
+    #[test]
+    fn test_resolve_ref_with_package() {
+        assert_eq!(resolve("a", Some("b"), &["root", "a", "b"]), vec!["b"]);
+        assert_eq!(resolve("a", Some("root"), &["root", "a"]), vec!["root"]);
+        assert_eq!(
+            resolve("a", Some("c"), &["root", "a"]),
+            Vec::<String>::new()
+        );
+    }
 }
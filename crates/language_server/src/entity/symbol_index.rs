@@ -0,0 +1,226 @@
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::sync::RwLock;
+
+use tower_lsp::lsp_types::{Range, SymbolKind};
+
+/// A model, macro, source table or other named thing in a project, as found
+/// by workspace symbol search
+#[derive(Debug, Clone)]
+pub struct IndexedSymbol {
+    /// the name to match against and show, e.g. `utils.star` for a macro
+    pub name: String,
+    pub kind: SymbolKind,
+    pub range: Range,
+}
+
+impl IndexedSymbol {
+    pub fn new(name: String, kind: SymbolKind, range: Range) -> Self {
+        IndexedSymbol { name, kind, range }
+    }
+}
+
+/// The symbols in each of a project's files. Files are indexed again
+/// whenever they change, so searching doesn't have to look at the files
+/// themselves.
+#[derive(Debug, Default)]
+pub struct SymbolIndex {
+    inner: RwLock<Postings>,
+}
+
+/// The symbols by id, and for each character, the ids of the symbols whose
+/// names have it. A fuzzy match needs every character of the query, so a
+/// search only has to score the symbols that all of them point to.
+#[derive(Debug, Default)]
+struct Postings {
+    /// each symbol and the file it's in, with `None` where one was removed
+    symbols: Vec<Option<(PathBuf, IndexedSymbol)>>,
+    /// ids in `symbols` that can be reused
+    free: Vec<usize>,
+    /// the ids of each file's symbols
+    files: HashMap<PathBuf, Vec<usize>>,
+    /// the ids of the symbols with each letter or digit in their name,
+    /// ignoring case
+    chars: HashMap<char, HashSet<usize>>,
+}
+
+impl SymbolIndex {
+    pub fn set_file(&self, path: &Path, symbols: Vec<IndexedSymbol>) {
+        let mut postings = self.inner.write().unwrap();
+        postings.remove_file(path);
+        if symbols.is_empty() {
+            return;
+        }
+        let ids = symbols
+            .into_iter()
+            .map(|symbol| postings.add(path, symbol))
+            .collect();
+        postings.files.insert(path.to_path_buf(), ids);
+    }
+
+    pub fn remove_file(&self, path: &Path) {
+        self.inner.write().unwrap().remove_file(path);
+    }
+
+    /// every symbol that fuzzily matches `query`, with its file and how
+    /// well it matches
+    pub fn search(&self, query: &str) -> Vec<(PathBuf, IndexedSymbol, i64)> {
+        let postings = self.inner.read().unwrap();
+        let score = |id: usize| {
+            let (path, symbol) = postings.symbols[id].as_ref()?;
+            let score = fuzzy_score(query, &symbol.name)?;
+            Some((path.clone(), symbol.clone(), score))
+        };
+        // starting from the rarest character keeps the candidates few
+        let mut lists: Vec<&HashSet<usize>> = Vec::new();
+        for c in indexed_chars(query) {
+            match postings.chars.get(&c) {
+                None => return Vec::new(),
+                Some(ids) => lists.push(ids),
+            }
+        }
+        lists.sort_by_key(|ids| ids.len());
+        match lists.split_first() {
+            None => (0..postings.symbols.len()).filter_map(score).collect(),
+            Some((rarest, others)) => rarest
+                .iter()
+                .filter(|id| others.iter().all(|ids| ids.contains(id)))
+                .filter_map(|id| score(*id))
+                .collect(),
+        }
+    }
+}
+
+impl Postings {
+    fn add(&mut self, path: &Path, symbol: IndexedSymbol) -> usize {
+        let chars = indexed_chars(&symbol.name);
+        let entry = Some((path.to_path_buf(), symbol));
+        let id = match self.free.pop() {
+            Some(id) => {
+                self.symbols[id] = entry;
+                id
+            }
+            None => {
+                self.symbols.push(entry);
+                self.symbols.len() - 1
+            }
+        };
+        for c in chars {
+            self.chars.entry(c).or_default().insert(id);
+        }
+        id
+    }
+
+    fn remove_file(&mut self, path: &Path) {
+        for id in self.files.remove(path).unwrap_or_default() {
+            if let Some((_, symbol)) = self.symbols[id].take() {
+                for c in indexed_chars(&symbol.name) {
+                    if let Some(ids) = self.chars.get_mut(&c) {
+                        ids.remove(&id);
+                        if ids.is_empty() {
+                            self.chars.remove(&c);
+                        }
+                    }
+                }
+            }
+            self.free.push(id);
+        }
+    }
+}
+
+/// The letters and digits in some text, ignoring case. Other characters
+/// aren't indexed, so they don't rule anything out.
+fn indexed_chars(text: &str) -> HashSet<char> {
+    text.chars()
+        .filter(char::is_ascii_alphanumeric)
+        .map(|c| c.to_ascii_lowercase())
+        .collect()
+}
+
+/// How well `query` matches `name`, if all of its characters are in the
+/// name in order, ignoring case. Matches at the start of a word and runs of
+/// consecutive matches count for more, and skipping characters between
+/// matches counts against it, so `stgord` scores `stg_orders` higher than
+/// `stg_customer_records`.
+fn fuzzy_score(query: &str, name: &str) -> Option<i64> {
+    let mut name_chars = name.chars();
+    let mut previous: Option<char> = None;
+    let mut score = 0;
+    let mut started = false;
+    for query_char in query.chars() {
+        let mut consecutive = true;
+        loop {
+            let name_char = name_chars.next()?;
+            let word_start = match previous {
+                None => true,
+                Some(previous) => {
+                    !previous.is_alphanumeric()
+                        || (previous.is_lowercase() && name_char.is_uppercase())
+                }
+            };
+            previous = Some(name_char);
+            if name_char.to_lowercase().eq(query_char.to_lowercase()) {
+                score += 1;
+                if word_start {
+                    score += 8;
+                } else if consecutive && started {
+                    score += 4;
+                }
+                break;
+            }
+            consecutive = false;
+            if started {
+                score -= 1;
+            }
+        }
+        started = true;
+    }
+    Some(score)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn symbols(names: &[&str]) -> Vec<IndexedSymbol> {
+        names
+            .iter()
+            .map(|name| IndexedSymbol::new(name.to_string(), SymbolKind::MODULE, Range::default()))
+            .collect()
+    }
+
+    fn search(index: &SymbolIndex, query: &str) -> Vec<String> {
+        let mut names: Vec<String> = index
+            .search(query)
+            .into_iter()
+            .map(|(_, symbol, _)| symbol.name)
+            .collect();
+        names.sort();
+        names
+    }
+
+    #[test]
+    fn test_search() {
+        let index = SymbolIndex::default();
+        index.set_file(Path::new("/a.sql"), symbols(&["stg_orders", "orders"]));
+        index.set_file(Path::new("/b.yml"), symbols(&["stg_customer_records"]));
+        assert_eq!(
+            search(&index, "stgord"),
+            vec!["stg_customer_records", "stg_orders"]
+        );
+        assert_eq!(
+            search(&index, "ORD"),
+            vec!["orders", "stg_customer_records", "stg_orders"]
+        );
+        assert_eq!(search(&index, "xyz"), Vec::<String>::new());
+        assert_eq!(search(&index, "").len(), 3);
+
+        // reindexing a file replaces its symbols, and their ids get reused
+        index.set_file(Path::new("/a.sql"), symbols(&["payments"]));
+        assert_eq!(search(&index, "ord"), vec!["stg_customer_records"]);
+        assert_eq!(search(&index, "pay"), vec!["payments"]);
+        index.remove_file(Path::new("/b.yml"));
+        assert_eq!(search(&index, "ord"), Vec::<String>::new());
+        assert_eq!(search(&index, ""), vec!["payments"]);
+    }
+}
@@ -1,19 +1,30 @@
 use std::path::Path;
 
+use dbt_jinja_parser::ast::{
+    AstNode, Signature, SignatureDefaultArg, StmtMacro, StmtMaterialization, StmtTest,
+};
 use dbt_jinja_parser::lexer::tokenize;
 use dbt_jinja_parser::parser::{parse, Parse, SyntaxKind};
 use derivative::Derivative;
+use rowan::{TextRange, TextSize};
 
 use crate::entity::{Macro, Materialization};
-use crate::position_finder::PositionFinder;
-use crate::utils::{get_child_of_kind, read_file, SyntaxNode, TraverseOrder};
+use crate::line_index::LineIndex;
+use crate::utils::{get_text_edit, read_file, shift_range, SyntaxNode};
+
+/// the tags that start the blocks macros and materializations are defined in
+const BLOCK_STARTS: [SyntaxKind; 3] = [
+    SyntaxKind::MacroBlockStart,
+    SyntaxKind::TestBlockStart,
+    SyntaxKind::MaterializationBlockStart,
+];
 
 #[derive(Derivative)]
 #[derivative(Debug)]
 /// This represents the metadata we need to track for a dbt macro file
 pub struct MacroFile {
     #[derivative(Debug = "ignore")]
-    pub position_finder: PositionFinder,
+    pub line_index: LineIndex,
     #[derivative(Debug = "ignore")]
     pub parsed_repr: Parse,
     pub macros: Vec<Macro>,
@@ -27,7 +38,7 @@ impl MacroFile {
         let (macros, materializations) =
             Self::macros_from_parsed(&SyntaxNode::new_root(syntax_tree.clone()));
         Ok(Self {
-            position_finder: PositionFinder::from_text(file_contents),
+            line_index: LineIndex::new(file_contents),
             parsed_repr,
             macros,
             materializations,
@@ -44,13 +55,49 @@ impl MacroFile {
     }
 
     pub fn refresh(&mut self, file_contents: &str) {
-        self.position_finder = PositionFinder::from_text(file_contents);
+        let old_contents = self.parsed_repr.syntax().to_string();
+        let (delete, insert) = get_text_edit(&old_contents, file_contents);
+        self.apply_edit(delete, insert);
+    }
 
-        let parsed_repr = parse(tokenize(file_contents));
-        let syntax_tree = parsed_repr.green();
-        let (macros, materializations) =
-            Self::macros_from_parsed(&SyntaxNode::new_root(syntax_tree.clone()));
+    /// Replaces the text in `delete` with `insert`, only reparsing what's
+    /// needed
+    pub fn apply_edit(&mut self, delete: TextRange, insert: &str) {
+        self.line_index.apply_edit(delete, insert);
+        let (parsed_repr, segment) = self.parsed_repr.reparse_with_range(delete, insert);
         self.parsed_repr = parsed_repr;
+        // only the tag that starts a block says what it defines, so edits
+        // anywhere else just move the macros around
+        if let Some(segment) = segment {
+            let new_len = segment.len() - delete.len() + TextSize::of(insert);
+            let kind = self
+                .parsed_repr
+                .syntax()
+                .covering_element(TextRange::at(segment.start(), new_len))
+                .kind();
+            if !BLOCK_STARTS.contains(&kind) {
+                let shifted: Option<Vec<Macro>> = self
+                    .macros
+                    .iter()
+                    .map(|mac| {
+                        Some(Macro {
+                            declaration: shift_range(mac.declaration, segment, new_len)?,
+                            declaration_selection: shift_range(
+                                mac.declaration_selection,
+                                segment,
+                                new_len,
+                            )?,
+                            ..mac.clone()
+                        })
+                    })
+                    .collect();
+                if let Some(macros) = shifted {
+                    self.macros = macros;
+                    return;
+                }
+            }
+        }
+        let (macros, materializations) = Self::macros_from_parsed(&self.parsed_repr.syntax());
         self.macros = macros;
         self.materializations = materializations;
     }
@@ -58,150 +105,147 @@ impl MacroFile {
     fn macros_from_parsed(syntax_tree: &SyntaxNode) -> (Vec<Macro>, Vec<Materialization>) {
         let mut macros = Vec::new();
         let mut materializations = Vec::new();
-        for some_macro in syntax_tree.descendants() {
-            match some_macro.kind() {
-                SyntaxKind::StmtMacro => macros.push(Self::extract_macro(&some_macro)),
-                SyntaxKind::StmtMaterialization => {
-                    materializations.push(Self::extract_materialization(&some_macro))
-                }
-                _ => (),
+        for node in syntax_tree.descendants() {
+            if let Some(macro_node) = StmtMacro::cast(node.clone()) {
+                macros.push(Self::extract_macro(&macro_node));
+            } else if let Some(test_node) = StmtTest::cast(node.clone()) {
+                macros.push(Self::extract_generic_test(&test_node));
+            } else if let Some(mat_node) = StmtMaterialization::cast(node) {
+                materializations.push(Self::extract_materialization(&mat_node));
             }
         }
         (macros, materializations)
     }
 
-    fn extract_default_arg(
-        name: &Option<String>,
-        default_arg_node: &SyntaxNode,
-    ) -> (Option<String>, Option<String>) {
-        let children = default_arg_node.children_with_tokens();
-        let mut seen_assign = false;
-        let mut assign_target = None;
-        let mut default_value = None;
-        for child in children {
-            // tracing::info!(message = "examining child", ?child, macro_name = ?name);
-            if !seen_assign {
-                match child.kind() {
-                    SyntaxKind::ExprName => {
-                        assign_target = Some(child.into_node().unwrap().text().to_string())
-                    }
-                    SyntaxKind::Assign => seen_assign = true,
-                    _ => (),
-                }
-            } else {
-                match child.kind() {
-                    SyntaxKind::Whitespace => (),
-                    _ => {
-                        default_value = Some(match child {
-                            rowan::NodeOrToken::Node(node) => node.text().to_string(),
-                            rowan::NodeOrToken::Token(token) => token.text().to_string(),
-                        });
-                        break;
-                    }
-                }
-            }
-        }
+    fn extract_default_arg(default_arg: &SignatureDefaultArg) -> (Option<String>, Option<String>) {
+        let assign_target = default_arg.name().and_then(|name| name.name());
+        let default_value = default_arg.default_value().map(|value| value.to_string());
         (assign_target, default_value)
     }
 
-    fn extract_macro(macro_node: &SyntaxNode) -> Macro {
-        debug_assert!(macro_node.kind() == SyntaxKind::StmtMacro);
-        let macro_start = get_child_of_kind(
-            macro_node,
-            SyntaxKind::MacroBlockStart,
-            TraverseOrder::Forward,
+    fn extract_macro(macro_node: &StmtMacro) -> Macro {
+        let name_node = macro_node.name();
+        let declaration_selection = match (&name_node, macro_node.start()) {
+            (Some(node), _) => node.syntax().text_range(),
+            (None, Some(start)) => start.syntax().text_range(),
+            (None, None) => macro_node.syntax().text_range(),
+        };
+        let name = name_node.and_then(|n| n.name());
+        Self::build_macro(
+            name,
+            macro_node.syntax().text_range(),
+            declaration_selection,
+            macro_node.signature(),
         )
-        .unwrap();
-        let name_node =
-            get_child_of_kind(&macro_start, SyntaxKind::ExprName, TraverseOrder::Forward);
-        let declaration_selection = match &name_node {
-            None => macro_start.text_range(),
-            Some(node) => node.text_range(),
+    }
+
+    /// A `{% test name(...) %}` block defines a generic test, which dbt
+    /// treats as a macro called `test_name`
+    fn extract_generic_test(test_node: &StmtTest) -> Macro {
+        let name_node = test_node.name();
+        let declaration_selection = match (&name_node, test_node.start()) {
+            (Some(node), _) => node.syntax().text_range(),
+            (None, Some(start)) => start.syntax().text_range(),
+            (None, None) => test_node.syntax().text_range(),
+        };
+        let name = name_node
+            .and_then(|n| n.name())
+            .map(|name| format!("test_{}", name));
+        Self::build_macro(
+            name,
+            test_node.syntax().text_range(),
+            declaration_selection,
+            test_node.signature(),
+        )
+    }
+
+    fn build_macro(
+        name: Option<String>,
+        declaration: TextRange,
+        declaration_selection: TextRange,
+        signature: Option<Signature>,
+    ) -> Macro {
+        let (args, default_args) = match signature {
+            None => (Vec::new(), Vec::new()),
+            Some(signature) => (
+                signature
+                    .args()
+                    .map(|arg| arg.name().and_then(|name| name.name()))
+                    .collect(),
+                signature
+                    .default_args()
+                    .map(|arg| Self::extract_default_arg(&arg))
+                    .collect(),
+            ),
         };
-        let name = name_node.map(|n| n.text().to_string());
-
-        let signature =
-            get_child_of_kind(&macro_start, SyntaxKind::Signature, TraverseOrder::Forward);
-        let mut args = Vec::new();
-        let mut default_args = Vec::new();
-        match signature {
-            None => (),
-            Some(node) => {
-                for child in node.children() {
-                    match child.kind() {
-                        SyntaxKind::SignatureArg => {
-                            match get_child_of_kind(
-                                &child,
-                                SyntaxKind::ExprName,
-                                TraverseOrder::Forward,
-                            ) {
-                                None => args.push(None),
-                                Some(arg_name) => args.push(Some(arg_name.text().to_string())),
-                            }
-                        }
-                        SyntaxKind::SignatureDefaultArg => {
-                            default_args.push(Self::extract_default_arg(&name, &child))
-                        }
-                        SyntaxKind::Whitespace => (),
-                        _ => unreachable!(),
-                    }
-                }
-            }
-        }
         Macro {
             name,
-            declaration: macro_node.text_range(),
+            declaration,
             declaration_selection,
             args,
             default_args,
         }
     }
 
-    fn extract_materialization(mat_node: &SyntaxNode) -> Materialization {
-        debug_assert!(mat_node.kind() == SyntaxKind::StmtMaterialization);
-        let mat_start = get_child_of_kind(
-            mat_node,
-            SyntaxKind::MaterializationBlockStart,
-            TraverseOrder::Forward,
-        )
-        .unwrap();
-        let name = get_child_of_kind(&mat_start, SyntaxKind::ExprName, TraverseOrder::Forward)
-            .map(|n| n.text().to_string());
-
-        if let Some(_) = get_child_of_kind(
-            &mat_start,
-            SyntaxKind::MaterializationDefault,
-            TraverseOrder::Forward,
-        ) {
-            Materialization {
-                name,
-                adapter: "default".to_string(),
-            }
-        } else if let Some(adapter_node) = get_child_of_kind(
-            &mat_start,
-            SyntaxKind::MaterializationAdapter,
-            TraverseOrder::Backward,
-        ) {
-            if let Some(str_node) = get_child_of_kind(
-                &adapter_node,
-                SyntaxKind::ExprConstantString,
-                TraverseOrder::Forward,
-            ) {
-                Materialization {
-                    name,
-                    adapter: str_node.text().to_string(),
-                }
-            } else {
-                Materialization {
-                    name,
-                    adapter: "default".to_string(),
-                }
-            }
-        } else {
-            Materialization {
-                name,
-                adapter: "default".to_string(),
-            }
+    fn extract_materialization(mat_node: &StmtMaterialization) -> Materialization {
+        Materialization {
+            name: mat_node.name().and_then(|n| n.name()),
+            adapter: mat_node.adapter(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// applies each edit in turn, checking the macros against the ones found
+    /// in the whole file
+    fn check_edits(text: &str, edits: &[(&str, &str)]) {
+        let mut macro_file = MacroFile::from_file(text).unwrap();
+        let mut text = text.to_string();
+        for (from, to) in edits {
+            let start = text.find(from).unwrap();
+            let delete = TextRange::at(TextSize::from(start as u32), TextSize::of(*from));
+            text = text.replacen(from, to, 1);
+            macro_file.apply_edit(delete, to);
+            let expected = MacroFile::from_file(&text).unwrap();
+            let summary = |macros: &[Macro]| -> Vec<_> {
+                macros
+                    .iter()
+                    .map(|mac| {
+                        (
+                            mac.name.clone(),
+                            mac.args.clone(),
+                            mac.declaration,
+                            mac.declaration_selection,
+                        )
+                    })
+                    .collect()
+            };
+            assert_eq!(
+                summary(&macro_file.macros),
+                summary(&expected.macros),
+                "after {:?}",
+                to
+            );
         }
     }
+
+    #[test]
+    fn test_apply_edit_macros() {
+        check_edits(
+            "{% macro a(x) %}{{ x }}{% endmacro %}\n{% test b(model) %}select 1{% endtest %}",
+            &[
+                // in a body, which moves the macros after it
+                ("{{ x }}", "{{ x * 100 }}"),
+                ("select 1", "select 1 from {{ model }}"),
+                // in the tags that define them
+                ("a(x)", "cents(x, scale=2)"),
+                ("b(model)", "positive(model, column_name)"),
+                // a new macro
+                ("\n", "\n{% macro c() %}{% endmacro %}\n"),
+            ],
+        );
+    }
 }
@@ -1,3 +1,5 @@
 pub mod macro_file;
 pub mod model_file;
 pub mod project_yml;
+pub mod property_file;
+pub mod yaml;
@@ -1,11 +1,14 @@
 use std::path::Path;
 
+use dbt_jinja_parser::ast::{AstNode, ExprCall};
 use dbt_jinja_parser::lexer::tokenize;
-use dbt_jinja_parser::parser::{parse, Parse};
+use dbt_jinja_parser::parser::{parse, Parse, SyntaxNode};
 use derivative::Derivative;
+use rowan::{TextRange, TextSize};
 
-use crate::position_finder::PositionFinder;
-use crate::utils::read_file;
+use crate::entity::Dependency;
+use crate::line_index::LineIndex;
+use crate::utils::{get_text_edit, read_file, shift_range};
 
 #[derive(Derivative)]
 #[derivative(Debug)]
@@ -13,9 +16,11 @@ use crate::utils::read_file;
 pub struct ModelFile {
     pub name: String,
     #[derivative(Debug = "ignore")]
-    pub position_finder: PositionFinder,
+    pub line_index: LineIndex,
     #[derivative(Debug = "ignore")]
     pub parsed_repr: Parse,
+    /// every `ref()` and `source()` in the model
+    pub dependencies: Vec<Dependency>,
 }
 
 impl ModelFile {
@@ -24,10 +29,12 @@ impl ModelFile {
             None => return Err(format!("no file stem found for {:?}", file_path)),
             Some(stem) => stem.to_string_lossy(),
         };
+        let parsed_repr = parse(tokenize(file_contents));
         Ok(Self {
             name: name.to_string(),
-            position_finder: PositionFinder::from_text(file_contents),
-            parsed_repr: parse(tokenize(file_contents)),
+            line_index: LineIndex::new(file_contents),
+            dependencies: Self::dependencies_from_parsed(&parsed_repr.syntax()),
+            parsed_repr,
         })
     }
 
@@ -37,7 +44,120 @@ impl ModelFile {
     }
 
     pub fn refresh(&mut self, file_contents: &str) {
-        self.position_finder = PositionFinder::from_text(file_contents);
-        self.parsed_repr = parse(tokenize(file_contents));
+        let old_contents = self.parsed_repr.syntax().to_string();
+        let (delete, insert) = get_text_edit(&old_contents, file_contents);
+        self.apply_edit(delete, insert);
+    }
+
+    /// Replaces the text in `delete` with `insert`, only reparsing what's
+    /// needed
+    pub fn apply_edit(&mut self, delete: TextRange, insert: &str) {
+        self.line_index.apply_edit(delete, insert);
+        let (parsed_repr, segment) = self.parsed_repr.reparse_with_range(delete, insert);
+        self.parsed_repr = parsed_repr;
+        let syntax_tree = self.parsed_repr.syntax();
+        let segment = match segment {
+            None => {
+                self.dependencies = Self::dependencies_from_parsed(&syntax_tree);
+                return;
+            }
+            Some(segment) => segment,
+        };
+        // only the calls in the reparsed segment need finding again
+        let new_len = segment.len() - delete.len() + TextSize::of(insert);
+        self.dependencies.retain_mut(|dependency| {
+            let (Dependency::Ref { range, .. } | Dependency::Source { range, .. }) = dependency;
+            match shift_range(*range, segment, new_len) {
+                None => false,
+                Some(shifted) => {
+                    *range = shifted;
+                    true
+                }
+            }
+        });
+        if let Some(node) = syntax_tree
+            .covering_element(TextRange::at(segment.start(), new_len))
+            .into_node()
+        {
+            let at = self
+                .dependencies
+                .partition_point(|dependency| dependency.range().start() < segment.start());
+            self.dependencies
+                .splice(at..at, Self::dependencies_from_parsed(&node));
+        }
+    }
+
+    /// Finds the calls that name their model or source with string literals,
+    /// which are the only ones that can be resolved without running dbt
+    fn dependencies_from_parsed(syntax_tree: &SyntaxNode) -> Vec<Dependency> {
+        syntax_tree
+            .descendants()
+            .filter_map(ExprCall::cast)
+            .filter_map(|call_node| {
+                let args: Vec<_> = call_node
+                    .static_args()
+                    .map(|arg| arg.string_value())
+                    .collect();
+                let range = call_node.syntax().text_range();
+                match (call_node.callee_name().as_deref(), args.as_slice()) {
+                    (Some("ref"), [Some(name)]) => Some(Dependency::Ref {
+                        package: None,
+                        name: name.clone(),
+                        range,
+                    }),
+                    (Some("ref"), [Some(package), Some(name)]) => Some(Dependency::Ref {
+                        package: Some(package.clone()),
+                        name: name.clone(),
+                        range,
+                    }),
+                    (Some("source"), [Some(source), Some(table)]) => Some(Dependency::Source {
+                        source: source.clone(),
+                        table: table.clone(),
+                        range,
+                    }),
+                    _ => None,
+                }
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// applies each edit in turn, checking the dependencies against the ones
+    /// found in the whole file
+    fn check_edits(text: &str, edits: &[(&str, &str)]) {
+        let path = Path::new("/shop/models/orders.sql");
+        let mut model = ModelFile::from_file(path, text).unwrap();
+        let mut text = text.to_string();
+        for (from, to) in edits {
+            let start = text.find(from).unwrap();
+            let delete = TextRange::at(TextSize::from(start as u32), TextSize::of(*from));
+            text = text.replacen(from, to, 1);
+            model.apply_edit(delete, to);
+            let expected = ModelFile::from_file(path, &text).unwrap();
+            assert_eq!(model.dependencies, expected.dependencies, "after {:?}", to);
+        }
+    }
+
+    #[test]
+    fn test_apply_edit_dependencies() {
+        check_edits(
+            "select * from {{ ref('a') }} join {{ source('s', 't') }} {% if x %}{{ ref('b') }}{% endif %}",
+            &[
+                // within one call
+                ("'a'", "'customers'"),
+                // moves the calls after it
+                ("select *", "select id"),
+                ("join", "left join"),
+                // a call appears and disappears
+                ("{{ ref('b') }}", "{{ ref('b') }} {{ ref('c') }}"),
+                ("ref('customers')", "customers"),
+                // changes the block structure
+                ("{% endif %}", ""),
+            ],
+        );
     }
 }
@@ -11,6 +11,12 @@ pub struct DbtProjectSpec {
     pub model_paths: Vec<String>,
     #[serde(rename = "macro-paths", default = "default_macro_paths")]
     pub macro_paths: Vec<String>,
+    #[serde(rename = "seed-paths", default = "default_seed_paths")]
+    pub seed_paths: Vec<String>,
+    #[serde(rename = "snapshot-paths", default = "default_snapshot_paths")]
+    pub snapshot_paths: Vec<String>,
+    #[serde(rename = "analysis-paths", default = "default_analysis_paths")]
+    pub analysis_paths: Vec<String>,
     #[serde(
         rename = "packages-install-path",
         default = "default_packages_install_path"
@@ -26,6 +32,18 @@ fn default_macro_paths() -> Vec<String> {
     vec!["macros".to_string()]
 }
 
+fn default_seed_paths() -> Vec<String> {
+    vec!["seeds".to_string()]
+}
+
+fn default_snapshot_paths() -> Vec<String> {
+    vec!["snapshots".to_string()]
+}
+
+fn default_analysis_paths() -> Vec<String> {
+    vec!["analyses".to_string()]
+}
+
 fn default_packages_install_path() -> String {
     "dbt_packages".to_string()
 }
@@ -47,6 +65,22 @@ impl DbtProjectSpec {
         Self::from_file(&file_contents)
     }
 
+    /// every directory that can hold `.yml` property files
+    pub fn property_paths(&self) -> Vec<String> {
+        [
+            &self.model_paths,
+            &self.seed_paths,
+            &self.snapshot_paths,
+            &self.macro_paths,
+            &self.analysis_paths,
+        ]
+        .into_iter()
+        .flatten()
+        .cloned()
+        .collect()
+    }
+
+    #[allow(dead_code)]
     pub fn refresh(&mut self, file_contents: &str) -> Result<(), String> {
         *self = Self::from_file(file_contents)?;
         Ok(())
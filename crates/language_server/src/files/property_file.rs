@@ -0,0 +1,340 @@
+use std::path::Path;
+
+use derivative::Derivative;
+use rowan::{TextRange, TextSize};
+
+use crate::entity::{
+    Column, Exposure, GenericTest, MacroArgument, MacroProperties, NodeProperties, Source,
+    SourceTable,
+};
+use crate::files::yaml::{parse_yaml, YamlError, YamlNode};
+use crate::line_index::LineIndex;
+use crate::utils::read_file;
+
+#[derive(Derivative)]
+#[derivative(Debug)]
+/// This represents the metadata we track for a `.yml` property file, e.g. a
+/// `schema.yml` describing models and sources
+pub struct PropertyFile {
+    #[derivative(Debug = "ignore")]
+    contents: String,
+    #[derivative(Debug = "ignore")]
+    pub line_index: LineIndex,
+    /// why the file couldn't be parsed, in which case it has no entries
+    pub error: Option<YamlError>,
+    pub models: Vec<NodeProperties>,
+    pub seeds: Vec<NodeProperties>,
+    pub snapshots: Vec<NodeProperties>,
+    pub sources: Vec<Source>,
+    pub macros: Vec<MacroProperties>,
+    pub exposures: Vec<Exposure>,
+}
+
+impl PropertyFile {
+    pub fn from_file(file_contents: &str) -> Result<Self, String> {
+        let mut property_file = Self {
+            contents: file_contents.to_string(),
+            line_index: LineIndex::new(file_contents),
+            error: None,
+            models: Vec::new(),
+            seeds: Vec::new(),
+            snapshots: Vec::new(),
+            sources: Vec::new(),
+            macros: Vec::new(),
+            exposures: Vec::new(),
+        };
+        property_file.extract_properties();
+        Ok(property_file)
+    }
+
+    pub async fn from_file_path(file_path: &Path) -> Result<Self, String> {
+        let file_contents = read_file(file_path).await?;
+        Self::from_file(&file_contents)
+    }
+
+    pub fn refresh(&mut self, file_contents: &str) {
+        self.contents = file_contents.to_string();
+        self.line_index = LineIndex::new(file_contents);
+        self.extract_properties();
+    }
+
+    /// Replaces the text in `delete` with `insert`. YAML can't be parsed
+    /// piecemeal, so the whole file is parsed again.
+    pub fn apply_edit(&mut self, delete: TextRange, insert: &str) {
+        self.line_index.apply_edit(delete, insert);
+        self.contents
+            .replace_range(std::ops::Range::<usize>::from(delete), insert);
+        self.extract_properties();
+    }
+
+    /// The text of a value, with the indentation of any lines after the
+    /// first adjusted to line up with it, e.g. to show a block by itself
+    pub fn snippet(&self, range: TextRange) -> String {
+        let start = usize::from(range.start());
+        let indent = start - self.contents[..start].rfind('\n').map_or(0, |i| i + 1);
+        self.contents[range]
+            .lines()
+            .enumerate()
+            .map(|(i, line)| {
+                let leading = line.len() - line.trim_start_matches(' ').len();
+                if i == 0 {
+                    line
+                } else {
+                    &line[leading.min(indent)..]
+                }
+            })
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+
+    /// the part of a scalar's range inside its quotes, if it has any, e.g.
+    /// to replace a name without changing how it's quoted
+    pub fn unquoted(&self, range: TextRange) -> TextRange {
+        let text = &self.contents[range];
+        for quote in ['\'', '"'] {
+            if text.len() >= 2 && text.starts_with(quote) && text.ends_with(quote) {
+                let quote_len = TextSize::of(quote);
+                return TextRange::new(range.start() + quote_len, range.end() - quote_len);
+            }
+        }
+        range
+    }
+
+    /// every generic test applied in the file, whether to a resource or one
+    /// of its columns
+    pub fn tests(&self) -> Vec<&GenericTest> {
+        let node_tests = self
+            .models
+            .iter()
+            .chain(&self.seeds)
+            .chain(&self.snapshots)
+            .flat_map(|node| {
+                node.tests
+                    .iter()
+                    .chain(node.columns.iter().flat_map(|column| &column.tests))
+            });
+        let table_tests = self
+            .sources
+            .iter()
+            .flat_map(|source| &source.tables)
+            .flat_map(|table| {
+                table
+                    .tests
+                    .iter()
+                    .chain(table.columns.iter().flat_map(|column| &column.tests))
+            });
+        node_tests.chain(table_tests).collect()
+    }
+
+    fn extract_properties(&mut self) {
+        let root = match parse_yaml(&self.contents) {
+            Ok(root) => {
+                self.error = None;
+                root
+            }
+            Err(e) => {
+                self.error = Some(e);
+                None
+            }
+        };
+        let entries = |key: &str| -> &[YamlNode] {
+            match &root {
+                Some(root) => root.get(key).map_or(&[], |value| value.items()),
+                None => &[],
+            }
+        };
+        self.models = entries("models")
+            .iter()
+            .filter_map(extract_node_properties)
+            .collect();
+        self.seeds = entries("seeds")
+            .iter()
+            .filter_map(extract_node_properties)
+            .collect();
+        self.snapshots = entries("snapshots")
+            .iter()
+            .filter_map(extract_node_properties)
+            .collect();
+        self.sources = entries("sources")
+            .iter()
+            .filter_map(extract_source)
+            .collect();
+        self.macros = entries("macros")
+            .iter()
+            .filter_map(extract_macro_properties)
+            .collect();
+        self.exposures = entries("exposures")
+            .iter()
+            .filter_map(extract_exposure)
+            .collect();
+    }
+}
+
+/// the `name` of an entry, along with where it's written
+fn extract_name(node: &YamlNode) -> Option<(String, TextRange)> {
+    let value = node.get("name")?;
+    Some((value.as_str()?.to_string(), value.range))
+}
+
+/// `tests` was renamed to `data_tests` in dbt 1.8, but both are accepted
+fn extract_tests(node: &YamlNode) -> Vec<GenericTest> {
+    ["tests", "data_tests"]
+        .iter()
+        .filter_map(|key| node.get(key))
+        .flat_map(|tests| tests.items())
+        .filter_map(extract_test)
+        .collect()
+}
+
+fn extract_test(node: &YamlNode) -> Option<GenericTest> {
+    if let Some(name) = node.as_str() {
+        return Some(GenericTest {
+            name: name.to_string(),
+            name_range: node.range,
+            args: Vec::new(),
+            range: node.range,
+        });
+    }
+    // a test with arguments is a mapping from its name to the arguments
+    let (name, args) = node.entries().first()?;
+    Some(GenericTest {
+        name: name.as_str()?.to_string(),
+        name_range: name.range,
+        args: args
+            .entries()
+            .iter()
+            .filter_map(|(arg, _)| arg.as_str())
+            .filter(|arg| !matches!(*arg, "config" | "name"))
+            .map(|arg| arg.to_string())
+            .collect(),
+        range: node.range,
+    })
+}
+
+fn extract_columns(node: &YamlNode) -> Vec<Column> {
+    node.get("columns")
+        .map_or(&[][..], |columns| columns.items())
+        .iter()
+        .filter_map(|column| {
+            let (name, name_range) = extract_name(column)?;
+            Some(Column {
+                name,
+                name_range,
+                range: column.range,
+                description: column.get_str("description"),
+                data_type: column.get_str("data_type"),
+                tests: extract_tests(column),
+                config: column.get("config").cloned(),
+                meta: column.get("meta").cloned(),
+            })
+        })
+        .collect()
+}
+
+fn extract_node_properties(node: &YamlNode) -> Option<NodeProperties> {
+    let (name, name_range) = extract_name(node)?;
+    Some(NodeProperties {
+        name,
+        name_range,
+        range: node.range,
+        description: node.get_str("description"),
+        columns: extract_columns(node),
+        tests: extract_tests(node),
+        config: node.get("config").cloned(),
+        meta: node.get("meta").cloned(),
+    })
+}
+
+fn extract_source(node: &YamlNode) -> Option<Source> {
+    let (name, name_range) = extract_name(node)?;
+    let loaded_at_field = node.get_str("loaded_at_field");
+    let freshness = node.get("freshness").cloned();
+    Some(Source {
+        name,
+        name_range,
+        range: node.range,
+        description: node.get_str("description"),
+        database: node.get_str("database"),
+        schema: node.get_str("schema"),
+        tables: node
+            .get("tables")
+            .map_or(&[][..], |tables| tables.items())
+            .iter()
+            .filter_map(|table| extract_source_table(table, &loaded_at_field, &freshness))
+            .collect(),
+        loaded_at_field,
+        freshness,
+        config: node.get("config").cloned(),
+        meta: node.get("meta").cloned(),
+    })
+}
+
+fn extract_source_table(
+    node: &YamlNode,
+    source_loaded_at_field: &Option<String>,
+    source_freshness: &Option<YamlNode>,
+) -> Option<SourceTable> {
+    let (name, name_range) = extract_name(node)?;
+    Some(SourceTable {
+        name,
+        name_range,
+        range: node.range,
+        identifier: node.get_str("identifier"),
+        description: node.get_str("description"),
+        loaded_at_field: node
+            .get_str("loaded_at_field")
+            .or_else(|| source_loaded_at_field.clone()),
+        // `freshness: null` on a table turns off the source's checks
+        freshness: node.get("freshness").or(source_freshness.as_ref()).cloned(),
+        columns: extract_columns(node),
+        tests: extract_tests(node),
+        config: node.get("config").cloned(),
+        meta: node.get("meta").cloned(),
+    })
+}
+
+fn extract_macro_properties(node: &YamlNode) -> Option<MacroProperties> {
+    let (name, name_range) = extract_name(node)?;
+    Some(MacroProperties {
+        name,
+        name_range,
+        range: node.range,
+        description: node.get_str("description"),
+        arguments: node
+            .get("arguments")
+            .map_or(&[][..], |arguments| arguments.items())
+            .iter()
+            .filter_map(|argument| {
+                let (name, name_range) = extract_name(argument)?;
+                Some(MacroArgument {
+                    name,
+                    name_range,
+                    data_type: argument.get_str("type"),
+                    description: argument.get_str("description"),
+                })
+            })
+            .collect(),
+        meta: node.get("meta").cloned(),
+    })
+}
+
+fn extract_exposure(node: &YamlNode) -> Option<Exposure> {
+    let (name, name_range) = extract_name(node)?;
+    Some(Exposure {
+        name,
+        name_range,
+        range: node.range,
+        exposure_type: node.get_str("type"),
+        label: node.get_str("label"),
+        description: node.get_str("description"),
+        url: node.get_str("url"),
+        depends_on: node
+            .get("depends_on")
+            .map_or(&[][..], |depends_on| depends_on.items())
+            .iter()
+            .filter_map(|dependency| Some((dependency.as_str()?.to_string(), dependency.range)))
+            .collect(),
+        config: node.get("config").cloned(),
+        meta: node.get("meta").cloned(),
+    })
+}
@@ -0,0 +1,416 @@
+//! YAML documents that keep the position of every value, which `serde_yaml`
+//! throws away.
+
+use std::collections::HashMap;
+
+use rowan::{TextRange, TextSize};
+use yaml_rust::parser::{Event, Parser};
+use yaml_rust::scanner::{Marker, ScanError, TScalarStyle};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum YamlValue {
+    Null,
+    Scalar(String),
+    Sequence(Vec<YamlNode>),
+    Mapping(Vec<(YamlNode, YamlNode)>),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct YamlNode {
+    pub value: YamlValue,
+    pub range: TextRange,
+}
+
+/// A syntax error, which stops the whole document from being parsed
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct YamlError {
+    pub offset: TextSize,
+    pub message: String,
+}
+
+impl YamlNode {
+    pub fn as_str(&self) -> Option<&str> {
+        match &self.value {
+            YamlValue::Scalar(value) => Some(value),
+            _ => None,
+        }
+    }
+
+    /// the items of a sequence, or nothing if this isn't one
+    pub fn items(&self) -> &[YamlNode] {
+        match &self.value {
+            YamlValue::Sequence(items) => items,
+            _ => &[],
+        }
+    }
+
+    /// the entries of a mapping, or nothing if this isn't one
+    pub fn entries(&self) -> &[(YamlNode, YamlNode)] {
+        match &self.value {
+            YamlValue::Mapping(entries) => entries,
+            _ => &[],
+        }
+    }
+
+    /// the key and value of a mapping entry with a string key
+    pub fn get_entry(&self, key: &str) -> Option<&(YamlNode, YamlNode)> {
+        self.entries()
+            .iter()
+            .find(|(entry_key, _)| entry_key.as_str() == Some(key))
+    }
+
+    pub fn get(&self, key: &str) -> Option<&YamlNode> {
+        self.get_entry(key).map(|(_, value)| value)
+    }
+
+    pub fn get_str(&self, key: &str) -> Option<String> {
+        self.get(key)
+            .and_then(|value| value.as_str())
+            .map(|value| value.to_string())
+    }
+}
+
+/// Parses the first document in `text`, returning `None` if it's empty
+pub fn parse_yaml(text: &str) -> Result<Option<YamlNode>, YamlError> {
+    let mut loader = Loader {
+        parser: Parser::new(text.chars()),
+        text,
+        char_offsets: if text.is_ascii() {
+            None
+        } else {
+            Some(text.char_indices().map(|(offset, _)| offset).collect())
+        },
+        anchors: HashMap::new(),
+    };
+    loader.load().map_err(|e| YamlError {
+        offset: loader.offset(e.marker()),
+        message: e.to_string(),
+    })
+}
+
+struct Loader<'a> {
+    parser: Parser<std::str::Chars<'a>>,
+    text: &'a str,
+    /// byte offset of each char, since markers count chars. Not needed when
+    /// the text is all ASCII.
+    char_offsets: Option<Vec<usize>>,
+    anchors: HashMap<usize, YamlNode>,
+}
+
+impl<'a> Loader<'a> {
+    fn load(&mut self) -> Result<Option<YamlNode>, ScanError> {
+        loop {
+            let (event, mark) = self.parser.next()?;
+            match event {
+                Event::StreamEnd => return Ok(None),
+                Event::StreamStart | Event::DocumentStart => continue,
+                _ => return self.load_node(event, mark).map(Some),
+            }
+        }
+    }
+
+    fn load_node(&mut self, event: Event, mark: Marker) -> Result<YamlNode, ScanError> {
+        let start = self.offset(&mark);
+        let (node, anchor) = match event {
+            Event::Alias(anchor) => {
+                let node = match self.anchors.get(&anchor) {
+                    Some(node) => node.clone(),
+                    None => YamlNode {
+                        value: YamlValue::Null,
+                        range: TextRange::empty(start),
+                    },
+                };
+                (node, 0)
+            }
+            Event::Scalar(value, style, anchor, _) => {
+                let end = self.scalar_end(start, &value, style)?;
+                let value = if style == TScalarStyle::Plain
+                    && matches!(value.as_str(), "~" | "null" | "Null" | "NULL" | "")
+                {
+                    YamlValue::Null
+                } else {
+                    YamlValue::Scalar(value)
+                };
+                let node = YamlNode {
+                    value,
+                    range: TextRange::new(start, end.max(start)),
+                };
+                (node, anchor)
+            }
+            Event::SequenceStart(anchor) => {
+                let mut items = Vec::new();
+                let end = loop {
+                    let (event, mark) = self.parser.next()?;
+                    if event == Event::SequenceEnd {
+                        let last = items.last().map(|item: &YamlNode| item.range.end());
+                        break self.collection_end(&mark, ']', last);
+                    }
+                    items.push(self.load_node(event, mark)?);
+                };
+                // block collections are only marked once their first
+                // value has been scanned
+                let start = items
+                    .first()
+                    .map_or(start, |item| item.range.start().min(start));
+                let node = YamlNode {
+                    value: YamlValue::Sequence(items),
+                    range: TextRange::new(start, end.max(start)),
+                };
+                (node, anchor)
+            }
+            Event::MappingStart(anchor) => {
+                let mut entries = Vec::new();
+                let end = loop {
+                    let (event, mark) = self.parser.next()?;
+                    if event == Event::MappingEnd {
+                        let last = entries.last().map(|(key, value): &(YamlNode, YamlNode)| {
+                            key.range.end().max(value.range.end())
+                        });
+                        break self.collection_end(&mark, '}', last);
+                    }
+                    let key = self.load_node(event, mark)?;
+                    let (event, mark) = self.parser.next()?;
+                    let value = self.load_node(event, mark)?;
+                    entries.push((key, value));
+                };
+                let start = entries
+                    .first()
+                    .map_or(start, |(key, _)| key.range.start().min(start));
+                let node = YamlNode {
+                    value: YamlValue::Mapping(entries),
+                    range: TextRange::new(start, end.max(start)),
+                };
+                (node, anchor)
+            }
+            _ => (
+                YamlNode {
+                    value: YamlValue::Null,
+                    range: TextRange::empty(start),
+                },
+                0,
+            ),
+        };
+        if anchor != 0 {
+            self.anchors.insert(anchor, node.clone());
+        }
+        Ok(node)
+    }
+
+    fn offset(&self, mark: &Marker) -> TextSize {
+        let offset = match &self.char_offsets {
+            None => mark.index(),
+            Some(offsets) => offsets
+                .get(mark.index())
+                .copied()
+                .unwrap_or(self.text.len()),
+        };
+        TextSize::from(offset.min(self.text.len()) as u32)
+    }
+
+    /// Markers only point at the start of a value, so the end of a scalar
+    /// has to be found from the text
+    fn scalar_end(
+        &mut self,
+        start: TextSize,
+        value: &str,
+        style: TScalarStyle,
+    ) -> Result<TextSize, ScanError> {
+        let rest = &self.text[usize::from(start)..];
+        match style {
+            TScalarStyle::SingleQuoted | TScalarStyle::DoubleQuoted => {
+                let quote = if style == TScalarStyle::SingleQuoted {
+                    '\''
+                } else {
+                    '"'
+                };
+                let mut chars = rest.char_indices().skip(1).peekable();
+                while let Some((index, char)) = chars.next() {
+                    if style == TScalarStyle::DoubleQuoted && char == '\\' {
+                        chars.next();
+                    } else if char == quote {
+                        // a doubled quote is an escaped quote in single quotes
+                        if style == TScalarStyle::SingleQuoted
+                            && matches!(chars.peek(), Some((_, '\'')))
+                        {
+                            chars.next();
+                            continue;
+                        }
+                        return Ok(start + TextSize::from((index + 1) as u32));
+                    }
+                }
+                Ok(TextSize::of(self.text))
+            }
+            TScalarStyle::Plain if rest.starts_with(value) => Ok(start + TextSize::of(value)),
+            // block scalars and plain scalars that span lines end wherever
+            // the next value starts
+            _ => {
+                let next = self.parser.peek()?.1;
+                let end = self.trim_end(self.offset(&next));
+                if style == TScalarStyle::Plain {
+                    Ok(self.trim_comment(start, end))
+                } else {
+                    Ok(end)
+                }
+            }
+        }
+    }
+
+    /// The end mark of a block collection is wherever the next value
+    /// starts, so it ends with its `last` value instead, while a flow
+    /// collection's points at its closing bracket.
+    fn collection_end(&self, mark: &Marker, closing: char, last: Option<TextSize>) -> TextSize {
+        let offset = self.offset(mark);
+        if self.text[usize::from(offset)..].starts_with(closing) {
+            offset + TextSize::of(closing)
+        } else {
+            last.unwrap_or_else(|| self.trim_end(offset))
+        }
+    }
+
+    /// Moves the end of a plain scalar back over a `# comment` on its last
+    /// line. Any `#` after whitespace starts one, since plain scalars can't
+    /// have them.
+    fn trim_comment(&self, start: TextSize, end: TextSize) -> TextSize {
+        // empty scalars, like the value of `key:`, end before they start
+        if end <= start {
+            return end;
+        }
+        let end = usize::from(end);
+        let line_start = self.text[..end]
+            .rfind('\n')
+            .map_or(0, |i| i + 1)
+            .max(usize::from(start));
+        let line = &self.text[line_start..end];
+        match line
+            .match_indices('#')
+            .find(|(i, _)| line[..*i].ends_with([' ', '\t']))
+        {
+            Some((i, _)) => TextSize::from((line_start + line[..i].trim_end().len()) as u32),
+            None => TextSize::from(end as u32),
+        }
+    }
+
+    /// moves an offset back over any whitespace and comments before it
+    fn trim_end(&self, offset: TextSize) -> TextSize {
+        let mut end = usize::from(offset);
+        loop {
+            let trimmed = self.text[..end].trim_end();
+            let line_start = trimmed.rfind('\n').map_or(0, |i| i + 1);
+            // a comment takes up the rest of its line
+            if line_start < trimmed.len() && trimmed[line_start..].trim_start().starts_with('#') {
+                end = line_start;
+            } else {
+                return TextSize::from(trimmed.len() as u32);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(text: &str) -> YamlNode {
+        parse_yaml(text).unwrap().unwrap()
+    }
+
+    /// the text of a node's range
+    fn text_of<'a>(text: &'a str, node: &YamlNode) -> &'a str {
+        &text[node.range]
+    }
+
+    #[test]
+    fn test_scalar_ranges() {
+        let text = "name: orders\ndescription: 'it''s # not a comment'\nquoted: \"a\\\"b\"\n";
+        let root = parse(text);
+        let (key, value) = root.get_entry("name").unwrap();
+        assert_eq!(text_of(text, key), "name");
+        assert_eq!(text_of(text, value), "orders");
+        let description = root.get("description").unwrap();
+        assert_eq!(text_of(text, description), "'it''s # not a comment'");
+        assert_eq!(description.as_str(), Some("it's # not a comment"));
+        let quoted = root.get("quoted").unwrap();
+        assert_eq!(text_of(text, quoted), "\"a\\\"b\"");
+        assert_eq!(quoted.as_str(), Some("a\"b"));
+    }
+
+    #[test]
+    fn test_null_values() {
+        let root = parse("a:\nb: ~\nc: null\nd: ''\n");
+        assert_eq!(root.get("a").unwrap().value, YamlValue::Null);
+        assert_eq!(root.get("b").unwrap().value, YamlValue::Null);
+        assert_eq!(root.get("c").unwrap().value, YamlValue::Null);
+        assert_eq!(root.get_str("d"), Some("".to_string()));
+    }
+
+    #[test]
+    fn test_trailing_comments() {
+        let text = "a: one # c\nb: one\n  two # c\nc:\n  - x # c\n  - y # c\n# end\n";
+        let root = parse(text);
+        assert_eq!(text_of(text, root.get("a").unwrap()), "one");
+        let b = root.get("b").unwrap();
+        assert_eq!(text_of(text, b), "one\n  two");
+        assert_eq!(b.as_str(), Some("one two"));
+        let c = root.get("c").unwrap();
+        assert_eq!(text_of(text, c), "- x # c\n  - y");
+        let items: Vec<_> = c.items().iter().map(|item| text_of(text, item)).collect();
+        assert_eq!(items, vec!["x", "y"]);
+        assert!(text_of(text, &root).ends_with("- y"));
+    }
+
+    #[test]
+    fn test_block_scalars() {
+        let text = "a: |\n  x # not a comment\n  y\n\nb: >\n  folded\n  text\nc: 1\n";
+        let root = parse(text);
+        let a = root.get("a").unwrap();
+        assert_eq!(a.as_str(), Some("x # not a comment\ny\n"));
+        assert!(text_of(text, a).ends_with("x # not a comment\n  y"));
+        let b = root.get("b").unwrap();
+        assert_eq!(b.as_str(), Some("folded text\n"));
+        assert!(text_of(text, b).ends_with("folded\n  text"));
+    }
+
+    #[test]
+    fn test_collections() {
+        let text = "models:\n  - name: a\n    tags: [x, y] # c\n  - {name: b}\n";
+        let root = parse(text);
+        let models = root.get("models").unwrap();
+        assert_eq!(models.items().len(), 2);
+        let first = &models.items()[0];
+        assert_eq!(text_of(text, first), "name: a\n    tags: [x, y]");
+        assert_eq!(text_of(text, first.get("tags").unwrap()), "[x, y]");
+        assert_eq!(text_of(text, &models.items()[1]), "{name: b}");
+        assert_eq!(models.items()[1].get_str("name"), Some("b".to_string()));
+    }
+
+    #[test]
+    fn test_anchors() {
+        let text = "base: &base\n  x: 1\nother: *base\nmissing: *nope\n";
+        let result = parse_yaml(text);
+        // an alias to an unknown anchor is a syntax error
+        assert!(result.is_err());
+
+        let text = "base: &base\n  x: 1\nother: *base\n";
+        let root = parse(text);
+        let other = root.get("other").unwrap();
+        assert_eq!(other.get_str("x"), Some("1".to_string()));
+        assert_eq!(other, root.get("base").unwrap());
+    }
+
+    #[test]
+    fn test_non_ascii_offsets() {
+        let text = "\u{e9}t\u{e9}: \u{fc}ber # c\nname: \u{1f600} x\n";
+        let root = parse(text);
+        let (key, value) = &root.entries()[0];
+        assert_eq!(text_of(text, key), "\u{e9}t\u{e9}");
+        assert_eq!(text_of(text, value), "\u{fc}ber");
+        assert_eq!(text_of(text, root.get("name").unwrap()), "\u{1f600} x");
+    }
+
+    #[test]
+    fn test_errors() {
+        assert_eq!(parse_yaml("").unwrap(), None);
+        let error = parse_yaml("a: [1, 2\nb: 3\n").unwrap_err();
+        assert!(!error.message.is_empty());
+    }
+}
@@ -0,0 +1,82 @@
+use dbt_jinja_parser::parser::{SyntaxKind, SyntaxNode};
+use rowan::TextSize;
+use tower_lsp::lsp_types::{FoldingRange, FoldingRangeKind};
+
+use crate::line_index::{LineIndex, PositionEncoding};
+
+/// The blocks and multi-line comments in a model or macro file. Blocks with
+/// branches, like `if` with `elif` and `else`, fold each branch separately
+/// so the others stay visible.
+pub fn get_folding_ranges(
+    syntax_tree: &SyntaxNode,
+    line_index: &LineIndex,
+    encoding: PositionEncoding,
+) -> Vec<FoldingRange> {
+    let line = |offset: TextSize| line_index.position(offset, encoding).line;
+    let mut ranges = Vec::new();
+    for node in syntax_tree.descendants() {
+        match node.kind() {
+            SyntaxKind::Comment => {
+                let range = node.text_range();
+                push_range(
+                    &mut ranges,
+                    line(range.start()),
+                    line(range.end()),
+                    FoldingRangeKind::Comment,
+                );
+            }
+            SyntaxKind::StmtMacro
+            | SyntaxKind::StmtTest
+            | SyntaxKind::StmtMaterialization
+            | SyntaxKind::StmtIf
+            | SyntaxKind::StmtFor
+            | SyntaxKind::StmtCallBlock
+            | SyntaxKind::StmtFilterBlock
+            | SyntaxKind::StmtAssignBlock
+            | SyntaxKind::StmtDocs
+            | SyntaxKind::StmtSnapshot => {
+                let branches = node.children().filter(|child| {
+                    matches!(
+                        child.kind(),
+                        SyntaxKind::IfElif | SyntaxKind::IfElse | SyntaxKind::ForElse
+                    )
+                });
+                let mut start = line(node.text_range().start());
+                for branch in branches {
+                    let branch_start = line(branch.text_range().start());
+                    if branch_start > 0 {
+                        push_range(
+                            &mut ranges,
+                            start,
+                            branch_start - 1,
+                            FoldingRangeKind::Region,
+                        );
+                    }
+                    start = branch_start;
+                }
+                push_range(
+                    &mut ranges,
+                    start,
+                    line(node.text_range().end()),
+                    FoldingRangeKind::Region,
+                );
+            }
+            _ => {}
+        }
+    }
+    ranges
+}
+
+/// adds a range, unless it's all on one line and there's nothing to fold
+fn push_range(ranges: &mut Vec<FoldingRange>, start: u32, end: u32, kind: FoldingRangeKind) {
+    if end > start {
+        ranges.push(FoldingRange {
+            start_line: start,
+            start_character: None,
+            end_line: end,
+            end_character: None,
+            kind: Some(kind),
+            collapsed_text: None,
+        });
+    }
+}
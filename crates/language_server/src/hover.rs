@@ -0,0 +1,288 @@
+use dbt_jinja_parser::ast::{AstNode, CallStaticKwarg, ExprCall};
+use dbt_jinja_parser::parser::{SyntaxKind, SyntaxNode, SyntaxToken};
+use std::path::{Path, PathBuf};
+use tower_lsp::lsp_types::{Hover, HoverContents, MarkupContent, MarkupKind, Position};
+
+use crate::config::is_config_call;
+use crate::entity::{Column, DbtProject, Direction, GraphNode, NodeProperties, BUILTINS};
+use crate::files::yaml::YamlValue;
+
+/// a list item for a column or argument, e.g. `` - `id` (int): the key ``
+fn get_field_markdown(
+    name: &str,
+    data_type: &Option<String>,
+    description: &Option<String>,
+) -> String {
+    let mut item = format!("- `{}`", name);
+    if let Some(data_type) = data_type {
+        item.push_str(&format!(" ({})", data_type));
+    }
+    if let Some(description) = description {
+        item.push_str(&format!(": {}", description.trim()));
+    }
+    item
+}
+
+fn get_columns_markdown(columns: &[Column]) -> String {
+    let mut markdown = "Columns:".to_string();
+    for column in columns {
+        markdown.push('\n');
+        markdown.push_str(&get_field_markdown(
+            &column.name,
+            &column.data_type,
+            &column.description,
+        ));
+    }
+    markdown
+}
+
+/// Describes the builtin variable, object member, filter or test `token`
+/// names. Builtins that are called are described along with their call.
+fn get_builtin_hover(token: &SyntaxToken) -> Option<String> {
+    if token.kind() != SyntaxKind::Name {
+        return None;
+    }
+    let parent = token.parent()?;
+    let is_called = |node: &SyntaxNode| {
+        node.parent()
+            .is_some_and(|parent| parent.kind() == SyntaxKind::ExprCall)
+    };
+    let builtin = match parent.kind() {
+        SyntaxKind::ExprName if !is_called(&parent) => BUILTINS.get(None, token.text()),
+        SyntaxKind::Subscript => {
+            let get_attr = parent.parent()?;
+            let object = get_attr
+                .first_child()
+                .filter(|object| object.kind() == SyntaxKind::ExprName)?;
+            if is_called(&get_attr) {
+                return None;
+            }
+            BUILTINS.get(Some(&object.text().to_string()), token.text())
+        }
+        SyntaxKind::CallStaticKwarg => {
+            let kwarg = CallStaticKwarg::cast(parent.clone())?;
+            let call_node = ExprCall::cast(parent.parent()?.parent()?)?;
+            if kwarg.ident().as_ref() != Some(token) || !is_config_call(&call_node) {
+                return None;
+            }
+            BUILTINS.get_config(token.text())
+        }
+        SyntaxKind::ExprFilterName => BUILTINS.get_filter(token.text()),
+        SyntaxKind::ExprTest => match token.text() {
+            "is" | "not" => None,
+            name => BUILTINS.get_test(name),
+        },
+        _ => None,
+    };
+    Some(builtin?.get_hover_markdown())
+}
+
+impl DbtProject {
+    /// Describes the model, source or macro called under the cursor
+    pub fn get_hover(&self, path: PathBuf, position: Position) -> Option<Hover> {
+        let file = self.get_jinja_file(&path)?;
+        let (syntax_tree, line_index) = (file.syntax(), file.line_index());
+        let offset = line_index.offset(position, self.position_encoding);
+        syntax_tree.token_at_offset(offset).find_map(|leaf| {
+            let (value, range) = match get_builtin_hover(&leaf) {
+                Some(value) => (value, leaf.text_range()),
+                None => {
+                    let call_node = leaf.parent_ancestors().find_map(ExprCall::cast)?;
+                    (
+                        self.get_call_hover(&call_node)?,
+                        call_node.syntax().text_range(),
+                    )
+                }
+            };
+            Some(Hover {
+                contents: HoverContents::Markup(MarkupContent {
+                    kind: MarkupKind::Markdown,
+                    value,
+                }),
+                range: Some(line_index.range(range, self.position_encoding)),
+            })
+        })
+    }
+
+    fn get_call_hover(&self, call_node: &ExprCall) -> Option<String> {
+        let (namespace, name) = call_node.qualified_callee_name()?;
+        let args: Vec<_> = call_node
+            .static_args()
+            .map(|arg| arg.string_value())
+            .collect();
+        let hover = match (namespace.as_deref(), name.as_str(), args.as_slice()) {
+            (None, "ref", [Some(model_name)]) => self.get_model_hover(None, model_name),
+            (None, "ref", [Some(package_name), Some(model_name)]) => {
+                self.get_model_hover(Some(package_name), model_name)
+            }
+            (None, "source", [Some(source_name), Some(table_name)]) => {
+                self.get_source_hover(source_name, table_name)
+            }
+            (None, "ref" | "source", _) => None,
+            (namespace, name, _) => self
+                .resolve_macro_with(&self.spec.name, namespace, |project| {
+                    project.get_macro_hovers(name, &self.root_path)
+                })
+                .into_iter()
+                .next(),
+        };
+        // builtins come after the macros of the project and its packages
+        hover.or_else(|| {
+            BUILTINS
+                .get(namespace.as_deref(), &name)
+                .map(|builtin| builtin.get_hover_markdown())
+        })
+    }
+
+    fn get_model_hover(&self, package_name: Option<&str>, model_name: &str) -> Option<String> {
+        let graph = self.get_graph();
+        self.resolve_ref_with(package_name, |project| {
+            let model = project
+                .models
+                .iter()
+                .find(|model| model.value().name == model_name)?;
+            let node = GraphNode::Model {
+                package: project.spec.name.clone(),
+                name: model_name.to_string(),
+            };
+            let path = model
+                .key()
+                .strip_prefix(&self.root_path)
+                .unwrap_or(model.key());
+            let properties = project.find_model_properties(model_name);
+
+            let mut sections = vec![format!(
+                "**{}**\n\n`{}` in `{}`",
+                model_name,
+                path.display(),
+                project.spec.name
+            )];
+            // a `config()` call in the model wins over its properties
+            let materialized = model
+                .parsed_repr
+                .syntax()
+                .descendants()
+                .filter_map(ExprCall::cast)
+                .filter(|call_node| call_node.callee_name().as_deref() == Some("config"))
+                .find_map(|call_node| call_node.static_kwarg("materialized")?.string_value())
+                .or_else(|| {
+                    properties
+                        .as_ref()?
+                        .config
+                        .as_ref()?
+                        .get_str("materialized")
+                });
+            if let Some(materialized) = materialized {
+                sections.push(format!("Materialized as `{}`", materialized));
+            }
+            if let Some(description) = properties.as_ref().and_then(|p| p.description.as_ref()) {
+                sections.push(description.trim().to_string());
+            }
+            if let Some(properties) = &properties {
+                if !properties.columns.is_empty() {
+                    sections.push(get_columns_markdown(&properties.columns));
+                }
+            }
+            sections.push(format!(
+                "Upstream: {} · Downstream: {}",
+                graph.traverse(&node, Direction::Upstream, Some(1)).len(),
+                graph.traverse(&node, Direction::Downstream, Some(1)).len(),
+            ));
+            Some(sections.join("\n\n"))
+        })
+        .into_iter()
+        .next()
+    }
+
+    /// the first entry for a model in this project's property files
+    fn find_model_properties(&self, model_name: &str) -> Option<NodeProperties> {
+        let mut property_files: Vec<_> = self.properties.iter().collect();
+        property_files.sort_by(|a, b| a.key().cmp(b.key()));
+        property_files.iter().find_map(|property_file| {
+            property_file
+                .models
+                .iter()
+                .find(|model| model.name == model_name)
+                .cloned()
+        })
+    }
+
+    fn get_source_hover(&self, source_name: &str, table_name: &str) -> Option<String> {
+        self.flat_map_sources(&|path, property_file, source| {
+            if source.name != source_name {
+                return None;
+            }
+            let table = source
+                .tables
+                .iter()
+                .find(|table| table.name == table_name)?;
+            let path = path.strip_prefix(&self.root_path).unwrap_or(path);
+
+            let mut sections = vec![format!(
+                "**{}.{}**\n\n`{}`",
+                source_name,
+                table_name,
+                path.display()
+            )];
+            if let Some(identifier) = &table.identifier {
+                sections.push(format!("Identifier `{}`", identifier));
+            }
+            if let Some(description) = table.description.as_ref().or(source.description.as_ref()) {
+                sections.push(description.trim().to_string());
+            }
+            match &table.freshness {
+                Some(freshness) if freshness.value != YamlValue::Null => {
+                    let mut section = format!(
+                        "Freshness:\n```yaml\n{}\n```",
+                        property_file.snippet(freshness.range)
+                    );
+                    if let Some(loaded_at_field) = &table.loaded_at_field {
+                        section.push_str(&format!("\nLoaded at `{}`", loaded_at_field));
+                    }
+                    sections.push(section);
+                }
+                _ => (),
+            }
+            if !table.columns.is_empty() {
+                sections.push(get_columns_markdown(&table.columns));
+            }
+            Some(sections.join("\n\n"))
+        })
+        .into_iter()
+        .next()
+    }
+
+    /// descriptions of this project's macros called `macro_name`, ordered
+    /// by path, which is shown relative to `root_path`
+    fn get_macro_hovers(&self, macro_name: &str, root_path: &Path) -> Vec<String> {
+        let properties = self.find_macro_properties(macro_name);
+        self.find_macro_definitions(macro_name)
+            .into_iter()
+            .filter_map(|(path, mac)| {
+                let path = path.strip_prefix(root_path).unwrap_or(&path);
+                let mut sections = vec![
+                    format!("```jinja\n{{% macro {} %}}\n```", mac.signature()?),
+                    format!("`{}` in `{}`", path.display(), self.spec.name),
+                ];
+                if let Some(properties) = &properties {
+                    if let Some(description) = &properties.description {
+                        sections.push(description.trim().to_string());
+                    }
+                    if !properties.arguments.is_empty() {
+                        let mut arguments = "Arguments:".to_string();
+                        for argument in &properties.arguments {
+                            arguments.push('\n');
+                            arguments.push_str(&get_field_markdown(
+                                &argument.name,
+                                &argument.data_type,
+                                &argument.description,
+                            ));
+                        }
+                        sections.push(arguments);
+                    }
+                }
+                Some(sections.join("\n\n"))
+            })
+            .collect()
+    }
+}
@@ -0,0 +1,380 @@
+use rowan::{TextRange, TextSize};
+use tower_lsp::lsp_types::{Position, PositionEncodingKind, Range};
+
+/// The units that the `character` of an LSP position is counted in
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum PositionEncoding {
+    Utf8,
+    /// the only encoding every client has to support
+    #[default]
+    Utf16,
+    Utf32,
+}
+
+impl PositionEncoding {
+    /// Picks the client's most preferred encoding, falling back to UTF-16 if
+    /// the client didn't say or doesn't support anything else
+    pub fn negotiate(client_encodings: Option<&[PositionEncodingKind]>) -> Self {
+        client_encodings
+            .unwrap_or_default()
+            .iter()
+            .find_map(|kind| {
+                if *kind == PositionEncodingKind::UTF8 {
+                    Some(PositionEncoding::Utf8)
+                } else if *kind == PositionEncodingKind::UTF16 {
+                    Some(PositionEncoding::Utf16)
+                } else if *kind == PositionEncodingKind::UTF32 {
+                    Some(PositionEncoding::Utf32)
+                } else {
+                    None
+                }
+            })
+            .unwrap_or_default()
+    }
+
+    pub fn kind(self) -> PositionEncodingKind {
+        match self {
+            PositionEncoding::Utf8 => PositionEncodingKind::UTF8,
+            PositionEncoding::Utf16 => PositionEncodingKind::UTF16,
+            PositionEncoding::Utf32 => PositionEncodingKind::UTF32,
+        }
+    }
+
+    /// number of code units for a character that's `len_utf8` bytes long
+    fn char_len(self, len_utf8: u32) -> u32 {
+        match self {
+            PositionEncoding::Utf8 => len_utf8,
+            PositionEncoding::Utf16 => {
+                if len_utf8 == 4 {
+                    2
+                } else {
+                    1
+                }
+            }
+            PositionEncoding::Utf32 => 1,
+        }
+    }
+}
+
+/// `\n`, `\r\n` or a lone `\r`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct LineBreak {
+    offset: TextSize,
+    text: &'static str,
+}
+
+impl LineBreak {
+    fn end(&self) -> TextSize {
+        self.offset + TextSize::of(self.text)
+    }
+}
+
+/// a character that isn't a single byte in UTF-8
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct WideChar {
+    offset: TextSize,
+    len_utf8: u32,
+}
+
+/// Converts between offsets into a file and LSP positions.
+///
+/// Only line breaks and non-ASCII characters are tracked, so the index can
+/// be updated for an edit without looking at the rest of the file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LineIndex {
+    breaks: Vec<LineBreak>,
+    wide_chars: Vec<WideChar>,
+    len: TextSize,
+}
+
+fn scan(text: &str, base: TextSize, breaks: &mut Vec<LineBreak>, wide_chars: &mut Vec<WideChar>) {
+    let mut chars = text.char_indices().peekable();
+    while let Some((pos, char)) = chars.next() {
+        let offset = base + TextSize::from(pos as u32);
+        match char {
+            '\n' => breaks.push(LineBreak { offset, text: "\n" }),
+            '\r' => {
+                if matches!(chars.peek(), Some((_, '\n'))) {
+                    chars.next();
+                    breaks.push(LineBreak {
+                        offset,
+                        text: "\r\n",
+                    });
+                } else {
+                    breaks.push(LineBreak { offset, text: "\r" });
+                }
+            }
+            char if !char.is_ascii() => wide_chars.push(WideChar {
+                offset,
+                len_utf8: char.len_utf8() as u32,
+            }),
+            _ => (),
+        }
+    }
+}
+
+impl LineIndex {
+    pub fn new(text: &str) -> Self {
+        let mut breaks = Vec::new();
+        let mut wide_chars = Vec::new();
+        scan(text, 0.into(), &mut breaks, &mut wide_chars);
+        Self {
+            breaks,
+            wide_chars,
+            len: TextSize::of(text),
+        }
+    }
+
+    /// Updates the index after replacing the text in `delete` with `insert`
+    pub fn apply_edit(&mut self, delete: TextRange, insert: &str) {
+        let start = delete.start().min(self.len);
+        let end = delete.end().clamp(start, self.len);
+
+        // line breaks touching the edit may change, e.g. a '\r' right before
+        // it joining with a '\n' at the start of the inserted text. Their
+        // text is all we need to rescan them, since they're all that's
+        // between them and the edit.
+        let first = self.breaks.partition_point(|b| b.end() < start);
+        let last = self.breaks.partition_point(|b| b.offset <= end);
+        let affected = &self.breaks[first..last];
+        let mut window_start = start;
+        let mut window_text = String::new();
+        for b in affected.iter().filter(|b| b.offset < start) {
+            window_start = b.offset;
+            window_text.push_str(&b.text[..usize::from(start - b.offset).min(b.text.len())]);
+        }
+        window_text.push_str(insert);
+        for b in affected.iter().filter(|b| b.end() > end) {
+            window_text.push_str(&b.text[usize::from(end.max(b.offset) - b.offset)..]);
+        }
+
+        let mut new_breaks = Vec::new();
+        let mut new_wide_chars = Vec::new();
+        scan(
+            &window_text,
+            window_start,
+            &mut new_breaks,
+            &mut new_wide_chars,
+        );
+
+        let deleted_len = end - start;
+        let inserted_len = TextSize::of(insert);
+        for b in &mut self.breaks[last..] {
+            b.offset = b.offset - deleted_len + inserted_len;
+        }
+        self.breaks.splice(first..last, new_breaks);
+
+        let first_wide = self.wide_chars.partition_point(|w| w.offset < start);
+        let last_wide = self.wide_chars.partition_point(|w| w.offset < end);
+        for w in &mut self.wide_chars[last_wide..] {
+            w.offset = w.offset - deleted_len + inserted_len;
+        }
+        self.wide_chars
+            .splice(first_wide..last_wide, new_wide_chars);
+
+        self.len = self.len - deleted_len + inserted_len;
+    }
+
+    /// length of the whole text
+    pub fn text_len(&self) -> TextSize {
+        self.len
+    }
+
+    fn line_start(&self, line: usize) -> TextSize {
+        match line {
+            0 => 0.into(),
+            _ => self.breaks[line - 1].end(),
+        }
+    }
+
+    /// end of the line's content, before its line break
+    fn line_end(&self, line: usize) -> TextSize {
+        match self.breaks.get(line) {
+            Some(b) => b.offset,
+            None => self.len,
+        }
+    }
+
+    fn wide_chars_in(&self, range: TextRange) -> &[WideChar] {
+        let first = self
+            .wide_chars
+            .partition_point(|w| w.offset < range.start());
+        let last = self.wide_chars.partition_point(|w| w.offset < range.end());
+        &self.wide_chars[first..last]
+    }
+
+    /// Converts an offset into a position, clamping it to the end of the
+    /// text. Offsets in the middle of a character point to its start, and
+    /// ones between a `\r` and `\n` to the end of the line.
+    pub fn position(&self, offset: TextSize, encoding: PositionEncoding) -> Position {
+        let offset = offset.min(self.len);
+        let line = self.breaks.partition_point(|b| b.end() <= offset);
+        let line_start = self.line_start(line);
+        let offset = offset.min(self.line_end(line));
+        let mut character = u32::from(offset - line_start);
+        for w in self.wide_chars_in(TextRange::new(line_start, offset)) {
+            if w.offset + TextSize::from(w.len_utf8) > offset {
+                // the offset is in the middle of this character
+                character -= u32::from(offset - w.offset);
+                break;
+            }
+            character = character - w.len_utf8 + encoding.char_len(w.len_utf8);
+        }
+        Position {
+            line: line as u32,
+            character,
+        }
+    }
+
+    pub fn range(&self, range: TextRange, encoding: PositionEncoding) -> Range {
+        Range {
+            start: self.position(range.start(), encoding),
+            end: self.position(range.end(), encoding),
+        }
+    }
+
+    /// Converts a position into an offset. Positions past the end of a line
+    /// are clamped to the end of that line, and positions past the last line
+    /// to the end of the text. Positions in the middle of a character point
+    /// to its start.
+    pub fn offset(&self, position: Position, encoding: PositionEncoding) -> TextSize {
+        let line = position.line as usize;
+        if line > self.breaks.len() {
+            return self.len;
+        }
+        let line_start = self.line_start(line);
+        let line_end = self.line_end(line);
+        let mut remaining = position.character;
+        let mut cursor = line_start;
+        for w in self.wide_chars_in(TextRange::new(line_start, line_end)) {
+            let gap = u32::from(w.offset - cursor);
+            if remaining <= gap {
+                return cursor + TextSize::from(remaining);
+            }
+            remaining -= gap;
+            cursor = w.offset;
+            let units = encoding.char_len(w.len_utf8);
+            if remaining < units {
+                return cursor;
+            }
+            remaining -= units;
+            cursor += TextSize::from(w.len_utf8);
+        }
+        cursor + TextSize::from(remaining.min(u32::from(line_end - cursor)))
+    }
+
+    pub fn text_range(&self, range: Range, encoding: PositionEncoding) -> TextRange {
+        let start = self.offset(range.start, encoding);
+        let end = self.offset(range.end, encoding);
+        TextRange::new(start, end.max(start))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ENCODINGS: [PositionEncoding; 3] = [
+        PositionEncoding::Utf8,
+        PositionEncoding::Utf16,
+        PositionEncoding::Utf32,
+    ];
+
+    fn pos(line: u32, character: u32) -> Position {
+        Position { line, character }
+    }
+
+    /// applies an edit to both the text and its index
+    fn edit(text: &mut String, index: &mut LineIndex, start: u32, end: u32, insert: &str) {
+        text.replace_range(start as usize..end as usize, insert);
+        index.apply_edit(TextRange::new(start.into(), end.into()), insert);
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let text = "a\u{e9}b\n\u{1f600}x\r\n\u{4e2d}\ry";
+        let index = LineIndex::new(text);
+        for encoding in ENCODINGS {
+            // the offset between '\r' and '\n' is clamped to the line end
+            for (offset, _) in text.char_indices().filter(|(i, _)| *i != 11) {
+                let offset = TextSize::from(offset as u32);
+                let position = index.position(offset, encoding);
+                assert_eq!(index.offset(position, encoding), offset, "{:?}", encoding);
+            }
+        }
+    }
+
+    #[test]
+    fn test_wide_chars() {
+        // 'é' is 2 bytes, '😀' 4 bytes and a surrogate pair in UTF-16
+        let index = LineIndex::new("\u{e9}\u{1f600}x");
+        let x = TextSize::from(6);
+        assert_eq!(index.position(x, PositionEncoding::Utf8), pos(0, 6));
+        assert_eq!(index.position(x, PositionEncoding::Utf16), pos(0, 3));
+        assert_eq!(index.position(x, PositionEncoding::Utf32), pos(0, 2));
+        assert_eq!(index.offset(pos(0, 3), PositionEncoding::Utf16), x);
+        // in the middle of the surrogate pair
+        assert_eq!(index.offset(pos(0, 2), PositionEncoding::Utf16), 2.into());
+        // in the middle of the UTF-8 bytes of 'é'
+        assert_eq!(index.position(1.into(), PositionEncoding::Utf16), pos(0, 0));
+    }
+
+    #[test]
+    fn test_line_breaks() {
+        let index = LineIndex::new("ab\r\ncd\ref\ngh");
+        let encoding = PositionEncoding::Utf16;
+        assert_eq!(index.position(4.into(), encoding), pos(1, 0));
+        assert_eq!(index.position(7.into(), encoding), pos(2, 0));
+        assert_eq!(index.position(10.into(), encoding), pos(3, 0));
+        assert_eq!(index.offset(pos(2, 1), encoding), 8.into());
+        // between the '\r' and '\n'
+        assert_eq!(index.position(3.into(), encoding), pos(0, 2));
+    }
+
+    #[test]
+    fn test_clamping() {
+        let index = LineIndex::new("ab\r\ncd");
+        let encoding = PositionEncoding::Utf16;
+        assert_eq!(index.offset(pos(0, 10), encoding), 2.into());
+        assert_eq!(index.offset(pos(1, 10), encoding), 6.into());
+        assert_eq!(index.offset(pos(5, 0), encoding), 6.into());
+        assert_eq!(index.position(100.into(), encoding), pos(1, 2));
+        let range = Range {
+            start: pos(1, 1),
+            end: pos(0, 0),
+        };
+        assert_eq!(
+            index.text_range(range, encoding),
+            TextRange::empty(5.into())
+        );
+    }
+
+    #[test]
+    fn test_crlf_split_across_edits() {
+        let mut text = "ab".to_string();
+        let mut index = LineIndex::new(&text);
+        edit(&mut text, &mut index, 2, 2, "\r");
+        assert_eq!(index, LineIndex::new(&text));
+        edit(&mut text, &mut index, 3, 3, "\ncd");
+        assert_eq!(index, LineIndex::new(&text));
+        assert_eq!(index.position(4.into(), PositionEncoding::Utf16), pos(1, 0));
+
+        // and split again by inserting between them
+        edit(&mut text, &mut index, 3, 3, "x");
+        assert_eq!(index, LineIndex::new(&text));
+        assert_eq!(index.position(5.into(), PositionEncoding::Utf16), pos(2, 0));
+    }
+
+    #[test]
+    fn test_edits_with_wide_chars() {
+        let mut text = "\u{e9}a\nb\u{1f600}c\n".to_string();
+        let mut index = LineIndex::new(&text);
+        edit(&mut text, &mut index, 3, 5, "\u{4e2d}\r\n");
+        assert_eq!(index, LineIndex::new(&text));
+        edit(&mut text, &mut index, 0, 2, "");
+        assert_eq!(index, LineIndex::new(&text));
+        let len = text.len() as u32;
+        edit(&mut text, &mut index, len, len, "\u{1f600}");
+        assert_eq!(index, LineIndex::new(&text));
+    }
+}
@@ -0,0 +1,228 @@
+use dbt_jinja_parser::parser::Severity;
+use std::path::Path;
+use tower_lsp::lsp_types::Url;
+
+use crate::diagnostics::ProjectError;
+use crate::entity::{
+    resolve_ref_in, DbtProject, Dependency, Direction, GraphEdge, GraphNode, ProjectGraph,
+};
+use crate::files::model_file::ModelFile;
+use crate::files::property_file::PropertyFile;
+use crate::lsp_ext::{LineageItem, LineageItemKind};
+
+impl DbtProject {
+    pub(crate) fn rebuild_graph(&self) {
+        let graph = self.with_projects(Self::build_graph);
+        *self.graph.write().unwrap() = graph;
+    }
+
+    fn build_graph(projects: &[&DbtProject]) -> ProjectGraph {
+        let mut graph = ProjectGraph::default();
+        for project in projects {
+            for model in project.models.iter() {
+                graph.add_node(
+                    GraphNode::Model {
+                        package: project.spec.name.clone(),
+                        name: model.name.clone(),
+                    },
+                    model.key().clone(),
+                );
+            }
+            for property_file in project.properties.iter() {
+                Self::add_source_nodes(&mut graph, property_file.key(), &property_file);
+            }
+        }
+
+        let packages: Vec<&str> = projects
+            .iter()
+            .map(|project| project.spec.name.as_str())
+            .collect();
+        for project in projects {
+            for model in project.models.iter() {
+                let dependent = GraphNode::Model {
+                    package: project.spec.name.clone(),
+                    name: model.name.clone(),
+                };
+                let edges = Self::get_model_edges(&graph, &packages, &project.spec.name, &model);
+                graph.set_dependencies(dependent, edges);
+            }
+        }
+        graph
+    }
+
+    fn add_source_nodes(graph: &mut ProjectGraph, path: &Path, property_file: &PropertyFile) {
+        for source in &property_file.sources {
+            for table in &source.tables {
+                graph.add_node(
+                    GraphNode::Source {
+                        source: source.name.clone(),
+                        table: table.name.clone(),
+                    },
+                    path.to_path_buf(),
+                );
+            }
+        }
+    }
+
+    /// the edges from a model in the package `caller` to the nodes of
+    /// `graph` it depends on
+    fn get_model_edges(
+        graph: &ProjectGraph,
+        packages: &[&str],
+        caller: &str,
+        model: &ModelFile,
+    ) -> Vec<GraphEdge> {
+        model
+            .dependencies
+            .iter()
+            .filter_map(|dependency| match dependency {
+                Dependency::Ref {
+                    package,
+                    name,
+                    range,
+                } => {
+                    let node = resolve_ref_in(packages, caller, package.as_deref(), |i| {
+                        let node = GraphNode::Model {
+                            package: packages[i].to_string(),
+                            name: name.clone(),
+                        };
+                        graph.contains(&node).then_some(node)
+                    })
+                    .into_iter()
+                    .next()?;
+                    Some(GraphEdge {
+                        node,
+                        range: *range,
+                    })
+                }
+                Dependency::Source {
+                    source,
+                    table,
+                    range,
+                } => Some(GraphEdge {
+                    node: GraphNode::Source {
+                        source: source.clone(),
+                        table: table.clone(),
+                    },
+                    range: *range,
+                }),
+            })
+            .collect()
+    }
+
+    /// Updates the graph after a file changes. Only the edges of an edited
+    /// model are worked out again, unless a model was added or removed,
+    /// which can change what the refs of other models point to.
+    ///
+    /// Returns whether the diagnostics of other files may have changed,
+    /// which they can when the graph or the sources do.
+    pub(crate) fn update_graph(&self, path: &Path) -> bool {
+        if self.is_file_model(path) {
+            let model = match self.models.get(path) {
+                None => {
+                    self.rebuild_graph();
+                    return true;
+                }
+                Some(model) => model,
+            };
+            let node = GraphNode::Model {
+                package: self.spec.name.clone(),
+                name: model.name.clone(),
+            };
+            let mut graph = self.graph.write().unwrap();
+            if graph.path(&node) != Some(path) {
+                drop(graph);
+                drop(model);
+                self.rebuild_graph();
+                return true;
+            }
+            let edges = self.with_projects(|projects| {
+                let packages: Vec<&str> = projects
+                    .iter()
+                    .map(|project| project.spec.name.as_str())
+                    .collect();
+                Self::get_model_edges(&graph, &packages, &self.spec.name, &model)
+            });
+            graph.set_dependencies(node, edges)
+        } else if self.is_file_properties(path) {
+            let mut graph = self.graph.write().unwrap();
+            graph.remove_nodes_at(path);
+            if let Some(property_file) = self.properties.get(path) {
+                Self::add_source_nodes(&mut graph, path, &property_file);
+            }
+            true
+        } else {
+            false
+        }
+    }
+
+    /// the graph node for a model in this project
+    fn get_model_node(&self, path: &Path) -> Option<GraphNode> {
+        let model = self.models.get(path)?;
+        Some(GraphNode::Model {
+            package: self.spec.name.clone(),
+            name: model.name.clone(),
+        })
+    }
+
+    /// the models and sources upstream or downstream of a model
+    pub fn get_lineage(
+        &self,
+        path: &Path,
+        direction: Direction,
+        depth: Option<usize>,
+    ) -> Vec<LineageItem> {
+        let node = match self.get_model_node(path) {
+            None => return vec![],
+            Some(node) => node,
+        };
+        let graph = self.get_graph();
+        graph
+            .traverse(&node, direction, depth)
+            .into_iter()
+            .map(|(node, depth)| {
+                let uri = graph
+                    .path(&node)
+                    .and_then(|path| Url::from_file_path(path).ok());
+                let (kind, package) = match &node {
+                    GraphNode::Model { package, .. } => {
+                        (LineageItemKind::Model, Some(package.clone()))
+                    }
+                    GraphNode::Source { .. } => (LineageItemKind::Source, None),
+                };
+                LineageItem {
+                    kind,
+                    name: node.to_string(),
+                    package,
+                    uri,
+                    depth,
+                }
+            })
+            .collect()
+    }
+
+    /// Reports the `ref`s in a model that make it depend on itself
+    pub(crate) fn get_cycle_errors(&self, path: &Path) -> Vec<ProjectError> {
+        let node = match self.get_model_node(path) {
+            None => return vec![],
+            Some(node) => node,
+        };
+        self.get_graph()
+            .get_cycles_through(&node)
+            .into_iter()
+            .map(|(range, cycle)| ProjectError {
+                range,
+                severity: Severity::Error,
+                code: "dependency-cycle",
+                message: format!(
+                    "ref creates a cycle: {}",
+                    cycle
+                        .iter()
+                        .map(|node| node.to_string())
+                        .collect::<Vec<_>>()
+                        .join(" -> ")
+                ),
+            })
+            .collect()
+    }
+}
@@ -0,0 +1,42 @@
+//! Requests on top of the LSP spec, for things editors don't have a standard
+//! way to ask for
+
+use serde::{Deserialize, Serialize};
+use tower_lsp::lsp_types::{TextDocumentIdentifier, Url};
+
+/// `dbt/lineage`: the models and sources upstream or downstream of a model
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LineageParams {
+    pub text_document: TextDocumentIdentifier,
+    pub direction: LineageDirection,
+    /// how many edges away to look, or everything if not given
+    pub depth: Option<usize>,
+}
+
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LineageDirection {
+    Upstream,
+    Downstream,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LineageItemKind {
+    Model,
+    Source,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LineageItem {
+    pub kind: LineageItemKind,
+    /// the model's name, or `source.table` for sources
+    pub name: String,
+    /// the package a model belongs to
+    pub package: Option<String>,
+    pub uri: Option<Url>,
+    /// how many edges away from the requested model this is
+    pub depth: usize,
+}
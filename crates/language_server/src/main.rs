@@ -1,14 +1,33 @@
 use std::io;
+use std::sync::RwLock;
 
 use dashmap::DashMap;
 use tower_lsp::{LspService, Server};
 
+mod completion;
+mod config;
+mod definition;
+mod diagnostics;
+mod document;
 mod entity;
 mod files;
-mod position_finder;
+mod folding_ranges;
+mod hover;
+mod line_index;
+mod lineage;
+mod lsp_ext;
+mod references;
+mod rename;
+mod selection_ranges;
+mod semantic_tokens;
 mod server;
+mod signature_help;
+mod sources;
+mod symbols;
 mod utils;
+mod workspace_symbols;
 
+use crate::line_index::PositionEncoding;
 use crate::server::Backend;
 use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt};
 
@@ -32,7 +51,10 @@ async fn main() {
     let (service, socket) = LspService::build(|client| Backend {
         client,
         projects: DashMap::new(),
+        documents: DashMap::new(),
+        position_encoding: RwLock::new(PositionEncoding::default()),
     })
+    .custom_method("dbt/lineage", Backend::lineage)
     .finish();
 
     tracing::debug!("built lsp service");
@@ -0,0 +1,506 @@
+use dbt_jinja_parser::ast::{AstNode, ExprCall, ExprName};
+use dbt_jinja_parser::parser::{SyntaxKind, SyntaxNode};
+use rowan::{TextRange, TextSize};
+use std::path::{Path, PathBuf};
+use tower_lsp::lsp_types::{Location, Position, Range, Url};
+
+use crate::entity::{get_ref_target, DbtProject, ProjectNames};
+use crate::files::property_file::PropertyFile;
+use crate::line_index::LineIndex;
+
+/// Something declared in a project that other files can refer to
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum ProjectSymbol {
+    Model {
+        package: String,
+        name: String,
+    },
+    /// generic tests are macros called `test_<name>`
+    Macro {
+        package: String,
+        name: String,
+    },
+    Source {
+        source: String,
+        table: String,
+    },
+}
+
+/// Where a symbol is named in a file
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct NameRange {
+    range: TextRange,
+    /// generic tests are named without the `test_` prefix of their macro
+    is_test: bool,
+}
+
+impl NameRange {
+    fn new(range: TextRange) -> Self {
+        NameRange {
+            range,
+            is_test: false,
+        }
+    }
+}
+
+/// Whether the cursor at `offset` is on what `call_node` names: anywhere in
+/// a `ref` or `source`, but only on the name of a macro
+fn names_symbol_at(call_node: &ExprCall, offset: TextSize) -> bool {
+    match call_node.qualified_callee_name() {
+        None => false,
+        Some((None, callee)) if callee == "ref" || callee == "source" => true,
+        Some(_) => call_node
+            .callee()
+            .is_some_and(|callee| callee.syntax().text_range().contains_inclusive(offset)),
+    }
+}
+
+/// Where a symbol is named in the project
+#[derive(Debug, Clone)]
+pub(crate) struct Reference {
+    pub(crate) location: Location,
+    /// generic tests are named without the `test_` prefix of their macro
+    pub(crate) is_test: bool,
+}
+
+impl DbtProject {
+    /// Finds every use of the model, macro or source under the cursor across
+    /// the project and its installed packages. Anywhere else in a model
+    /// file, the model itself is meant.
+    pub fn get_references(
+        &self,
+        path: &Path,
+        position: Position,
+        include_declaration: bool,
+    ) -> Vec<Location> {
+        self.with_projects(|projects| {
+            let names = ProjectNames::new(projects);
+            let symbol = match Self::find_symbol(projects, path, position, &names) {
+                None => return vec![],
+                Some((symbol, _)) => symbol,
+            };
+            tracing::debug!(message = "finding references", ?symbol);
+            projects
+                .iter()
+                .flat_map(|project| project.find_references(&symbol, &names, include_declaration))
+                .map(|reference| reference.location)
+                .collect()
+        })
+    }
+
+    /// the symbol under the cursor in whichever project the file is in
+    pub(crate) fn find_symbol(
+        projects: &[&DbtProject],
+        path: &Path,
+        position: Position,
+        names: &ProjectNames,
+    ) -> Option<(ProjectSymbol, Reference)> {
+        projects
+            .iter()
+            .find_map(|project| project.get_symbol(path, position, names))
+    }
+
+    /// The symbol under the cursor in one of this project's files, along
+    /// with where its name is. Anywhere else in a model file stands for the
+    /// model, with an empty range at the cursor.
+    fn get_symbol(
+        &self,
+        path: &Path,
+        position: Position,
+        names: &ProjectNames,
+    ) -> Option<(ProjectSymbol, Reference)> {
+        let file = self.get_jinja_file(path)?;
+        let (syntax_tree, line_index) = (file.syntax(), file.line_index());
+        let uri = Url::from_file_path(path).ok()?;
+        let offset = line_index.offset(position, self.position_encoding);
+        let symbol = syntax_tree.token_at_offset(offset).find_map(|leaf| {
+            if let Some(name) = leaf.parent().and_then(ExprName::cast) {
+                if let Some(symbol) = self.get_definition_symbol(&name) {
+                    return Some(Some(symbol));
+                }
+            }
+            leaf.parent_ancestors()
+                .filter_map(ExprCall::cast)
+                .find(|call_node| names_symbol_at(call_node, offset))
+                .map(|call_node| self.get_call_symbol(&call_node, names))
+        });
+        match symbol {
+            // e.g. `ref('typo')` or a builtin like `config()`, which mustn't
+            // stand in for the model
+            Some(None) => None,
+            Some(Some((symbol, name_range))) => {
+                let reference = Reference {
+                    location: Location {
+                        uri,
+                        range: line_index.range(name_range.range, self.position_encoding),
+                    },
+                    is_test: name_range.is_test,
+                };
+                Some((symbol, reference))
+            }
+            None => {
+                let model = self.models.get(path)?;
+                let symbol = ProjectSymbol::Model {
+                    package: self.spec.name.clone(),
+                    name: model.name.clone(),
+                };
+                let reference = Reference {
+                    location: Location {
+                        uri,
+                        range: Range::new(position, position),
+                    },
+                    is_test: false,
+                };
+                Some((symbol, reference))
+            }
+        }
+    }
+
+    /// the macro or generic test whose name is `name_node`, if it's the
+    /// name in a definition
+    fn get_definition_symbol(&self, name_node: &ExprName) -> Option<(ProjectSymbol, NameRange)> {
+        let (name, is_test) = match name_node.syntax().parent()?.kind() {
+            SyntaxKind::MacroBlockStart => (name_node.name()?, false),
+            SyntaxKind::TestBlockStart => (format!("test_{}", name_node.name()?), true),
+            _ => return None,
+        };
+        let symbol = ProjectSymbol::Macro {
+            package: self.spec.name.clone(),
+            name,
+        };
+        let name_range = NameRange {
+            range: name_node.ident()?.text_range(),
+            is_test,
+        };
+        Some((symbol, name_range))
+    }
+
+    /// what a call in this project refers to, and where the name of that is
+    /// in the call
+    fn get_call_symbol(
+        &self,
+        call_node: &ExprCall,
+        names: &ProjectNames,
+    ) -> Option<(ProjectSymbol, NameRange)> {
+        match call_node.qualified_callee_name()? {
+            (None, callee) if callee == "ref" => {
+                let (package, name, model_arg) = get_ref_target(call_node)?;
+                let symbol = ProjectSymbol::Model {
+                    package: names.resolve_model(&self.spec.name, package.as_deref(), &name)?,
+                    name,
+                };
+                Some((symbol, NameRange::new(model_arg.string_value_range()?)))
+            }
+            (None, callee) if callee == "source" => {
+                let args: Vec<_> = call_node.static_args().collect();
+                let (source, table_arg) = match args.as_slice() {
+                    [source_arg, table_arg] => (source_arg.string_value()?, table_arg),
+                    _ => return None,
+                };
+                let symbol = ProjectSymbol::Source {
+                    source,
+                    table: table_arg.string_value()?,
+                };
+                Some((symbol, NameRange::new(table_arg.string_value_range()?)))
+            }
+            (namespace, name) => {
+                let symbol = ProjectSymbol::Macro {
+                    package: names.resolve_macro(&self.spec.name, namespace.as_deref(), &name)?,
+                    name,
+                };
+                Some((symbol, NameRange::new(call_node.callee_name_range()?)))
+            }
+        }
+    }
+
+    /// the uses of `symbol` in this project's files, ordered by path
+    pub(crate) fn find_references(
+        &self,
+        symbol: &ProjectSymbol,
+        names: &ProjectNames,
+        include_declaration: bool,
+    ) -> Vec<Reference> {
+        let mut references: Vec<(PathBuf, Vec<Reference>)> = Vec::new();
+        for model in self.models.iter() {
+            let mut ranges = self.find_jinja_references(&model.parsed_repr.syntax(), symbol, names);
+            if include_declaration {
+                if let ProjectSymbol::Model { package, name } = symbol {
+                    if package == &self.spec.name && name == &model.name {
+                        ranges.insert(0, NameRange::new(TextRange::empty(TextSize::from(0))));
+                    }
+                }
+            }
+            references.push((
+                model.key().clone(),
+                self.get_file_references(model.key(), &model.line_index, ranges),
+            ));
+        }
+        for macro_file in self.macros.iter() {
+            let syntax_tree = macro_file.parsed_repr.syntax();
+            let mut ranges = Vec::new();
+            if include_declaration {
+                ranges.extend(
+                    syntax_tree
+                        .descendants()
+                        .filter_map(ExprName::cast)
+                        .filter_map(|name_node| self.get_definition_symbol(&name_node))
+                        .filter(|(defined, _)| defined == symbol)
+                        .map(|(_, name_range)| name_range),
+                );
+            }
+            ranges.extend(self.find_jinja_references(&syntax_tree, symbol, names));
+            references.push((
+                macro_file.key().clone(),
+                self.get_file_references(macro_file.key(), &macro_file.line_index, ranges),
+            ));
+        }
+        for property_file in self.properties.iter() {
+            let ranges =
+                self.find_property_references(&property_file, symbol, names, include_declaration);
+            references.push((
+                property_file.key().clone(),
+                self.get_file_references(property_file.key(), &property_file.line_index, ranges),
+            ));
+        }
+        references.sort_by(|(a, _), (b, _)| a.cmp(b));
+        references
+            .into_iter()
+            .flat_map(|(_, references)| references)
+            .collect()
+    }
+
+    /// Where `symbol` is used in a model or macro file: the model's name in
+    /// a `ref`, the table's name in a `source` or the macro's name in a call
+    fn find_jinja_references(
+        &self,
+        syntax_tree: &SyntaxNode,
+        symbol: &ProjectSymbol,
+        names: &ProjectNames,
+    ) -> Vec<NameRange> {
+        syntax_tree
+            .descendants()
+            .filter_map(ExprCall::cast)
+            .filter_map(|call_node| {
+                let (namespace, callee) = call_node.qualified_callee_name()?;
+                let range = match (symbol, namespace, callee.as_str()) {
+                    (ProjectSymbol::Model { package, name }, None, "ref") => {
+                        let (ref_package, ref_name, model_arg) = get_ref_target(&call_node)?;
+                        let resolved = names.resolve_model(
+                            &self.spec.name,
+                            ref_package.as_deref(),
+                            &ref_name,
+                        )?;
+                        if &ref_name == name && &resolved == package {
+                            model_arg.string_value_range()
+                        } else {
+                            None
+                        }
+                    }
+                    (ProjectSymbol::Source { source, table }, None, "source") => {
+                        let args: Vec<_> = call_node.static_args().collect();
+                        match args.as_slice() {
+                            [source_arg, table_arg]
+                                if source_arg.string_value().as_ref() == Some(source)
+                                    && table_arg.string_value().as_ref() == Some(table) =>
+                            {
+                                table_arg.string_value_range()
+                            }
+                            _ => None,
+                        }
+                    }
+                    (ProjectSymbol::Macro { package, name }, namespace, callee)
+                        if callee == name =>
+                    {
+                        let resolved =
+                            names.resolve_macro(&self.spec.name, namespace.as_deref(), callee)?;
+                        if &resolved == package {
+                            call_node.callee_name_range()
+                        } else {
+                            None
+                        }
+                    }
+                    _ => None,
+                };
+                range.map(NameRange::new)
+            })
+            .collect()
+    }
+
+    /// Where `symbol` is used in a property file: the entry describing a
+    /// model or macro, generic tests using a `test_` macro and, as the
+    /// declaration, a source's table. Quotes around names and the package
+    /// of a test are left out.
+    fn find_property_references(
+        &self,
+        property_file: &PropertyFile,
+        symbol: &ProjectSymbol,
+        names: &ProjectNames,
+        include_declaration: bool,
+    ) -> Vec<NameRange> {
+        match symbol {
+            ProjectSymbol::Model { package, name } => {
+                if package != &self.spec.name {
+                    return vec![];
+                }
+                property_file
+                    .models
+                    .iter()
+                    .filter(|model| &model.name == name)
+                    .map(|model| NameRange::new(property_file.unquoted(model.name_range)))
+                    .collect()
+            }
+            ProjectSymbol::Macro { package, name } => {
+                let mut ranges: Vec<NameRange> = Vec::new();
+                if package == &self.spec.name {
+                    ranges.extend(
+                        property_file
+                            .macros
+                            .iter()
+                            .filter(|mac| &mac.name == name)
+                            .map(|mac| NameRange::new(property_file.unquoted(mac.name_range))),
+                    );
+                }
+                ranges.extend(property_file.tests().into_iter().filter_map(|test| {
+                    let (namespace, test_name) = match test.name.rsplit_once('.') {
+                        Some((namespace, test_name)) => (Some(namespace), test_name),
+                        None => (None, test.name.as_str()),
+                    };
+                    let macro_name = format!("test_{}", test_name);
+                    if &macro_name != name
+                        || names
+                            .resolve_macro(&self.spec.name, namespace, &macro_name)
+                            .as_ref()
+                            != Some(package)
+                    {
+                        return None;
+                    }
+                    let range = property_file.unquoted(test.name_range);
+                    Some(NameRange {
+                        range: TextRange::new(range.end() - TextSize::of(test_name), range.end()),
+                        is_test: true,
+                    })
+                }));
+                ranges
+            }
+            ProjectSymbol::Source { source, table } => {
+                if !include_declaration {
+                    return vec![];
+                }
+                property_file
+                    .sources
+                    .iter()
+                    .filter(|declared| &declared.name == source)
+                    .flat_map(|declared| &declared.tables)
+                    .filter(|declared| &declared.name == table)
+                    .map(|declared| NameRange::new(property_file.unquoted(declared.name_range)))
+                    .collect()
+            }
+        }
+    }
+
+    /// references in one of this project's files
+    fn get_file_references(
+        &self,
+        path: &Path,
+        line_index: &LineIndex,
+        ranges: Vec<NameRange>,
+    ) -> Vec<Reference> {
+        if ranges.is_empty() {
+            return vec![];
+        }
+        match Url::from_file_path(path) {
+            Ok(uri) => ranges
+                .into_iter()
+                .map(|name_range| Reference {
+                    location: Location {
+                        uri: uri.clone(),
+                        range: line_index.range(name_range.range, self.position_encoding),
+                    },
+                    is_test: name_range.is_test,
+                })
+                .collect(),
+            Err(_) => {
+                tracing::error!(message = "couldn't convert path to uri", ?path);
+                vec![]
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::entity::position_of;
+
+    const MODEL: &str = "{{ config(materialized='table') }}\nselect * from {{ ref('typo') }}";
+
+    fn project() -> DbtProject {
+        DbtProject::from_files(
+            "shop",
+            Path::new("/shop"),
+            &[
+                ("models/orders.sql", MODEL),
+                ("models/totals.sql", "select * from {{ ref('orders') }}"),
+            ],
+        )
+    }
+
+    #[test]
+    fn test_references_to_model() {
+        let project = project();
+        let path = Path::new("/shop/models/orders.sql");
+        let references = project.get_references(path, Position::new(1, 0), false);
+        let uris: Vec<_> = references.iter().map(|r| r.uri.path()).collect();
+        assert_eq!(uris, vec!["/shop/models/totals.sql"]);
+    }
+
+    #[test]
+    fn test_references_to_shadowed_macro() {
+        // the root project, the calling package and another package all
+        // define `cents`, and the root project's wins
+        let definition = "{% macro cents(column) %}{{ column }} * 100{% endmacro %}";
+        let model = "select {{ cents('amount') }}";
+        let files = [("macros/cents.sql", definition), ("models/m.sql", model)];
+        let project = DbtProject::from_files("shop", Path::new("/shop"), &files);
+        for package in ["a", "b"] {
+            let root_path = Path::new("/shop/dbt_packages").join(package);
+            let files = [("macros/cents.sql", definition), ("models/m.sql", model)];
+            let package = DbtProject::from_files(package, &root_path, &files);
+            project.packages.insert(root_path, package);
+        }
+        let position = position_of(model, "cents");
+
+        let links = project.get_declaration(PathBuf::from("/shop/models/m.sql"), position);
+        let targets: Vec<_> = links.iter().map(|link| link.target_uri.path()).collect();
+        assert_eq!(targets, vec!["/shop/macros/cents.sql"]);
+
+        let path = Path::new("/shop/dbt_packages/a/models/m.sql");
+        let references = project.get_references(path, position, true);
+        let uris: Vec<_> = references.iter().map(|r| r.uri.path()).collect();
+        assert_eq!(
+            uris,
+            vec![
+                "/shop/macros/cents.sql",
+                "/shop/models/m.sql",
+                "/shop/dbt_packages/a/models/m.sql",
+                "/shop/dbt_packages/b/models/m.sql",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_references_to_unresolved_ref() {
+        let project = project();
+        let path = Path::new("/shop/models/orders.sql");
+        let position = position_of(MODEL, "typo");
+        assert!(project.get_references(path, position, true).is_empty());
+    }
+
+    #[test]
+    fn test_references_to_builtin() {
+        let project = project();
+        let path = Path::new("/shop/models/orders.sql");
+        let position = position_of(MODEL, "config");
+        assert!(project.get_references(path, position, true).is_empty());
+    }
+}
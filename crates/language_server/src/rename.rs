@@ -0,0 +1,295 @@
+use std::path::Path;
+use tower_lsp::lsp_types::{
+    AnnotatedTextEdit, DocumentChangeOperation, DocumentChanges, Location, OneOf,
+    OptionalVersionedTextDocumentIdentifier, Position, PrepareRenameResponse, RenameFile,
+    ResourceOp, TextDocumentEdit, TextEdit, Url, WorkspaceEdit,
+};
+
+use crate::entity::{DbtProject, ProjectNames};
+use crate::references::ProjectSymbol;
+use crate::utils::is_sql_file;
+
+/// Replaces the text at each location with its new text, as one edit per
+/// file in the order the files first come up
+fn get_document_edits(replacements: Vec<(Location, String)>) -> Vec<DocumentChangeOperation> {
+    let mut edits: Vec<(Url, Vec<OneOf<TextEdit, AnnotatedTextEdit>>)> = Vec::new();
+    for (location, new_text) in replacements {
+        let edit = OneOf::Left(TextEdit::new(location.range, new_text));
+        match edits.iter_mut().find(|(uri, _)| uri == &location.uri) {
+            Some((_, file_edits)) => file_edits.push(edit),
+            None => edits.push((location.uri, vec![edit])),
+        }
+    }
+    edits
+        .into_iter()
+        .map(|(uri, edits)| {
+            DocumentChangeOperation::Edit(TextDocumentEdit {
+                text_document: OptionalVersionedTextDocumentIdentifier { uri, version: None },
+                edits,
+            })
+        })
+        .collect()
+}
+
+impl DbtProject {
+    /// Where the name of the model or macro under the cursor is and what it
+    /// is, or why it can't be renamed
+    pub fn prepare_rename(
+        &self,
+        path: &Path,
+        position: Position,
+    ) -> Result<Option<PrepareRenameResponse>, String> {
+        self.with_projects(|projects| {
+            let names = ProjectNames::new(projects);
+            let (symbol, at) = match Self::find_symbol(projects, path, position, &names) {
+                None => return Ok(None),
+                Some(found) => found,
+            };
+            let name = self.get_renameable_name(&symbol)?;
+            let placeholder = match name.strip_prefix("test_") {
+                Some(test_name) if at.is_test => test_name.to_string(),
+                _ => name,
+            };
+            Ok(Some(PrepareRenameResponse::RangeWithPlaceholder {
+                range: at.location.range,
+                placeholder,
+            }))
+        })
+    }
+
+    /// Renames the model or macro under the cursor everywhere it's used. A
+    /// model's file is renamed too.
+    pub fn rename(
+        &self,
+        path: &Path,
+        position: Position,
+        new_name: &str,
+    ) -> Result<Option<WorkspaceEdit>, String> {
+        self.with_projects(|projects| {
+            let names = ProjectNames::new(projects);
+            let (symbol, at) = match Self::find_symbol(projects, path, position, &names) {
+                None => return Ok(None),
+                Some(found) => found,
+            };
+            let old_name = self.get_renameable_name(&symbol)?;
+            match symbol {
+                ProjectSymbol::Model { .. } => self
+                    .get_model_rename_edit(&names, &old_name, new_name, true)
+                    .map(Some),
+                ProjectSymbol::Macro { .. } => {
+                    // a generic test is named without its macro's prefix
+                    let new_name = if at.is_test {
+                        format!("test_{}", new_name)
+                    } else {
+                        new_name.to_string()
+                    };
+                    self.get_macro_rename_edit(&names, &old_name, &new_name)
+                        .map(Some)
+                }
+                ProjectSymbol::Source { .. } => Ok(None),
+            }
+        })
+    }
+
+    /// The edits to make before a model file is renamed, since the model's
+    /// name comes from its file. Moving a model without renaming it doesn't
+    /// need any.
+    pub fn get_file_rename_edit(
+        &self,
+        old_path: &Path,
+        new_path: &Path,
+    ) -> Result<Option<WorkspaceEdit>, String> {
+        let old_name = match self.models.get(old_path) {
+            None => return Ok(None),
+            Some(model) => model.name.clone(),
+        };
+        let new_name = match new_path.file_stem() {
+            Some(stem) if is_sql_file(new_path) => stem.to_string_lossy().to_string(),
+            _ => return Ok(None),
+        };
+        if new_name == old_name {
+            return Ok(None);
+        }
+        self.with_projects(|projects| {
+            let names = ProjectNames::new(projects);
+            self.get_model_rename_edit(&names, &old_name, &new_name, false)
+                .map(Some)
+        })
+    }
+
+    /// The current name of a symbol, if it's one that can be renamed. Only
+    /// the root project's models and macros can be, since changes to
+    /// installed packages are lost the next time they're installed.
+    fn get_renameable_name(&self, symbol: &ProjectSymbol) -> Result<String, String> {
+        match symbol {
+            ProjectSymbol::Model { package, name } | ProjectSymbol::Macro { package, name }
+                if package == &self.spec.name =>
+            {
+                Ok(name.clone())
+            }
+            ProjectSymbol::Model { package, name } | ProjectSymbol::Macro { package, name } => {
+                Err(format!(
+                    "can't rename `{}` since it's in the package `{}` installed in `{}`, where changes are lost on `dbt deps`",
+                    name, package, self.spec.packages_install_path
+                ))
+            }
+            ProjectSymbol::Source { .. } => Err("sources can't be renamed".to_string()),
+        }
+    }
+
+    /// Renames one of this project's models everywhere it's used in this
+    /// project, and optionally its file. Like macros, `ref`s in installed
+    /// packages are left alone, since they'd be undone by the next install.
+    fn get_model_rename_edit(
+        &self,
+        names: &ProjectNames,
+        old_name: &str,
+        new_name: &str,
+        rename_file: bool,
+    ) -> Result<WorkspaceEdit, String> {
+        if new_name.is_empty()
+            || !new_name
+                .chars()
+                .all(|c| c.is_alphanumeric() || c == '_' || c == '-')
+        {
+            return Err(format!("`{}` isn't a valid model name", new_name));
+        }
+        if new_name != old_name
+            && names
+                .models
+                .contains(&(self.spec.name.clone(), new_name.to_string()))
+        {
+            return Err(format!("there's already a model called `{}`", new_name));
+        }
+        let symbol = ProjectSymbol::Model {
+            package: self.spec.name.clone(),
+            name: old_name.to_string(),
+        };
+        let edits = self
+            .find_references(&symbol, names, false)
+            .into_iter()
+            .map(|reference| (reference.location, new_name.to_string()))
+            .collect();
+        let mut operations = get_document_edits(edits);
+        if rename_file {
+            let old_path = self
+                .models
+                .iter()
+                .find(|model| model.name == old_name)
+                .map(|model| model.key().clone())
+                .ok_or_else(|| format!("couldn't find the file for model `{}`", old_name))?;
+            let new_path = old_path.with_file_name(format!("{}.sql", new_name));
+            let (old_uri, new_uri) = match (
+                Url::from_file_path(&old_path),
+                Url::from_file_path(&new_path),
+            ) {
+                (Ok(old_uri), Ok(new_uri)) => (old_uri, new_uri),
+                _ => return Err(format!("couldn't convert path={:?} to uri", old_path)),
+            };
+            // the file is renamed after its contents are edited, which
+            // refer to it by its old name
+            operations.push(DocumentChangeOperation::Op(ResourceOp::Rename(
+                RenameFile {
+                    old_uri,
+                    new_uri,
+                    options: None,
+                    annotation_id: None,
+                },
+            )));
+        }
+        Ok(WorkspaceEdit {
+            document_changes: Some(DocumentChanges::Operations(operations)),
+            ..Default::default()
+        })
+    }
+
+    /// Renames one of this project's macros, its calls and its entries in
+    /// property files. Calls in installed packages are left alone, since
+    /// changes there would be lost.
+    fn get_macro_rename_edit(
+        &self,
+        names: &ProjectNames,
+        old_name: &str,
+        new_name: &str,
+    ) -> Result<WorkspaceEdit, String> {
+        let mut chars = new_name.chars();
+        let is_identifier = chars.next().is_some_and(|c| c.is_alphabetic() || c == '_')
+            && chars.all(|c| c.is_alphanumeric() || c == '_');
+        if !is_identifier {
+            return Err(format!("`{}` isn't a valid macro name", new_name));
+        }
+        if new_name != old_name
+            && names
+                .macros
+                .contains(&(self.spec.name.clone(), new_name.to_string()))
+        {
+            return Err(format!("there's already a macro called `{}`", new_name));
+        }
+        let symbol = ProjectSymbol::Macro {
+            package: self.spec.name.clone(),
+            name: old_name.to_string(),
+        };
+        let references = self.find_references(&symbol, names, true);
+        let test_name = new_name.strip_prefix("test_");
+        let mut edits = Vec::new();
+        for reference in references {
+            let new_text = match (reference.is_test, test_name) {
+                (false, _) => new_name,
+                (true, Some(test_name)) => test_name,
+                (true, None) => {
+                    return Err(format!(
+                        "`{}` is used as a generic test, so its name has to start with `test_`",
+                        old_name
+                    ))
+                }
+            };
+            edits.push((reference.location, new_text.to_string()));
+        }
+        Ok(WorkspaceEdit {
+            document_changes: Some(DocumentChanges::Operations(get_document_edits(edits))),
+            ..Default::default()
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::entity::position_of;
+
+    const MODEL: &str = "{{ config(materialized='table') }}\nselect * from {{ ref('typo') }}";
+
+    fn project() -> DbtProject {
+        DbtProject::from_files("shop", Path::new("/shop"), &[("models/orders.sql", MODEL)])
+    }
+
+    #[test]
+    fn test_rename_unresolved_ref() {
+        let project = project();
+        let path = Path::new("/shop/models/orders.sql");
+        let position = position_of(MODEL, "typo");
+        assert_eq!(project.prepare_rename(path, position), Ok(None));
+        assert_eq!(project.rename(path, position, "customers"), Ok(None));
+    }
+
+    #[test]
+    fn test_rename_builtin() {
+        let project = project();
+        let path = Path::new("/shop/models/orders.sql");
+        let position = position_of(MODEL, "config");
+        assert_eq!(project.prepare_rename(path, position), Ok(None));
+        assert_eq!(project.rename(path, position, "customers"), Ok(None));
+    }
+
+    #[test]
+    fn test_rename_model() {
+        let project = project();
+        let path = Path::new("/shop/models/orders.sql");
+        let response = project.prepare_rename(path, Position::new(1, 0)).unwrap();
+        assert!(matches!(
+            response,
+            Some(PrepareRenameResponse::RangeWithPlaceholder { placeholder, .. })
+                if placeholder == "orders"
+        ));
+    }
+}
@@ -0,0 +1,67 @@
+use dbt_jinja_parser::ast::{AstNode, ExprConstantString};
+use dbt_jinja_parser::parser::{SyntaxKind, SyntaxNode};
+use rowan::{TextRange, TextSize, TokenAtOffset};
+use tower_lsp::lsp_types::SelectionRange;
+
+use crate::line_index::{LineIndex, PositionEncoding};
+
+/// The ranges a selection at `offset` expands through, following the
+/// syntax tree out from the token there, e.g. from a string to the call
+/// it's an argument of, to the `{{ }}` around the call, and so on
+pub fn get_selection_range(
+    syntax_tree: &SyntaxNode,
+    line_index: &LineIndex,
+    encoding: PositionEncoding,
+    offset: TextSize,
+) -> SelectionRange {
+    // between two tokens, the one that isn't whitespace is more likely to
+    // be what's wanted
+    let token = match syntax_tree.token_at_offset(offset) {
+        TokenAtOffset::None => None,
+        TokenAtOffset::Single(token) => Some(token),
+        TokenAtOffset::Between(left, right) => {
+            if right.kind() == SyntaxKind::Whitespace {
+                Some(left)
+            } else {
+                Some(right)
+            }
+        }
+    };
+
+    let mut ranges: Vec<TextRange> = Vec::new();
+    let parent = match token {
+        None => Some(syntax_tree.clone()),
+        Some(token) => {
+            // the contents of a string come before the string with quotes
+            if let Some(string) = token.parent().and_then(ExprConstantString::cast) {
+                ranges.extend(string.value_range());
+            }
+            if token.kind() != SyntaxKind::Whitespace {
+                ranges.push(token.text_range());
+            }
+            token.parent()
+        }
+    };
+    ranges.extend(
+        parent
+            .into_iter()
+            .flat_map(|parent| parent.ancestors())
+            .map(|node| node.text_range()),
+    );
+    ranges.dedup();
+
+    let empty = SelectionRange {
+        range: line_index.range(TextRange::empty(offset), encoding),
+        parent: None,
+    };
+    ranges
+        .into_iter()
+        .rev()
+        .fold(None, |parent, range| {
+            Some(SelectionRange {
+                range: line_index.range(range, encoding),
+                parent: parent.map(Box::new),
+            })
+        })
+        .unwrap_or(empty)
+}
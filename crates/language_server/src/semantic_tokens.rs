@@ -0,0 +1,307 @@
+use std::collections::HashSet;
+
+use dbt_jinja_parser::ast::{AstNode, CallArguments, Expr, ExprCall};
+use dbt_jinja_parser::parser::{SyntaxKind, SyntaxNode, SyntaxToken};
+use rowan::{TextRange, TextSize};
+use tower_lsp::lsp_types::{
+    Position, SemanticToken, SemanticTokenModifier, SemanticTokenType, SemanticTokensLegend,
+};
+
+use crate::entity::BUILTINS;
+use crate::line_index::{LineIndex, PositionEncoding};
+
+/// The kinds of tokens we highlight, in the order they're listed in the
+/// legend. `delimiter`, `filter` and `test` aren't standard, so the VSCode
+/// extension says what they fall back to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum TokenType {
+    Delimiter,
+    Keyword,
+    Macro,
+    Function,
+    Namespace,
+    /// models, source tables, docs and snapshots
+    Class,
+    Variable,
+    Parameter,
+    Property,
+    Method,
+    Filter,
+    Test,
+    Operator,
+    String,
+    Number,
+    Comment,
+}
+
+const TOKEN_TYPES: [SemanticTokenType; 16] = [
+    SemanticTokenType::new("delimiter"),
+    SemanticTokenType::KEYWORD,
+    SemanticTokenType::MACRO,
+    SemanticTokenType::FUNCTION,
+    SemanticTokenType::NAMESPACE,
+    SemanticTokenType::CLASS,
+    SemanticTokenType::VARIABLE,
+    SemanticTokenType::PARAMETER,
+    SemanticTokenType::PROPERTY,
+    SemanticTokenType::METHOD,
+    SemanticTokenType::new("filter"),
+    SemanticTokenType::new("test"),
+    SemanticTokenType::OPERATOR,
+    SemanticTokenType::STRING,
+    SemanticTokenType::NUMBER,
+    SemanticTokenType::COMMENT,
+];
+
+/// bits of the modifier set, in the order they're listed in the legend
+const DECLARATION: u32 = 1 << 0;
+const DEFAULT_LIBRARY: u32 = 1 << 1;
+
+pub fn legend() -> SemanticTokensLegend {
+    SemanticTokensLegend {
+        token_types: TOKEN_TYPES.to_vec(),
+        token_modifiers: vec![
+            SemanticTokenModifier::DECLARATION,
+            SemanticTokenModifier::DEFAULT_LIBRARY,
+        ],
+    }
+}
+
+/// Classifies the Jinja in a model or macro file, optionally only in
+/// `range`. SQL is left alone so the editor's own grammar highlights it.
+/// `packages` are the names that can qualify a macro call, e.g. `dbt_utils`
+/// in `dbt_utils.star(...)`.
+pub fn get_semantic_tokens(
+    syntax_tree: &SyntaxNode,
+    line_index: &LineIndex,
+    encoding: PositionEncoding,
+    range: Option<TextRange>,
+    packages: &HashSet<String>,
+) -> Vec<SemanticToken> {
+    let mut tokens = Vec::new();
+    let mut previous = Position::default();
+    for token in syntax_tree
+        .descendants_with_tokens()
+        .filter_map(|element| element.into_token())
+    {
+        let token_range = token.text_range();
+        if let Some(range) = range {
+            if token_range.end() <= range.start() || range.end() <= token_range.start() {
+                continue;
+            }
+        }
+        let (token_type, modifiers) = match classify(&token, packages) {
+            None => continue,
+            Some(classified) => classified,
+        };
+        // clients don't have to support tokens spanning several lines
+        let mut line_start = token_range.start();
+        for line in token.text().split_inclusive('\n') {
+            let content = line.trim_end_matches(['\n', '\r']);
+            let start = line_index.position(line_start, encoding);
+            let end = line_index.position(line_start + TextSize::of(content), encoding);
+            line_start += TextSize::of(line);
+            if start == end {
+                continue;
+            }
+            let delta_line = start.line - previous.line;
+            tokens.push(SemanticToken {
+                delta_line,
+                delta_start: if delta_line == 0 {
+                    start.character - previous.character
+                } else {
+                    start.character
+                },
+                length: end.character - start.character,
+                token_type: token_type as u32,
+                token_modifiers_bitset: modifiers,
+            });
+            previous = start;
+        }
+    }
+    tokens
+}
+
+fn classify(token: &SyntaxToken, packages: &HashSet<String>) -> Option<(TokenType, u32)> {
+    let token_type = match token.kind() {
+        SyntaxKind::BlockBegin
+        | SyntaxKind::BlockEnd
+        | SyntaxKind::VariableBegin
+        | SyntaxKind::VariableEnd
+        | SyntaxKind::RawBegin
+        | SyntaxKind::RawEnd => TokenType::Delimiter,
+        SyntaxKind::CommentBegin | SyntaxKind::CommentData | SyntaxKind::CommentEnd => {
+            TokenType::Comment
+        }
+        SyntaxKind::StringLiteral => classify_string(token),
+        SyntaxKind::IntegerLiteral | SyntaxKind::FloatLiteral => TokenType::Number,
+        SyntaxKind::Name => return classify_name(token, packages),
+        SyntaxKind::Equal
+        | SyntaxKind::GreaterThan
+        | SyntaxKind::GreaterThanOrEqual
+        | SyntaxKind::LessThan
+        | SyntaxKind::LessThanOrEqual
+        | SyntaxKind::NotEqual
+        | SyntaxKind::Add
+        | SyntaxKind::Subtract
+        | SyntaxKind::Multiply
+        | SyntaxKind::Div
+        | SyntaxKind::FloorDiv
+        | SyntaxKind::Modulo
+        | SyntaxKind::Power
+        | SyntaxKind::Assign
+        | SyntaxKind::Pipe
+        | SyntaxKind::Tilde => TokenType::Operator,
+        _ => return None,
+    };
+    Some((token_type, 0))
+}
+
+/// The arguments of `ref()` and `source()` name models and source tables.
+/// With two arguments, the first is the package or source they're in.
+fn classify_string(token: &SyntaxToken) -> TokenType {
+    let argument = token
+        .parent()
+        .and_then(|string| string.parent())
+        .filter(|argument| argument.kind() == SyntaxKind::CallStaticArg);
+    let call = argument
+        .as_ref()
+        .and_then(|argument| argument.parent())
+        .and_then(CallArguments::cast)
+        .and_then(|arguments| arguments.syntax().parent())
+        .and_then(ExprCall::cast);
+    let (argument, call) = match (argument, call) {
+        (Some(argument), Some(call)) => (argument, call),
+        _ => return TokenType::String,
+    };
+    match call.callee_name().as_deref() {
+        Some("ref") | Some("source") => {
+            let args: Vec<_> = call.static_args().collect();
+            match args.as_slice() {
+                [first, _] if first.syntax() == &argument => TokenType::Namespace,
+                _ => TokenType::Class,
+            }
+        }
+        _ => TokenType::String,
+    }
+}
+
+fn classify_name(token: &SyntaxToken, packages: &HashSet<String>) -> Option<(TokenType, u32)> {
+    let parent = token.parent()?;
+    let classified = match parent.kind() {
+        SyntaxKind::ExprName => classify_expr_name(&parent, packages)?,
+        SyntaxKind::Subscript => {
+            let get_attr = parent.parent()?;
+            let is_callee = get_attr
+                .parent()
+                .is_some_and(|parent| parent.kind() == SyntaxKind::ExprCall);
+            let namespace = get_attr
+                .first_child()
+                .filter(|object| object.kind() == SyntaxKind::ExprName)
+                .map(|object| object.text().to_string());
+            match namespace {
+                Some(namespace) if is_callee && is_namespace(&namespace, packages) => {
+                    (TokenType::Macro, 0)
+                }
+                _ if is_callee => (TokenType::Method, 0),
+                _ => (TokenType::Property, 0),
+            }
+        }
+        // `ns.x` in `set ns.x = ...`
+        SyntaxKind::ExprNamespaceRef => {
+            let is_first = parent
+                .children_with_tokens()
+                .find(|child| child.kind() == SyntaxKind::Name)
+                .is_some_and(|first| first.as_token() == Some(token));
+            if is_first {
+                (TokenType::Variable, 0)
+            } else {
+                (TokenType::Property, 0)
+            }
+        }
+        SyntaxKind::ExprFilterName => (TokenType::Filter, 0),
+        SyntaxKind::ExprTest => match token.text() {
+            "is" | "not" => (TokenType::Keyword, 0),
+            _ => (TokenType::Test, 0),
+        },
+        SyntaxKind::CallStaticKwarg => (TokenType::Parameter, 0),
+        // tag names like `macro` and `endfor`, operators like `and`, and
+        // constants like `true` and `none`
+        _ => (TokenType::Keyword, 0),
+    };
+    Some(classified)
+}
+
+fn classify_expr_name(
+    name_node: &SyntaxNode,
+    packages: &HashSet<String>,
+) -> Option<(TokenType, u32)> {
+    let parent = name_node.parent()?;
+    let classified = match parent.kind() {
+        SyntaxKind::MacroBlockStart
+        | SyntaxKind::TestBlockStart
+        | SyntaxKind::MaterializationBlockStart => (TokenType::Macro, DECLARATION),
+        SyntaxKind::DocsBlockStart | SyntaxKind::SnapshotBlockStart => {
+            (TokenType::Class, DECLARATION)
+        }
+        SyntaxKind::SignatureArg | SyntaxKind::SignatureDefaultArg => {
+            (TokenType::Parameter, DECLARATION)
+        }
+        // arguments are inside `CallArguments`, so this is what's called
+        SyntaxKind::ExprCall => {
+            let name = name_node.text().to_string();
+            if BUILTINS
+                .get(None, &name)
+                .is_some_and(|builtin| builtin.is_callable())
+            {
+                (TokenType::Function, DEFAULT_LIBRARY)
+            } else {
+                (TokenType::Macro, 0)
+            }
+        }
+        SyntaxKind::ExprGetAttr => {
+            let name = name_node.text().to_string();
+            let is_called = parent
+                .parent()
+                .is_some_and(|parent| parent.kind() == SyntaxKind::ExprCall);
+            if is_called && is_namespace(&name, packages) {
+                let modifiers = if packages.contains(&name) {
+                    0
+                } else {
+                    DEFAULT_LIBRARY
+                };
+                (TokenType::Namespace, modifiers)
+            } else {
+                (TokenType::Variable, 0)
+            }
+        }
+        _ if is_assign_target(name_node) => (TokenType::Variable, DECLARATION),
+        _ => (TokenType::Variable, 0),
+    };
+    Some(classified)
+}
+
+/// whether macros can be called through `name`, like `dbt.type_string()`
+fn is_namespace(name: &str, packages: &HashSet<String>) -> bool {
+    name == "dbt" || packages.contains(name)
+}
+
+/// whether a name is set by `set` or a for loop, which have their targets
+/// before anything else
+fn is_assign_target(name_node: &SyntaxNode) -> bool {
+    let (target, statement) = match name_node.parent() {
+        Some(parent) if parent.kind() == SyntaxKind::ExprTuple => match parent.parent() {
+            Some(statement) => (parent, statement),
+            None => return false,
+        },
+        Some(parent) => (name_node.clone(), parent),
+        None => return false,
+    };
+    matches!(
+        statement.kind(),
+        SyntaxKind::StmtAssign | SyntaxKind::AssignBlockStart | SyntaxKind::ForStart
+    ) && statement
+        .children()
+        .find(|child| Expr::can_cast(child.kind()))
+        .is_some_and(|first| first == target)
+}
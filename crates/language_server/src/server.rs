@@ -1,21 +1,37 @@
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+use std::sync::RwLock;
 
 use dashmap::DashMap;
 use tower_lsp::{
     jsonrpc::Error,
     lsp_types::{
         request::{GotoDeclarationParams, GotoDeclarationResponse},
-        CompletionOptions, CompletionParams, CompletionResponse, DeclarationCapability,
+        CompletionOptions, CompletionParams, CompletionResponse, DeclarationCapability, Diagnostic,
         DidChangeTextDocumentParams, DidCloseTextDocumentParams, DidOpenTextDocumentParams,
-        InitializeParams, InitializeResult, MessageType, ServerCapabilities,
-        TextDocumentSyncCapability, TextDocumentSyncKind, Url,
+        DocumentChanges, DocumentSymbolParams, DocumentSymbolResponse, FileOperationFilter,
+        FileOperationPattern, FileOperationPatternKind, FileOperationRegistrationOptions,
+        FoldingRange, FoldingRangeParams, FoldingRangeProviderCapability, GotoDefinitionParams,
+        GotoDefinitionResponse, Hover, HoverParams, HoverProviderCapability, InitializeParams,
+        InitializeResult, Location, LocationLink, MessageType, OneOf, PrepareRenameResponse,
+        ReferenceParams, RenameFilesParams, RenameOptions, RenameParams, SelectionRange,
+        SelectionRangeParams, SelectionRangeProviderCapability, SemanticTokens,
+        SemanticTokensFullOptions, SemanticTokensOptions, SemanticTokensParams,
+        SemanticTokensRangeParams, SemanticTokensRangeResult, SemanticTokensResult,
+        SemanticTokensServerCapabilities, ServerCapabilities, SignatureHelp, SignatureHelpOptions,
+        SignatureHelpParams, SymbolInformation, TextDocumentPositionParams,
+        TextDocumentSyncCapability, TextDocumentSyncKind, Url, WorkspaceEdit,
+        WorkspaceFileOperationsServerCapabilities, WorkspaceServerCapabilities,
+        WorkspaceSymbolParams,
     },
     Client, LanguageServer,
 };
-use tracing::{event, field, info, Level};
 
 use crate::{
-    entity::DbtProject,
+    document::TextDocument,
+    entity::{DbtProject, Direction, BUILTINS},
+    line_index::PositionEncoding,
+    lsp_ext::{LineageDirection, LineageItem, LineageParams},
+    semantic_tokens,
     utils::{read_file, uri_to_path},
 };
 
@@ -24,18 +40,31 @@ type JsonRpcResult<T> = tower_lsp::jsonrpc::Result<T>;
 pub struct Backend {
     pub client: Client,
     pub projects: DashMap<PathBuf, DbtProject>,
+    /// Documents currently open in the editor
+    pub documents: DashMap<Url, TextDocument>,
+    /// What LSP positions are measured in, as negotiated in `initialize`
+    pub position_encoding: RwLock<PositionEncoding>,
 }
 
 #[tower_lsp::async_trait]
 impl LanguageServer for Backend {
     async fn initialize(&self, params: InitializeParams) -> JsonRpcResult<InitializeResult> {
-        tracing::debug!(message = "initializing");
+        // loading the builtins now means a broken catalog fails right away
+        tracing::debug!(message = "initializing", builtins = %BUILTINS.version);
         let root_uri = match params.root_uri {
             None => return Err(Error::invalid_params("language server requires root uri")),
             Some(uri) => uri,
         };
         let root_path = uri_to_path(&root_uri)?;
-        let project = match DbtProject::find_single_project(&root_path).await {
+        let position_encoding = PositionEncoding::negotiate(
+            params
+                .capabilities
+                .general
+                .as_ref()
+                .and_then(|general| general.position_encodings.as_deref()),
+        );
+        *self.position_encoding.write().unwrap() = position_encoding;
+        let project = match DbtProject::find_single_project(&root_path, position_encoding).await {
             Err(msg) => {
                 tracing::error!(message = "failed to find single project", ?root_path);
                 return Err(Error::invalid_params(format!(
@@ -51,16 +80,65 @@ impl LanguageServer for Backend {
         Ok(InitializeResult {
             server_info: None,
             capabilities: ServerCapabilities {
+                position_encoding: Some(position_encoding.kind()),
                 text_document_sync: Some(TextDocumentSyncCapability::Kind(
-                    TextDocumentSyncKind::FULL,
+                    TextDocumentSyncKind::INCREMENTAL,
                 )),
                 completion_provider: Some(CompletionOptions {
                     resolve_provider: Some(false),
-                    trigger_characters: Some(vec!["(".to_string()]),
+                    trigger_characters: Some(vec![
+                        "(".to_string(),
+                        ",".to_string(),
+                        ".".to_string(),
+                        "|".to_string(),
+                    ]),
                     work_done_progress_options: Default::default(),
                     all_commit_characters: None,
+                    completion_item: None,
                 }),
                 declaration_provider: Some(DeclarationCapability::Simple(true)),
+                definition_provider: Some(OneOf::Left(true)),
+                folding_range_provider: Some(FoldingRangeProviderCapability::Simple(true)),
+                hover_provider: Some(HoverProviderCapability::Simple(true)),
+                document_symbol_provider: Some(OneOf::Left(true)),
+                references_provider: Some(OneOf::Left(true)),
+                rename_provider: Some(OneOf::Right(RenameOptions {
+                    prepare_provider: Some(true),
+                    work_done_progress_options: Default::default(),
+                })),
+                selection_range_provider: Some(SelectionRangeProviderCapability::Simple(true)),
+                semantic_tokens_provider: Some(
+                    SemanticTokensServerCapabilities::SemanticTokensOptions(
+                        SemanticTokensOptions {
+                            legend: semantic_tokens::legend(),
+                            range: Some(true),
+                            full: Some(SemanticTokensFullOptions::Bool(true)),
+                            ..Default::default()
+                        },
+                    ),
+                ),
+                signature_help_provider: Some(SignatureHelpOptions {
+                    trigger_characters: Some(vec!["(".to_string(), ",".to_string()]),
+                    retrigger_characters: None,
+                    work_done_progress_options: Default::default(),
+                }),
+                workspace_symbol_provider: Some(OneOf::Left(true)),
+                workspace: Some(WorkspaceServerCapabilities {
+                    workspace_folders: None,
+                    file_operations: Some(WorkspaceFileOperationsServerCapabilities {
+                        will_rename: Some(FileOperationRegistrationOptions {
+                            filters: vec![FileOperationFilter {
+                                scheme: Some("file".to_string()),
+                                pattern: FileOperationPattern {
+                                    glob: "**/*.sql".to_string(),
+                                    matches: Some(FileOperationPatternKind::File),
+                                    options: None,
+                                },
+                            }],
+                        }),
+                        ..Default::default()
+                    }),
+                }),
                 ..ServerCapabilities::default()
             },
         })
@@ -75,23 +153,23 @@ impl LanguageServer for Backend {
             Err(_) => return,
             Ok(path) => path,
         };
-        let file_contents = match read_file(&path).await {
-            Err(e) => {
-                self.client
-                    .log_message(
-                        MessageType::ERROR,
-                        format!("failed to read newly opened file {e}"),
-                    )
-                    .await;
-                return;
-            }
-            Ok(contents) => contents,
-        };
+        // the editor's copy of the file is the source of truth while it's open
+        let file_contents = params.text_document.text;
+        self.documents.insert(
+            params.text_document.uri.clone(),
+            TextDocument::new(
+                &file_contents,
+                params.text_document.version,
+                *self.position_encoding.read().unwrap(),
+            ),
+        );
+        let mut project_changed = false;
         for project in self.projects.iter() {
             if path.starts_with(project.key()) {
                 match project.on_file_open(&path, &file_contents) {
-                    Ok(_) => (),
+                    Ok(changed) => project_changed |= changed,
                     Err(e) => {
+                        project_changed = true;
                         self.client
                             .log_message(
                                 MessageType::ERROR,
@@ -102,6 +180,11 @@ impl LanguageServer for Backend {
                 }
             }
         }
+        self.publish_diagnostics(params.text_document.uri, &path)
+            .await;
+        if project_changed {
+            self.publish_open_diagnostics(&path).await;
+        }
     }
 
     async fn did_change(&self, params: DidChangeTextDocumentParams) {
@@ -109,13 +192,35 @@ impl LanguageServer for Backend {
             Err(_) => return,
             Ok(path) => path,
         };
-        let file_contents = &params.content_changes[0].text;
+        let edits = match self.documents.get_mut(&params.text_document.uri) {
+            None => Err(format!(
+                "received change for unopened document uri={:?}",
+                params.text_document.uri
+            )),
+            Some(mut document) => {
+                document.apply_changes(params.text_document.version, params.content_changes)
+            }
+        };
+        let edits = match edits {
+            Err(e) => {
+                self.client
+                    .log_message(
+                        MessageType::WARNING,
+                        format!("ignoring change to document - {e}"),
+                    )
+                    .await;
+                return;
+            }
+            Ok(edits) => edits,
+        };
+        let mut project_changed = false;
         for project in self.projects.iter() {
             if path.starts_with(project.key()) {
                 tracing::info!(message="parsing project", project = ?project.key());
-                match project.on_file_change(&path, file_contents) {
-                    Ok(_) => (),
+                match project.on_file_change(&path, &edits) {
+                    Ok(changed) => project_changed |= changed,
                     Err(e) => {
+                        project_changed = true;
                         self.client
                             .log_message(
                                 MessageType::ERROR,
@@ -126,9 +231,15 @@ impl LanguageServer for Backend {
                 }
             }
         }
+        self.publish_diagnostics(params.text_document.uri, &path)
+            .await;
+        if project_changed {
+            self.publish_open_diagnostics(&path).await;
+        }
     }
 
     async fn did_close(&self, params: DidCloseTextDocumentParams) {
+        self.documents.remove(&params.text_document.uri);
         let path = match self.uri_to_path(&params.text_document.uri).await {
             Err(_) => return,
             Ok(path) => path,
@@ -151,11 +262,13 @@ impl LanguageServer for Backend {
             }
             Ok(contents) => Some(contents),
         };
+        let mut project_changed = false;
         for project in self.projects.iter() {
             if path.starts_with(project.key()) {
                 match project.on_file_close(path.clone(), &file_contents) {
-                    Ok(_) => (),
+                    Ok(changed) => project_changed |= changed,
                     Err(e) => {
+                        project_changed = true;
                         self.client
                             .log_message(
                                 MessageType::ERROR,
@@ -166,6 +279,13 @@ impl LanguageServer for Backend {
                 }
             }
         }
+        // diagnostics are only kept up to date for open files
+        self.client
+            .publish_diagnostics(params.text_document.uri, vec![], None)
+            .await;
+        if project_changed {
+            self.publish_open_diagnostics(&path).await;
+        }
     }
 
     async fn completion(
@@ -197,29 +317,301 @@ impl LanguageServer for Backend {
         &self,
         params: GotoDeclarationParams,
     ) -> JsonRpcResult<Option<GotoDeclarationResponse>> {
-        let current_uri = params.text_document_position_params.text_document.uri;
-        let path = self.uri_to_path(&current_uri).await?;
         Ok(Some(GotoDeclarationResponse::Link(
+            self.get_declaration(params.text_document_position_params)
+                .await?,
+        )))
+    }
+
+    async fn goto_definition(
+        &self,
+        params: GotoDefinitionParams,
+    ) -> JsonRpcResult<Option<GotoDefinitionResponse>> {
+        // models and macros are declared and defined in the same place
+        Ok(Some(GotoDefinitionResponse::Link(
+            self.get_declaration(params.text_document_position_params)
+                .await?,
+        )))
+    }
+
+    async fn hover(&self, params: HoverParams) -> JsonRpcResult<Option<Hover>> {
+        let position_params = params.text_document_position_params;
+        let path = self.uri_to_path(&position_params.text_document.uri).await?;
+        Ok(self
+            .projects
+            .iter()
+            .filter(|project| path.starts_with(project.key()))
+            .find_map(|project| project.get_hover(path.clone(), position_params.position)))
+    }
+
+    async fn document_symbol(
+        &self,
+        params: DocumentSymbolParams,
+    ) -> JsonRpcResult<Option<DocumentSymbolResponse>> {
+        let path = self.uri_to_path(&params.text_document.uri).await?;
+        Ok(self
+            .projects
+            .iter()
+            .filter(|project| path.starts_with(project.key()))
+            .find_map(|project| project.get_document_symbols(&path))
+            .map(DocumentSymbolResponse::Nested))
+    }
+
+    async fn folding_range(
+        &self,
+        params: FoldingRangeParams,
+    ) -> JsonRpcResult<Option<Vec<FoldingRange>>> {
+        let path = self.uri_to_path(&params.text_document.uri).await?;
+        Ok(self
+            .projects
+            .iter()
+            .filter(|project| path.starts_with(project.key()))
+            .find_map(|project| project.get_folding_ranges(&path)))
+    }
+
+    async fn selection_range(
+        &self,
+        params: SelectionRangeParams,
+    ) -> JsonRpcResult<Option<Vec<SelectionRange>>> {
+        let path = self.uri_to_path(&params.text_document.uri).await?;
+        Ok(self
+            .projects
+            .iter()
+            .filter(|project| path.starts_with(project.key()))
+            .find_map(|project| project.get_selection_ranges(&path, &params.positions)))
+    }
+
+    async fn references(&self, params: ReferenceParams) -> JsonRpcResult<Option<Vec<Location>>> {
+        let position_params = params.text_document_position;
+        let path = self.uri_to_path(&position_params.text_document.uri).await?;
+        Ok(Some(
             self.projects
                 .iter()
-                .filter_map(|project| {
-                    if path.starts_with(project.key()) {
-                        Some(project.get_declaration(
-                            path.clone(),
-                            params.text_document_position_params.position,
-                        ))
-                    } else {
-                        None
-                    }
+                .filter(|project| path.starts_with(project.key()))
+                .flat_map(|project| {
+                    project.get_references(
+                        &path,
+                        position_params.position,
+                        params.context.include_declaration,
+                    )
                 })
-                .flatten()
                 .collect(),
-        )))
-        // Err(Error::method_not_found())
+        ))
+    }
+
+    async fn prepare_rename(
+        &self,
+        params: TextDocumentPositionParams,
+    ) -> JsonRpcResult<Option<PrepareRenameResponse>> {
+        let path = self.uri_to_path(&params.text_document.uri).await?;
+        for project in self.projects.iter() {
+            if path.starts_with(project.key()) {
+                if let Some(response) = project
+                    .prepare_rename(&path, params.position)
+                    .map_err(Error::invalid_params)?
+                {
+                    return Ok(Some(response));
+                }
+            }
+        }
+        Ok(None)
+    }
+
+    async fn rename(&self, params: RenameParams) -> JsonRpcResult<Option<WorkspaceEdit>> {
+        let position_params = params.text_document_position;
+        let path = self.uri_to_path(&position_params.text_document.uri).await?;
+        for project in self.projects.iter() {
+            if path.starts_with(project.key()) {
+                if let Some(edit) = project
+                    .rename(&path, position_params.position, &params.new_name)
+                    .map_err(Error::invalid_params)?
+                {
+                    return Ok(Some(edit));
+                }
+            }
+        }
+        Ok(None)
+    }
+
+    async fn will_rename_files(
+        &self,
+        params: RenameFilesParams,
+    ) -> JsonRpcResult<Option<WorkspaceEdit>> {
+        let mut operations = Vec::new();
+        for file in params.files {
+            let (old_path, new_path) = match (Url::parse(&file.old_uri), Url::parse(&file.new_uri))
+            {
+                (Ok(old_uri), Ok(new_uri)) => (
+                    self.uri_to_path(&old_uri).await?,
+                    self.uri_to_path(&new_uri).await?,
+                ),
+                _ => continue,
+            };
+            for project in self.projects.iter() {
+                if !old_path.starts_with(project.key()) {
+                    continue;
+                }
+                match project.get_file_rename_edit(&old_path, &new_path) {
+                    Ok(Some(WorkspaceEdit {
+                        document_changes: Some(DocumentChanges::Operations(changes)),
+                        ..
+                    })) => operations.extend(changes),
+                    Ok(_) => (),
+                    Err(e) => {
+                        // the file is renamed either way, so there's no
+                        // point failing the request
+                        self.client
+                            .show_message(
+                                MessageType::WARNING,
+                                format!("couldn't update refs to renamed model - {e}"),
+                            )
+                            .await;
+                    }
+                }
+            }
+        }
+        if operations.is_empty() {
+            return Ok(None);
+        }
+        Ok(Some(WorkspaceEdit {
+            document_changes: Some(DocumentChanges::Operations(operations)),
+            ..Default::default()
+        }))
+    }
+
+    async fn symbol(
+        &self,
+        params: WorkspaceSymbolParams,
+    ) -> JsonRpcResult<Option<Vec<SymbolInformation>>> {
+        Ok(Some(
+            self.projects
+                .iter()
+                .flat_map(|project| project.get_workspace_symbols(&params.query))
+                .collect(),
+        ))
+    }
+
+    async fn semantic_tokens_full(
+        &self,
+        params: SemanticTokensParams,
+    ) -> JsonRpcResult<Option<SemanticTokensResult>> {
+        let path = self.uri_to_path(&params.text_document.uri).await?;
+        Ok(self
+            .projects
+            .iter()
+            .filter(|project| path.starts_with(project.key()))
+            .find_map(|project| project.get_semantic_tokens(&path, None))
+            .map(|data| {
+                SemanticTokensResult::Tokens(SemanticTokens {
+                    result_id: None,
+                    data,
+                })
+            }))
+    }
+
+    async fn semantic_tokens_range(
+        &self,
+        params: SemanticTokensRangeParams,
+    ) -> JsonRpcResult<Option<SemanticTokensRangeResult>> {
+        let path = self.uri_to_path(&params.text_document.uri).await?;
+        Ok(self
+            .projects
+            .iter()
+            .filter(|project| path.starts_with(project.key()))
+            .find_map(|project| project.get_semantic_tokens(&path, Some(params.range)))
+            .map(|data| {
+                SemanticTokensRangeResult::Tokens(SemanticTokens {
+                    result_id: None,
+                    data,
+                })
+            }))
+    }
+
+    async fn signature_help(
+        &self,
+        params: SignatureHelpParams,
+    ) -> JsonRpcResult<Option<SignatureHelp>> {
+        let position_params = params.text_document_position_params;
+        let path = self.uri_to_path(&position_params.text_document.uri).await?;
+        Ok(self
+            .projects
+            .iter()
+            .filter(|project| path.starts_with(project.key()))
+            .find_map(|project| project.get_signature_help(path.clone(), position_params.position)))
     }
 }
 
 impl Backend {
+    async fn publish_diagnostics(&self, uri: Url, path: &Path) {
+        let version = self.documents.get(&uri).map(|document| document.version());
+        let diagnostics: Option<Vec<Diagnostic>> = self
+            .projects
+            .iter()
+            .filter(|project| path.starts_with(project.key()))
+            .filter_map(|project| project.get_diagnostics(path, &uri))
+            .reduce(|mut acc, diagnostics| {
+                acc.extend(diagnostics);
+                acc
+            });
+        if let Some(diagnostics) = diagnostics {
+            self.client
+                .publish_diagnostics(uri, diagnostics, version)
+                .await;
+        }
+    }
+
+    /// Handles `dbt/lineage`
+    pub async fn lineage(&self, params: LineageParams) -> JsonRpcResult<Vec<LineageItem>> {
+        let path = self.uri_to_path(&params.text_document.uri).await?;
+        let direction = match params.direction {
+            LineageDirection::Upstream => Direction::Upstream,
+            LineageDirection::Downstream => Direction::Downstream,
+        };
+        Ok(self
+            .projects
+            .iter()
+            .filter(|project| path.starts_with(project.key()))
+            .flat_map(|project| project.get_lineage(&path, direction, params.depth))
+            .collect())
+    }
+
+    /// Republishes the diagnostics of every open file other than `changed`,
+    /// after a change to it that others can depend on, e.g. to a property
+    /// file declaring sources or to a model that's part of a cycle
+    async fn publish_open_diagnostics(&self, changed: &Path) {
+        let uris: Vec<Url> = self
+            .documents
+            .iter()
+            .map(|document| document.key().clone())
+            .collect();
+        for uri in uris {
+            if let Ok(path) = uri_to_path(&uri) {
+                if path != changed {
+                    self.publish_diagnostics(uri, &path).await;
+                }
+            }
+        }
+    }
+
+    async fn get_declaration(
+        &self,
+        params: TextDocumentPositionParams,
+    ) -> JsonRpcResult<Vec<LocationLink>> {
+        let path = self.uri_to_path(&params.text_document.uri).await?;
+        Ok(self
+            .projects
+            .iter()
+            .filter_map(|project| {
+                if path.starts_with(project.key()) {
+                    Some(project.get_declaration(path.clone(), params.position))
+                } else {
+                    None
+                }
+            })
+            .flatten()
+            .collect())
+    }
+
     async fn uri_to_path(&self, uri: &Url) -> Result<PathBuf, Error> {
         match uri_to_path(uri) {
             Ok(path) => Ok(path),
@@ -230,7 +622,7 @@ impl Backend {
                         format!("couldn't open file with uri={:?} due to {:?}", uri, e),
                     )
                     .await;
-                return Err(Error::parse_error());
+                Err(Error::parse_error())
             }
         }
     }
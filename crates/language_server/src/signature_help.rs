@@ -0,0 +1,105 @@
+use dbt_jinja_parser::ast::{ArgumentPosition, AstNode, CallArguments, ExprCall};
+use dbt_jinja_parser::parser::{SyntaxKind, SyntaxNode};
+use std::path::PathBuf;
+use tower_lsp::lsp_types::{Position, SignatureHelp, SignatureInformation};
+
+use crate::entity::{DbtProject, BUILTINS};
+
+/// Describes the filter or test whose arguments are `arguments`, e.g.
+/// `join` in `x | join(', ')`, marking the argument the cursor is at
+fn get_filter_signature_help(
+    arguments_parent: &SyntaxNode,
+    argument: &ArgumentPosition,
+) -> Option<SignatureHelp> {
+    let name = arguments_parent
+        .children_with_tokens()
+        .filter_map(|child| child.into_token())
+        .filter(|token| token.kind() == SyntaxKind::Name)
+        .last()?;
+    let builtin = match arguments_parent.kind() {
+        SyntaxKind::ExprFilterName => BUILTINS.get_filter(name.text()),
+        SyntaxKind::ExprTest => BUILTINS.get_test(name.text()),
+        _ => None,
+    }?;
+    let (signatures, active_signature) = builtin.get_signature_information(argument);
+    let active_parameter = signatures.get(active_signature)?.active_parameter;
+    Some(SignatureHelp {
+        signatures,
+        active_signature: Some(active_signature as u32),
+        active_parameter,
+    })
+}
+
+impl DbtProject {
+    /// Describes the call whose arguments the cursor is in, marking the
+    /// argument it's at
+    pub fn get_signature_help(&self, path: PathBuf, position: Position) -> Option<SignatureHelp> {
+        let file = self.get_jinja_file(&path)?;
+        let (syntax_tree, line_index) = (file.syntax(), file.line_index());
+        let offset = line_index.offset(position, self.position_encoding);
+        // the innermost call we know about, skipping any whose closing
+        // paren is before the cursor
+        syntax_tree.token_at_offset(offset).find_map(|leaf| {
+            leaf.parent_ancestors()
+                .filter_map(CallArguments::cast)
+                .find_map(|arguments| {
+                    let argument = arguments.argument_at(offset)?;
+                    let parent = arguments.syntax().parent()?;
+                    match ExprCall::cast(parent.clone()) {
+                        Some(call_node) => self.get_call_signature_help(&call_node, &argument),
+                        None => get_filter_signature_help(&parent, &argument),
+                    }
+                })
+        })
+    }
+
+    fn get_call_signature_help(
+        &self,
+        call_node: &ExprCall,
+        argument: &ArgumentPosition,
+    ) -> Option<SignatureHelp> {
+        let (namespace, name) = call_node.qualified_callee_name()?;
+        let mut signatures =
+            self.resolve_macro_with(&self.spec.name, namespace.as_deref(), |project| {
+                project.get_macro_signatures(&name, argument)
+            });
+        // builtins can have several signatures, of which the first that fits
+        // the argument is active, unless a macro came first
+        let mut active_signature = 0;
+        if let Some(builtin) = BUILTINS.get(namespace.as_deref(), &name) {
+            let (builtin_signatures, active) = builtin.get_signature_information(argument);
+            if signatures.is_empty() {
+                active_signature = active;
+            }
+            signatures.extend(builtin_signatures);
+        }
+        let active_parameter = signatures.get(active_signature)?.active_parameter;
+        Some(SignatureHelp {
+            signatures,
+            active_signature: Some(active_signature as u32),
+            active_parameter,
+        })
+    }
+
+    /// signatures of this project's macros called `macro_name`, ordered by
+    /// path
+    fn get_macro_signatures(
+        &self,
+        macro_name: &str,
+        argument: &ArgumentPosition,
+    ) -> Vec<SignatureInformation> {
+        let properties = self.find_macro_properties(macro_name);
+        self.find_macro_definitions(macro_name)
+            .into_iter()
+            .filter_map(|(_, mac)| {
+                let mut documentation = format!("Macro in `{}`", self.spec.name);
+                if let Some(description) = properties.as_ref().and_then(|p| p.description.as_ref())
+                {
+                    documentation.push_str("\n\n");
+                    documentation.push_str(description.trim());
+                }
+                mac.get_signature_information(documentation, argument)
+            })
+            .collect()
+    }
+}
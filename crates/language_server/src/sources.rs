@@ -0,0 +1,148 @@
+use dbt_jinja_parser::ast::{AstNode, ExprCall};
+use dbt_jinja_parser::parser::{Severity, SyntaxNode};
+use std::path::{Path, PathBuf};
+use tower_lsp::lsp_types::{CompletionItem, CompletionItemKind, Documentation};
+
+use crate::diagnostics::ProjectError;
+use crate::entity::{DbtProject, Source};
+use crate::files::property_file::PropertyFile;
+
+impl DbtProject {
+    pub(crate) fn get_source_completion(&self) -> Vec<CompletionItem> {
+        let mut sources = self.flat_map_sources(&|_, _, source| {
+            Some((source.name.clone(), source.description.clone()))
+        });
+        sources.sort();
+        sources.dedup_by(|(a, _), (b, _)| a == b);
+        sources
+            .into_iter()
+            .map(|(name, description)| CompletionItem {
+                label: name.clone(),
+                insert_text: Some(format!("'{}'", &name)),
+                kind: Some(CompletionItemKind::MODULE),
+                detail: Some("Source".to_string()),
+                documentation: description.map(Documentation::String),
+                sort_text: Some(format!("'{}'", &name)),
+                ..Default::default()
+            })
+            .collect()
+    }
+
+    pub(crate) fn get_source_table_completion(&self, source_name: &str) -> Vec<CompletionItem> {
+        let mut tables: Vec<_> = self
+            .flat_map_sources(&|_, _, source| {
+                if source.name == source_name {
+                    Some(
+                        source
+                            .tables
+                            .iter()
+                            .map(|table| (table.name.clone(), table.description.clone()))
+                            .collect::<Vec<_>>(),
+                    )
+                } else {
+                    None
+                }
+            })
+            .into_iter()
+            .flatten()
+            .collect();
+        tables.sort();
+        tables.dedup_by(|(a, _), (b, _)| a == b);
+        tables
+            .into_iter()
+            .map(|(name, description)| CompletionItem {
+                label: name.clone(),
+                insert_text: Some(format!("'{}'", &name)),
+                kind: Some(CompletionItemKind::FILE),
+                detail: Some(format!("Source table in {}", source_name)),
+                documentation: description.map(Documentation::String),
+                sort_text: Some(format!("'{}'", &name)),
+                ..Default::default()
+            })
+            .collect()
+    }
+
+    /// Checks that every `source('source', 'table')` in a file refers to a
+    /// declared source and table
+    pub(crate) fn get_source_errors(&self, syntax_tree: &SyntaxNode) -> Vec<ProjectError> {
+        let mut errors = Vec::new();
+        for call_node in syntax_tree.descendants().filter_map(ExprCall::cast) {
+            if call_node.callee_name().as_deref() != Some("source") {
+                continue;
+            }
+            let args: Vec<_> = call_node.static_args().collect();
+            let (source_arg, table_arg) = match args.as_slice() {
+                [source_arg, table_arg] => (source_arg, table_arg),
+                _ => continue,
+            };
+            let (source_name, table_name) =
+                match (source_arg.string_value(), table_arg.string_value()) {
+                    (Some(source_name), Some(table_name)) => (source_name, table_name),
+                    _ => continue,
+                };
+            let tables: Vec<Vec<String>> = self.flat_map_sources(&|_, _, source| {
+                if source.name == source_name {
+                    Some(
+                        source
+                            .tables
+                            .iter()
+                            .map(|table| table.name.clone())
+                            .collect(),
+                    )
+                } else {
+                    None
+                }
+            });
+            if tables.is_empty() {
+                errors.push(ProjectError {
+                    range: source_arg.syntax().text_range(),
+                    severity: Severity::Error,
+                    code: "unknown-source",
+                    message: format!("couldn't find a source named '{}'", source_name),
+                });
+            } else if !tables.iter().flatten().any(|table| table == &table_name) {
+                errors.push(ProjectError {
+                    range: table_arg.syntax().text_range(),
+                    severity: Severity::Error,
+                    code: "unknown-source-table",
+                    message: format!(
+                        "source '{}' doesn't have a table named '{}'",
+                        source_name, table_name
+                    ),
+                });
+            }
+        }
+        errors
+    }
+
+    /// Collects the results of `f` for every source that can be used in this
+    /// project, i.e. its own followed by those of installed packages
+    pub(crate) fn flat_map_sources<T>(
+        &self,
+        f: &dyn Fn(&Path, &PropertyFile, &Source) -> Option<T>,
+    ) -> Vec<T> {
+        let mut results = self.flat_map_own_sources(f);
+        results.extend(self.flat_map_packages(|package| package.flat_map_own_sources(f)));
+        results
+    }
+
+    fn flat_map_own_sources<T>(
+        &self,
+        f: &dyn Fn(&Path, &PropertyFile, &Source) -> Option<T>,
+    ) -> Vec<T> {
+        let mut results: Vec<(PathBuf, Vec<T>)> = self
+            .properties
+            .iter()
+            .map(|property_file| {
+                let results = property_file
+                    .sources
+                    .iter()
+                    .filter_map(|source| f(property_file.key(), property_file.value(), source))
+                    .collect();
+                (property_file.key().clone(), results)
+            })
+            .collect();
+        results.sort_by(|(a, _), (b, _)| a.cmp(b));
+        results.into_iter().flat_map(|(_, result)| result).collect()
+    }
+}
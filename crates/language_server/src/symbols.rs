@@ -0,0 +1,159 @@
+use dbt_jinja_parser::ast::{
+    AstNode, ExprCall, ExprName, StmtAssign, StmtAssignBlock, StmtDocs, StmtFor, StmtIf, StmtMacro,
+    StmtMaterialization, StmtSnapshot, StmtTest,
+};
+use dbt_jinja_parser::parser::SyntaxNode;
+use rowan::TextRange;
+use tower_lsp::lsp_types::{DocumentSymbol, SymbolKind};
+
+use crate::line_index::{LineIndex, PositionEncoding};
+
+/// Something to show in the outline, before its position is converted
+struct OutlineItem {
+    name: String,
+    detail: Option<String>,
+    kind: SymbolKind,
+    range: TextRange,
+    selection_range: TextRange,
+}
+
+/// The outline of a model or macro file: the blocks it defines, the
+/// variables it sets, its control flow and its `config()` calls, nested the
+/// way they are in the file
+pub fn get_document_symbols(
+    syntax_tree: &SyntaxNode,
+    line_index: &LineIndex,
+    encoding: PositionEncoding,
+) -> Vec<DocumentSymbol> {
+    syntax_tree
+        .children()
+        .flat_map(|node| to_symbols(&node, line_index, encoding))
+        .collect()
+}
+
+/// the symbols for a node, or for the nodes inside it if it isn't one
+#[allow(deprecated)] // `DocumentSymbol::deprecated` has to be set
+fn to_symbols(
+    node: &SyntaxNode,
+    line_index: &LineIndex,
+    encoding: PositionEncoding,
+) -> Vec<DocumentSymbol> {
+    let items = get_outline_items(node);
+    if items.is_empty() {
+        return get_document_symbols(node, line_index, encoding);
+    }
+    let mut children = Some(get_document_symbols(node, line_index, encoding))
+        .filter(|children| !children.is_empty());
+    items
+        .into_iter()
+        .map(|item| DocumentSymbol {
+            name: item.name,
+            detail: item.detail,
+            kind: item.kind,
+            tags: None,
+            deprecated: None,
+            range: line_index.range(item.range, encoding),
+            selection_range: line_index.range(item.selection_range, encoding),
+            // only assignments have several names, and nothing inside them
+            children: children.take(),
+        })
+        .collect()
+}
+
+fn get_outline_items(node: &SyntaxNode) -> Vec<OutlineItem> {
+    let range = node.text_range();
+    let named = |name: Option<ExprName>, detail, kind| {
+        let name = name?;
+        Some(OutlineItem {
+            name: name.name()?,
+            detail,
+            kind,
+            range,
+            selection_range: name.syntax().text_range(),
+        })
+    };
+    let item = if let Some(macro_node) = StmtMacro::cast(node.clone()) {
+        let signature = macro_node
+            .signature()
+            .map(|signature| collapse_whitespace(&signature.syntax().to_string()));
+        named(macro_node.name(), signature, SymbolKind::FUNCTION)
+    } else if let Some(test_node) = StmtTest::cast(node.clone()) {
+        let signature = test_node
+            .signature()
+            .map(|signature| collapse_whitespace(&signature.syntax().to_string()))
+            .unwrap_or_default();
+        named(
+            test_node.name(),
+            Some(format!("test{}", signature)),
+            SymbolKind::FUNCTION,
+        )
+    } else if let Some(materialization) = StmtMaterialization::cast(node.clone()) {
+        named(
+            materialization.name(),
+            Some(format!("materialization for {}", materialization.adapter())),
+            SymbolKind::CLASS,
+        )
+    } else if let Some(docs) = StmtDocs::cast(node.clone()) {
+        named(docs.name(), Some("docs".to_string()), SymbolKind::STRING)
+    } else if let Some(snapshot) = StmtSnapshot::cast(node.clone()) {
+        named(
+            snapshot.name(),
+            Some("snapshot".to_string()),
+            SymbolKind::OBJECT,
+        )
+    } else if let Some(assign) = StmtAssign::cast(node.clone()) {
+        return assign
+            .names()
+            .into_iter()
+            .filter_map(|name| named(Some(name), None, SymbolKind::VARIABLE))
+            .collect();
+    } else if let Some(assign_block) = StmtAssignBlock::cast(node.clone()) {
+        return assign_block
+            .names()
+            .into_iter()
+            .filter_map(|name| named(Some(name), None, SymbolKind::VARIABLE))
+            .collect();
+    } else if let Some(for_node) = StmtFor::cast(node.clone()) {
+        for_node.start().map(|start| region(range, start.syntax()))
+    } else if let Some(if_node) = StmtIf::cast(node.clone()) {
+        if_node.start().map(|start| region(range, start.syntax()))
+    } else if let Some(call_node) = ExprCall::cast(node.clone()) {
+        match (call_node.callee_name(), call_node.callee()) {
+            (Some(callee), Some(callee_node)) if callee == "config" => Some(OutlineItem {
+                name: callee,
+                detail: call_node
+                    .arguments()
+                    .map(|arguments| collapse_whitespace(&arguments.syntax().to_string())),
+                kind: SymbolKind::PROPERTY,
+                range,
+                selection_range: callee_node.syntax().text_range(),
+            }),
+            _ => None,
+        }
+    } else {
+        None
+    };
+    item.into_iter().collect()
+}
+
+/// A `for` or `if` block, named after its opening tag. LSP has no kind for
+/// control flow, so these show up as namespaces.
+fn region(range: TextRange, start: &SyntaxNode) -> OutlineItem {
+    let tag = start.to_string();
+    let tag = tag
+        .trim()
+        .trim_start_matches("{%")
+        .trim_end_matches("%}")
+        .trim_matches('-');
+    OutlineItem {
+        name: collapse_whitespace(tag),
+        detail: None,
+        kind: SymbolKind::NAMESPACE,
+        range,
+        selection_range: start.text_range(),
+    }
+}
+
+fn collapse_whitespace(text: &str) -> String {
+    text.split_whitespace().collect::<Vec<_>>().join(" ")
+}
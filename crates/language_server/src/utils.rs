@@ -3,7 +3,8 @@ use std::{
     path::{Path, PathBuf},
 };
 
-use dbt_jinja_parser::parser::{Lang, SyntaxKind};
+use dbt_jinja_parser::parser::Lang;
+use rowan::{TextRange, TextSize};
 use tokio::fs::read;
 use tower_lsp::{jsonrpc::Error, lsp_types::Url};
 
@@ -16,7 +17,7 @@ pub async fn read_file(path: &Path) -> Result<String, String> {
 
     match String::from_utf8(raw_bytes) {
         Ok(res) => Ok(res),
-        Err(e) => return Err(format!("couldn't read file as utf-8: {:?}", e)),
+        Err(e) => Err(format!("couldn't read file as utf-8: {:?}", e)),
     }
 }
 
@@ -46,29 +47,85 @@ pub fn print_node(node: SyntaxNode, indent: usize) {
     })
 }
 
+/// Finds the smallest edit that turns `old` into `new`, as the range of `old`
+/// to delete and the text from `new` to insert in its place
+pub fn get_text_edit<'a>(old: &str, new: &'a str) -> (TextRange, &'a str) {
+    let mut prefix = old
+        .bytes()
+        .zip(new.bytes())
+        .take_while(|(a, b)| a == b)
+        .count();
+    while !old.is_char_boundary(prefix) || !new.is_char_boundary(prefix) {
+        prefix -= 1;
+    }
+    let mut suffix = old[prefix..]
+        .bytes()
+        .rev()
+        .zip(new[prefix..].bytes().rev())
+        .take_while(|(a, b)| a == b)
+        .count();
+    while !old.is_char_boundary(old.len() - suffix) || !new.is_char_boundary(new.len() - suffix) {
+        suffix -= 1;
+    }
+    (
+        TextRange::new(
+            TextSize::from(prefix as u32),
+            TextSize::from((old.len() - suffix) as u32),
+        ),
+        &new[prefix..new.len() - suffix],
+    )
+}
+
+/// Where `range` ends up once the text in `replaced` is replaced by
+/// `new_len` worth of text: ranges after it move and ranges around it grow
+/// or shrink. Ranges inside it are gone, so they're `None`.
+pub fn shift_range(range: TextRange, replaced: TextRange, new_len: TextSize) -> Option<TextRange> {
+    let new_end = replaced.start() + new_len;
+    if range.end() <= replaced.start() {
+        Some(range)
+    } else if range.start() >= replaced.end() {
+        Some(TextRange::at(
+            range.start() - replaced.end() + new_end,
+            range.len(),
+        ))
+    } else if range.start() <= replaced.start() && range.end() >= replaced.end() {
+        Some(TextRange::new(
+            range.start(),
+            range.end() - replaced.end() + new_end,
+        ))
+    } else {
+        None
+    }
+}
+
 pub fn is_sql_file(path: &Path) -> bool {
     path.extension() == Some(OsStr::new("sql"))
 }
 
-pub enum TraverseOrder {
-    Forward,
-    Backward,
+pub fn is_markdown_file(path: &Path) -> bool {
+    path.extension() == Some(OsStr::new("md"))
+}
+
+pub fn is_csv_file(path: &Path) -> bool {
+    path.extension() == Some(OsStr::new("csv"))
 }
 
-pub fn get_child_of_kind(
-    node: &SyntaxNode,
-    kind: SyntaxKind,
-    order: TraverseOrder,
-) -> Option<SyntaxNode> {
-    let check_kind = |child: SyntaxNode| {
-        if child.kind() == kind {
-            Some(child)
-        } else {
-            None
+pub fn is_yaml_file(path: &Path) -> bool {
+    path.extension() == Some(OsStr::new("yml")) || path.extension() == Some(OsStr::new("yaml"))
+}
+
+/// The number of characters to insert, delete or replace to turn `a` into
+/// `b`, for suggesting what a misspelled name was meant to be
+pub fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+    for (i, a_char) in a.chars().enumerate() {
+        let mut current = vec![i + 1];
+        for (j, b_char) in b.iter().enumerate() {
+            let replace = previous[j] + usize::from(a_char != *b_char);
+            current.push(replace.min(previous[j + 1] + 1).min(current[j] + 1));
         }
-    };
-    match order {
-        TraverseOrder::Forward => node.children().filter_map(check_kind).next(),
-        TraverseOrder::Backward => node.children().filter_map(check_kind).last(),
+        previous = current;
     }
+    previous[b.len()]
 }
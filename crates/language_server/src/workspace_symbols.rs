@@ -0,0 +1,279 @@
+use dbt_jinja_parser::ast::{AstNode, StmtDocs, StmtSnapshot};
+use dbt_jinja_parser::lexer::tokenize;
+use dbt_jinja_parser::parser::{parse, SyntaxNode};
+use std::collections::HashSet;
+use std::path::Path;
+use tower_lsp::lsp_types::{Location, Range, SymbolInformation, SymbolKind, Url};
+
+use crate::entity::{DbtProject, IndexedSymbol};
+use crate::line_index::LineIndex;
+
+/// how many matches a workspace symbol search returns, since editors only
+/// show the first few and a short query matches most of the project
+const MAX_WORKSPACE_SYMBOLS: usize = 128;
+
+impl DbtProject {
+    /// Updates the symbol index with what's in the file now, or drops the
+    /// file from it if it's gone
+    pub(crate) fn index_file(&self, path: &Path) {
+        if self.is_file_seed(path) {
+            if !self.seed_files.contains(path) {
+                self.symbols.remove_file(path);
+            }
+            return self.index_seeds();
+        }
+        let symbols = if let Some(model) = self.models.get(path) {
+            let mut symbols = vec![IndexedSymbol::new(
+                model.name.clone(),
+                SymbolKind::MODULE,
+                Range::default(),
+            )];
+            symbols.extend(self.get_block_symbols(&model.parsed_repr.syntax(), &model.line_index));
+            symbols
+        } else if let Some(macro_file) = self.macros.get(path) {
+            let mut symbols: Vec<IndexedSymbol> = macro_file
+                .macros
+                .iter()
+                .filter_map(|mac| {
+                    let name = mac.name.as_ref()?;
+                    // generic tests are used without their prefix
+                    let (name, kind) = match name.strip_prefix("test_") {
+                        Some(test_name) => (test_name, SymbolKind::METHOD),
+                        None => (name.as_str(), SymbolKind::FUNCTION),
+                    };
+                    Some(IndexedSymbol::new(
+                        format!("{}.{}", self.spec.name, name),
+                        kind,
+                        macro_file
+                            .line_index
+                            .range(mac.declaration_selection, self.position_encoding),
+                    ))
+                })
+                .collect();
+            symbols.extend(
+                self.get_block_symbols(&macro_file.parsed_repr.syntax(), &macro_file.line_index),
+            );
+            symbols
+        } else if let Some(property_file) = self.properties.get(path) {
+            let range = |range| {
+                property_file
+                    .line_index
+                    .range(range, self.position_encoding)
+            };
+            let seeds = property_file.seeds.iter().map(|seed| {
+                IndexedSymbol::new(seed.name.clone(), SymbolKind::FILE, range(seed.name_range))
+            });
+            let snapshots = property_file.snapshots.iter().map(|snapshot| {
+                IndexedSymbol::new(
+                    snapshot.name.clone(),
+                    SymbolKind::OBJECT,
+                    range(snapshot.name_range),
+                )
+            });
+            let tables = property_file.sources.iter().flat_map(|source| {
+                source.tables.iter().map(|table| {
+                    IndexedSymbol::new(
+                        format!("{}.{}", source.name, table.name),
+                        SymbolKind::STRUCT,
+                        range(table.name_range),
+                    )
+                })
+            });
+            seeds.chain(snapshots).chain(tables).collect()
+        } else if self.is_file_docs(path) || self.is_file_snapshot(path) {
+            // indexed from their text by `index_text_file`
+            return;
+        } else {
+            Vec::new()
+        };
+        self.symbols.set_file(path, symbols);
+        if self.is_file_properties(path) {
+            // the seeds it declares are indexed here rather than at their
+            // `.csv` file
+            self.index_seeds();
+        }
+    }
+
+    /// Indexes the blocks in a `.md` docs file or a snapshot's `.sql` file
+    /// from its text, or drops the file from the index if it's gone. The
+    /// project doesn't keep these parsed, so while one is being edited its
+    /// symbols are the ones it had when it was opened.
+    pub(crate) fn index_text_file(&self, path: &Path, contents: Option<&str>) {
+        if !self.is_file_docs(path) && !self.is_file_snapshot(path) {
+            return;
+        }
+        let symbols = match contents {
+            None => Vec::new(),
+            Some(contents) => self.get_block_symbols(
+                &parse(tokenize(contents)).syntax(),
+                &LineIndex::new(contents),
+            ),
+        };
+        self.symbols.set_file(path, symbols);
+    }
+
+    /// Indexes each seed that no property file declares at its `.csv` file,
+    /// since it's a seed all the same
+    pub(crate) fn index_seeds(&self) {
+        let declared: HashSet<String> = self
+            .properties
+            .iter()
+            .flat_map(|property_file| {
+                property_file
+                    .seeds
+                    .iter()
+                    .map(|seed| seed.name.clone())
+                    .collect::<Vec<_>>()
+            })
+            .collect();
+        for seed_file in self.seed_files.iter() {
+            let symbols = match seed_file.file_stem() {
+                Some(name) if !declared.contains(name.to_string_lossy().as_ref()) => {
+                    vec![IndexedSymbol::new(
+                        name.to_string_lossy().to_string(),
+                        SymbolKind::FILE,
+                        Range::default(),
+                    )]
+                }
+                _ => Vec::new(),
+            };
+            self.symbols.set_file(&seed_file, symbols);
+        }
+    }
+
+    /// the docs and snapshot blocks in a file
+    fn get_block_symbols(
+        &self,
+        syntax_tree: &SyntaxNode,
+        line_index: &LineIndex,
+    ) -> Vec<IndexedSymbol> {
+        syntax_tree
+            .descendants()
+            .filter_map(|node| {
+                let (name, kind) = if let Some(docs) = StmtDocs::cast(node.clone()) {
+                    (docs.name()?, SymbolKind::STRING)
+                } else if let Some(snapshot) = StmtSnapshot::cast(node) {
+                    (snapshot.name()?, SymbolKind::OBJECT)
+                } else {
+                    return None;
+                };
+                Some(IndexedSymbol::new(
+                    name.name()?,
+                    kind,
+                    line_index.range(name.syntax().text_range(), self.position_encoding),
+                ))
+            })
+            .collect()
+    }
+
+    /// The models, macros, sources and so on in this project and its
+    /// installed packages whose names fuzzily match `query`, best matches
+    /// first
+    pub fn get_workspace_symbols(&self, query: &str) -> Vec<SymbolInformation> {
+        let mut results: Vec<(i64, SymbolInformation)> = self.with_projects(|projects| {
+            projects
+                .iter()
+                .flat_map(|project| project.search_symbols(query))
+                .collect()
+        });
+        results.sort_by(|(a_score, a), (b_score, b)| {
+            b_score
+                .cmp(a_score)
+                .then_with(|| a.name.len().cmp(&b.name.len()))
+                .then_with(|| a.name.cmp(&b.name))
+        });
+        results
+            .into_iter()
+            .take(MAX_WORKSPACE_SYMBOLS)
+            .map(|(_, symbol)| symbol)
+            .collect()
+    }
+
+    /// the symbols in this project's own index matching `query`, with how
+    /// well they match
+    #[allow(deprecated)] // `SymbolInformation::deprecated` has to be set
+    fn search_symbols(&self, query: &str) -> Vec<(i64, SymbolInformation)> {
+        self.symbols
+            .search(query)
+            .into_iter()
+            .filter_map(|(path, symbol, score)| {
+                let uri = Url::from_file_path(&path).ok()?;
+                let information = SymbolInformation {
+                    name: symbol.name,
+                    kind: symbol.kind,
+                    tags: None,
+                    deprecated: None,
+                    location: Location {
+                        uri,
+                        range: symbol.range,
+                    },
+                    container_name: Some(self.spec.name.clone()),
+                };
+                Some((score, information))
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn symbol_names(project: &DbtProject, query: &str) -> Vec<String> {
+        let mut names: Vec<String> = project
+            .get_workspace_symbols(query)
+            .into_iter()
+            .map(|symbol| symbol.name)
+            .collect();
+        names.sort();
+        names
+    }
+
+    #[test]
+    fn test_workspace_symbols_outside_models_and_macros() {
+        let root_path = Path::new("/project");
+        let project = DbtProject::from_files(
+            "root",
+            root_path,
+            &[
+                (
+                    "models/docs.md",
+                    "{% docs orders_docs %}Orders{% enddocs %}",
+                ),
+                (
+                    "snapshots/orders.sql",
+                    "{% snapshot orders_snapshot %}select 1{% endsnapshot %}",
+                ),
+                (
+                    "models/seeds.yml",
+                    "version: 2\nseeds:\n  - name: declared_orders\n",
+                ),
+                ("seeds/declared_orders.csv", "id\n1\n"),
+                ("seeds/raw_orders.csv", "id\n1\n"),
+            ],
+        );
+        assert_eq!(
+            symbol_names(&project, "orders"),
+            vec![
+                "declared_orders",
+                "orders_docs",
+                "orders_snapshot",
+                "raw_orders"
+            ]
+        );
+
+        // once it's declared, a seed is found at its property file instead
+        let declared = "version: 2\nseeds:\n  - name: declared_orders\n  - name: raw_orders\n";
+        project
+            .on_file_open(&root_path.join("models/seeds.yml"), declared)
+            .unwrap();
+        let symbols = project.get_workspace_symbols("raw_orders");
+        assert_eq!(symbols.len(), 1);
+        assert!(symbols[0].location.uri.path().ends_with("seeds.yml"));
+
+        project
+            .on_file_close(root_path.join("snapshots/orders.sql"), &None)
+            .unwrap();
+        assert!(project.get_workspace_symbols("orders_snapshot").is_empty());
+    }
+}